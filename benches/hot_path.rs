@@ -0,0 +1,81 @@
+//! Benchmarks of the code which runs for every output sample.
+
+use std::{hint::black_box, sync::Arc};
+
+use breakers::{
+    pipeline::{Pipeline, PipelineConfig, RenderLength},
+    postproc::FIRBuilder,
+    sampler::{Boundary, Sample, SamplePlayer},
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tree_sitter::Parser;
+
+const SAMPLE_RATE: u32 = 48000;
+
+fn pipeline(c: &mut Criterion) {
+    let source = include_str!("../testdata/bench_patch.br");
+    let mut parser = Parser::new();
+    parser
+        .set_language(tree_sitter_breaker::language())
+        .unwrap();
+    let tree = parser.parse(source, None).unwrap();
+
+    let config = PipelineConfig {
+        samples_dir: "testdata/samples".to_string(),
+        ..Default::default()
+    };
+    let (mut pipeline, _rx) = Pipeline::from_tree(&tree, source, Some(&config)).unwrap();
+
+    // a tenth of a second per iteration, the time of a few audio callbacks
+    c.bench_function("pipeline next_sample x4800", |b| {
+        b.iter(|| black_box(pipeline.render(RenderLength::Seconds(0.1))))
+    });
+}
+
+fn fir(c: &mut Criterion) {
+    let mut group = c.benchmark_group("FIR process");
+    for length in [16, 128, 1024] {
+        let mut fir = FIRBuilder::new()
+            .max_length(length)
+            .low_pass(10.0, SAMPLE_RATE as f32)
+            .build();
+        group.bench_with_input(BenchmarkId::from_parameter(length), &length, |b, _| {
+            let mut input = 0.0_f32;
+            b.iter(|| {
+                input = (input + 0.01) % 1.0;
+                black_box(fir.process(black_box(input)))
+            })
+        });
+    }
+    group.finish();
+}
+
+fn sample_player(c: &mut Criterion) {
+    let data = (0..SAMPLE_RATE)
+        .map(|i| (i as f32 * 0.05).sin())
+        .collect::<Vec<f32>>();
+    let sample = Arc::new(Sample::new("sine.wav", data, SAMPLE_RATE));
+
+    let mut group = c.benchmark_group("SamplePlayer get_sample");
+    for (name, boundary) in [
+        ("zero", Boundary::Zero),
+        ("clamp", Boundary::Clamp),
+        ("wrap", Boundary::Wrap),
+    ] {
+        // a fractional speed, so every sample is interpolated
+        let mut player = SamplePlayer::new(sample.clone())
+            .with_boundary(boundary)
+            .scale_speed(0.8);
+        group.bench_function(name, |b| {
+            let mut time = 0;
+            b.iter(|| {
+                time = (time + 1) % SAMPLE_RATE as u128;
+                black_box(player.get_sample(black_box(time), SAMPLE_RATE))
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, pipeline, fir, sample_player);
+criterion_main!(benches);
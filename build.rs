@@ -0,0 +1,58 @@
+/*!
+* Build script, bakes the reverb impulse response into the binary when the `baked-reverb` feature
+* is enabled.
+*
+* The impulse is read from `data/impulse.wav`, this file is not shipped with the crate, so the
+* feature is off by default.
+*/
+
+use std::{env, fs, path::Path};
+
+const IMPULSE_FILE: &str = "data/impulse.wav";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    if env::var_os("CARGO_FEATURE_BAKED_REVERB").is_none() {
+        return;
+    }
+
+    println!("cargo:rerun-if-changed={IMPULSE_FILE}");
+
+    let mut reader = hound::WavReader::open(IMPULSE_FILE)
+        .unwrap_or_else(|err| panic!("baked-reverb needs {IMPULSE_FILE}: {err}"));
+    let spec = reader.spec();
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().map(|s| s.unwrap()).collect(),
+        hound::SampleFormat::Int => {
+            let max = (1_i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.unwrap() as f32 / max)
+                .collect()
+        }
+    };
+
+    // mix all channels down to a single mask
+    let mask: Vec<f32> = samples
+        .chunks(spec.channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+
+    let values: Vec<String> = mask.iter().map(|s| format!("{s:?}")).collect();
+    let code = format!(
+        "/// Number of taps in the baked reverb impulse response.\n\
+         pub const REVERB_SIZE: usize = {};\n\
+         /// Sample rate the baked impulse response was recorded at.\n\
+         pub const REVERB_SAMPLE_RATE: u32 = {};\n\
+         /// The baked reverb impulse response, mixed down to mono.\n\
+         pub static REVERB_MASK: [f32; REVERB_SIZE] = [{}];\n",
+        mask.len(),
+        spec.sample_rate,
+        values.join(", ")
+    );
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("reverb.rs");
+    fs::write(out, code).unwrap();
+}
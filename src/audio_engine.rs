@@ -8,53 +8,261 @@
 */
 
 use std::{
-    sync::mpsc::Receiver,
+    error::Error,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
 use cpal::{
     traits::{DeviceTrait, HostTrait},
-    Stream, SupportedStreamConfig,
+    BuildStreamError, DefaultStreamConfigError, HostUnavailable, Stream, SupportedStreamConfig,
 };
 
-pub fn start(source: Receiver<f32>) -> (Stream, SupportedStreamConfig) {
+/// The reasons why the audio engine could not be started.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The jack host is not compiled in, or could not be reached.
+    HostUnavailable,
+    /// The host does not have a default output device.
+    NoOutputDevice,
+    /// The output device has no usable default configuration.
+    Config(DefaultStreamConfigError),
+    /// The output stream could not be built.
+    Stream(BuildStreamError),
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::HostUnavailable => write!(
+                f,
+                "jack host unavailable (make sure --features jack is specified)"
+            ),
+            Self::NoOutputDevice => write!(f, "no output device available"),
+            Self::Config(err) => write!(f, "no usable output config: {err}"),
+            Self::Stream(err) => write!(f, "could not build output stream: {err}"),
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+impl From<HostUnavailable> for EngineError {
+    fn from(_: HostUnavailable) -> Self {
+        Self::HostUnavailable
+    }
+}
+
+impl From<DefaultStreamConfigError> for EngineError {
+    fn from(err: DefaultStreamConfigError) -> Self {
+        Self::Config(err)
+    }
+}
+
+impl From<BuildStreamError> for EngineError {
+    fn from(err: BuildStreamError) -> Self {
+        Self::Stream(err)
+    }
+}
+
+/// How much of the previous peak level remains after each callback.
+const PEAK_DECAY: f32 = 0.9;
+
+/// Smoothed peak levels of each output channel, shared between the audio callback and a UI.
+///
+/// The levels are stored as atomics, so reading them never blocks the audio callback.
+#[derive(Debug)]
+pub struct Meters {
+    // the f32 bits of the peak level of each channel
+    peaks: Vec<AtomicU32>,
+}
+
+impl Meters {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            peaks: (0..channels).map(|_| AtomicU32::new(0)).collect(),
+        }
+    }
+
+    /// The peak level of the given channel, zero for channels that don't exist.
+    pub fn peak(&self, channel: usize) -> f32 {
+        self.peaks
+            .get(channel)
+            .map_or(0.0, |peak| f32::from_bits(peak.load(Ordering::Relaxed)))
+    }
+
+    /// The peak levels of all channels.
+    pub fn peaks(&self) -> Vec<f32> {
+        (0..self.peaks.len()).map(|ch| self.peak(ch)).collect()
+    }
+
+    /// Combine the peak of the latest buffer with the decayed previous peak.
+    fn update(&self, channel: usize, buffer_peak: f32) {
+        if let Some(peak) = self.peaks.get(channel) {
+            let previous = f32::from_bits(peak.load(Ordering::Relaxed));
+            let level = buffer_peak.max(previous * PEAK_DECAY);
+            peak.store(level.to_bits(), Ordering::Relaxed);
+        }
+    }
+}
+
+/// The part of a host the engine needs to find its output device.
+trait OutputHost {
+    type Device;
+
+    fn output_device(&self) -> Option<Self::Device>;
+}
+
+impl OutputHost for cpal::Host {
+    type Device = cpal::Device;
+
+    fn output_device(&self) -> Option<Self::Device> {
+        self.default_output_device()
+    }
+}
+
+fn find_output_device<H: OutputHost>(host: &H) -> Result<H::Device, EngineError> {
+    host.output_device().ok_or(EngineError::NoOutputDevice)
+}
+
+/// Write the samples of the source to an interleaved output buffer of `channels` channels, and
+/// meter them.
+///
+/// The source is mono, so each sample is written to every channel of its frame.
+fn fill_buffer(data: &mut [f32], channels: usize, source: &Receiver<f32>, meters: &Meters) {
+    let channels = channels.max(1);
+    for frame in data.chunks_mut(channels) {
+        let Ok(sample) = source.recv() else {
+            println!("Some receiving error at the audio engine side");
+            continue;
+        };
+        log::trace!(
+            "audio_engine, {}",
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        for ch in frame {
+            *ch = sample;
+        }
+    }
+
+    // meter after writing, so the samples reach the device as fast as before
+    for ch in 0..meters.peaks.len().min(channels) {
+        let peak = data
+            .iter()
+            .skip(ch)
+            .step_by(channels)
+            .fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        meters.update(ch, peak);
+    }
+}
+
+/// Start playing the samples of the source on the default output device.
+///
+/// Returns the stream (which stops playing when dropped), its config and the output meters.
+pub fn start(
+    source: Receiver<f32>,
+) -> Result<(Stream, SupportedStreamConfig, Arc<Meters>), EngineError> {
     let host = cpal::host_from_id(
         cpal::available_hosts()
             .into_iter()
             .find(|id| *id == cpal::HostId::Jack)
-            .expect("make sure --features jack is specified"),
-    )
-    .expect("jack host unavailable");
+            .ok_or(EngineError::HostUnavailable)?,
+    )?;
 
-    let device = host.default_output_device().unwrap();
+    let device = find_output_device(&host)?;
 
-    let config = device.default_output_config().unwrap();
+    let config = device.default_output_config()?;
 
     let err_fn = |err| eprintln!("an error occurred on input stream: {err}");
 
+    let channels = config.channels() as usize;
+    let meters = Arc::new(Meters::new(channels));
+    let callback_meters = meters.clone();
+
     let out_stream = device.build_output_stream(
         &config.config(),
         move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-            for (_, frame) in data.chunks_mut(2).enumerate() {
-                let Ok(sample) = source.recv() else {
-                    println!("Some receiving error at the audio engine side");
-                    continue;
-                };
-                log::trace!(
-                    "audio_engine, {}",
-                    SystemTime::now()
-                        .duration_since(UNIX_EPOCH)
-                        .unwrap()
-                        .as_nanos()
-                );
-                for ch in frame {
-                    *ch = sample;
-                }
-            }
+            fill_buffer(data, channels, &source, &callback_meters)
         },
         err_fn,
         None,
-    );
+    )?;
+
+    Ok((out_stream, config, meters))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A host without any output devices, like a headless CI machine.
+    struct HeadlessHost;
+
+    impl OutputHost for HeadlessHost {
+        type Device = ();
+
+        fn output_device(&self) -> Option<Self::Device> {
+            None
+        }
+    }
+
+    #[test]
+    fn meters_follow_written_samples() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        for sample in [0.5, -0.8, 0.25] {
+            tx.send(sample).unwrap();
+        }
+        let meters = Meters::new(2);
+        let mut data = [0.0; 6];
+
+        fill_buffer(&mut data, 2, &rx, &meters);
+
+        assert_eq!(data, [0.5, 0.5, -0.8, -0.8, 0.25, 0.25]);
+        assert_eq!(meters.peaks(), vec![0.8, 0.8]);
+        assert_eq!(meters.peak(2), 0.0);
+    }
+
+    #[test]
+    fn frames_follow_channel_count() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        for sample in [0.5, -0.8, 0.25, 0.1, 0.2, 0.3] {
+            tx.send(sample).unwrap();
+        }
+
+        // mono: every value is a frame of its own
+        let meters = Meters::new(1);
+        let mut mono = [0.0; 3];
+        fill_buffer(&mut mono, 1, &rx, &meters);
+        assert_eq!(mono, [0.5, -0.8, 0.25]);
+        assert_eq!(meters.peaks(), vec![0.8]);
+
+        // four channels: each sample fills a whole frame
+        let meters = Meters::new(4);
+        let mut quad = [0.0; 12];
+        fill_buffer(&mut quad, 4, &rx, &meters);
+        assert_eq!(
+            quad,
+            [0.1, 0.1, 0.1, 0.1, 0.2, 0.2, 0.2, 0.2, 0.3, 0.3, 0.3, 0.3]
+        );
+        assert_eq!(meters.peaks(), vec![0.3; 4]);
+    }
+
+    #[test]
+    fn no_output_device() {
+        let res = find_output_device(&HeadlessHost);
 
-    (out_stream.unwrap(), config)
+        assert!(
+            matches!(res, Err(EngineError::NoOutputDevice)),
+            "Expected NoOutputDevice, got {:?}",
+            res
+        );
+    }
 }
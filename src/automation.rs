@@ -0,0 +1,142 @@
+/*!
+* Automation of effect parameters, ramping them between two values over a musical duration.
+*/
+
+/// The shape of a ramp between two values.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum Curve {
+    #[default]
+    Linear,
+    /// Equal ratios in equal times, which sounds even for frequencies and gains.
+    Exponential,
+    /// Starts and ends slowly, without a kink.
+    Smoothstep,
+}
+
+/// A ramp of a parameter from one value to another, which advances one sample at a time.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ramp {
+    from: f32,
+    to: f32,
+    curve: Curve,
+    /// The length of the ramp in samples.
+    length: u128,
+    /// The number of samples the ramp has advanced.
+    elapsed: u128,
+}
+
+/// A ramp of one parameter of an effect, the effect is identified like in the effect chain.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Automation {
+    pub track: String,
+    pub effect: String,
+    pub param: String,
+    pub ramp: Ramp,
+}
+
+impl TryFrom<&str> for Curve {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "linear" | "lin" => Ok(Curve::Linear),
+            "exponential" | "exp" => Ok(Curve::Exponential),
+            "smoothstep" | "smooth" => Ok(Curve::Smoothstep),
+            _ => Err("Unknown ramp curve"),
+        }
+    }
+}
+
+impl Curve {
+    /// The value of this curve from `from` to `to` at `t` in [0, 1].
+    pub fn interpolate(&self, from: f32, to: f32, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => from + (to - from) * t,
+            // an exponential curve can't cross or touch zero, fall back to linear
+            Curve::Exponential if from * to <= 0.0 => Curve::Linear.interpolate(from, to, t),
+            Curve::Exponential => from * (to / from).powf(t),
+            Curve::Smoothstep => Curve::Linear.interpolate(from, to, t * t * (3.0 - 2.0 * t)),
+        }
+    }
+}
+
+impl Ramp {
+    /// A ramp from `from` to `to` over `length` samples.
+    pub fn new(from: f32, to: f32, length: u128, curve: Curve) -> Self {
+        Self {
+            from,
+            to,
+            curve,
+            length,
+            elapsed: 0,
+        }
+    }
+
+    /// The value of the ramp after `elapsed` samples, it stays at its end value afterwards.
+    pub fn value_at(&self, elapsed: u128) -> f32 {
+        if elapsed >= self.length {
+            return self.to;
+        }
+
+        let t = elapsed as f64 / self.length as f64;
+        self.curve.interpolate(self.from, self.to, t as f32)
+    }
+
+    /// The current value, after which the ramp advances by one sample.
+    pub fn next_value(&mut self) -> f32 {
+        let value = self.value_at(self.elapsed);
+        self.elapsed += 1;
+        value
+    }
+
+    /// Stretch the ramp by `ratio`, from the point it has reached.
+    pub fn rescale(&mut self, ratio: f64) {
+        self.length = (self.length as f64 * ratio) as u128;
+        self.elapsed = (self.elapsed as f64 * ratio) as u128;
+    }
+
+    /// Whether the ramp reached its end value.
+    pub fn is_finished(&self) -> bool {
+        self.elapsed > self.length
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_midpoint() {
+        let ramp = Ramp::new(100.0, 1000.0, 48000, Curve::Exponential);
+
+        let halfway = ramp.value_at(24000);
+        let geometric = (100.0_f32 * 1000.0).sqrt();
+
+        assert!(
+            (halfway - geometric).abs() < 0.01,
+            "Exponential ramp is at {} halfway, instead of {}",
+            halfway,
+            geometric
+        );
+        assert_eq!(ramp.value_at(0), 100.0);
+        assert_eq!(ramp.value_at(48000), 1000.0);
+    }
+
+    #[test]
+    fn rescale_keeps_progress() {
+        let mut ramp = Ramp::new(0.0, 1.0, 48000, Curve::Linear);
+        for _ in 0..12000 {
+            ramp.next_value();
+        }
+
+        // twice as long, from a quarter of the way
+        ramp.rescale(2.0);
+        assert_eq!(ramp.next_value(), 0.25);
+        for _ in 0..48000 {
+            ramp.next_value();
+        }
+        assert!((ramp.next_value() - 0.75).abs() < 1e-4);
+        assert!(!ramp.is_finished());
+    }
+}
@@ -11,14 +11,61 @@ use num_derive::FromPrimitive;
 
 use crate::util::FromNode;
 
-/// A chord consists of a root note, a mode, an optional augmentation, and an optional bass note.
+/// The default reference frequency of A4, in Hz.
+pub const A4: f64 = 440.0;
+
+/// A chord consists of a root note, a mode, an optional augmentation, an optional bass note, and
+/// an inversion.
+///
+/// The inversion is the number of lowest chord tones which are lifted by an octave, the bass note
+/// stays below the chord whatever its inversion.
 #[derive(Debug, PartialEq, Clone)]
-// TODO: add inversions support!
-pub struct Chord(Note, Mode, Augs, Option<Note>);
+pub struct Chord(Note, Mode, Augs, Option<Note>, u8);
 
 /// A note consists of a white note, an accidental, and an octave.
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub struct Note(PitchClass, Octave);
+///
+/// The note also remembers whether it was written with a flat, so it prints the same way. Notes
+/// which sound the same are equal, whatever their spelling. The last field detunes the note by a
+/// number of cents, for microtonal material.
+#[derive(Debug, Clone, Copy)]
+pub struct Note(PitchClass, Octave, Spelling, i16);
+
+/// The number of harmonics which are summed for a waveform, unless set otherwise.
+pub const DEFAULT_HARMONICS: usize = 15;
+
+/// The number of odd harmonics a single note sums for its square wave, unless set otherwise.
+pub const NOTE_HARMONICS: usize = 20;
+
+/// The shape of the wave each note of a chord is played with.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum Waveform {
+    /// The odd harmonics of a square wave.
+    #[default]
+    Square,
+    Sine,
+    /// All harmonics of a sawtooth wave.
+    Saw,
+    /// The odd harmonics of a triangle wave.
+    Triangle,
+}
+
+/// How notes and chords are synthesized, from the harmonics of a waveform.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub struct Timbre {
+    pub waveform: Waveform,
+    /// The number of harmonics to sum, without one a note with a square wave sums
+    /// [`NOTE_HARMONICS`] and the other waves sum [`DEFAULT_HARMONICS`]. Either way only the
+    /// harmonics below half the sample rate are summed, so a high count reaches all of them.
+    pub harmonics: Option<usize>,
+}
+
+/// Whether the black keys are printed as sharps or as flats.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum Spelling {
+    #[default]
+    Sharp,
+    Flat,
+}
 
 /// A pitch class is a white note with an accidental.
 #[derive(Debug, FromPrimitive, Clone, Copy, PartialEq)]
@@ -69,21 +116,37 @@ pub enum Mode {
     Aug,
     Sus4,
     Sus2,
+    /// Half-diminished, i.e. a diminished triad with a minor seventh (m7b5)
+    HalfDim,
+    /// Dominant seventh, i.e. a major triad with a minor seventh
+    Dominant,
+    /// Diminished seventh, i.e. a diminished triad with a diminished seventh
+    DimSeven,
 }
 
 /// Possible augmentations for chords (of course, there are a lot more)
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Aug {
+    /// Lowers the fifth of the mode
+    FlatFive,
+    /// Raises the fifth of the mode
+    SharpFive,
     Six,
     MajSix,
     Seven,
     MajSeven,
+    /// The natural extensions, `M9`, `M11` and `M13` are other spellings of them
     Nine,
     MajNine,
     Eleven,
     MajEleven,
     Thirteen,
     MajThirteen,
+    /// The altered tensions of dominant chords
+    FlatNine,
+    SharpNine,
+    SharpEleven,
+    FlatThirteen,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -93,55 +156,87 @@ impl FromNode for Note {
     /// Parse a note from a tree-sitter node, with given source string (which generated the
     /// treesitter node).
     fn from_node(node: &tree_sitter::Node, source: &str) -> Option<Self> {
-        let bass = node.child_by_field_name("bass");
-        let bass = if let Some(bass) = bass {
-            bass
-        } else {
-            node.child_by_field_name("small_bass").unwrap()
-        };
+        let bass = node
+            .child_by_field_name("bass")
+            .or_else(|| node.child_by_field_name("small_bass"))?;
 
-        let bass = bass.utf8_text(source.as_bytes()).unwrap();
+        let bass = bass.utf8_text(source.as_bytes()).ok()?;
         let mut bass: PitchClass = bass.try_into().ok()?;
 
         let acc = node.child_by_field_name("acc");
         let acc = match acc {
             Some(acc) => acc
                 .utf8_text(source.as_bytes())
-                .unwrap()
+                .ok()?
                 .try_into()
                 .unwrap_or_default(),
             None => Acc::default(),
         };
 
         bass += acc;
+        let spelling = match acc {
+            Acc::Flat => Spelling::Flat,
+            _ => Spelling::Sharp,
+        };
 
         let octave = node.child_by_field_name("oct");
         let octave = match octave {
             Some(octave) => octave
                 .utf8_text(source.as_bytes())
-                .unwrap()
+                .ok()?
                 .try_into()
                 .unwrap_or_default(),
             None => Octave::default(),
         };
 
-        Some(Self(bass, octave))
+        // a detune in cents is written as +50 or -30
+        let cents = match node.child_by_field_name("cents") {
+            Some(cents) => cents.utf8_text(source.as_bytes()).ok()?.parse().ok()?,
+            None => 0,
+        };
+
+        Some(Self(bass, octave, spelling, cents))
+    }
+}
+
+impl PartialEq for Note {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0 && self.1 == other.1 && self.3 == other.3
     }
 }
 
 impl Display for Note {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.1 != Octave::default() {
-            write!(f, "[{}]", self.1)?;
+        match self.2 {
+            Spelling::Sharp => {
+                if self.1 != Octave::default() {
+                    write!(f, "[{}]", self.1)?;
+                }
+                write!(f, "{}", self.0)?
+            }
+            Spelling::Flat => write!(f, "{}", self.display_flat())?,
         }
-        write!(f, "{}", self.0)
+        if self.3 != 0 {
+            write!(f, "{:+}", self.3)?;
+        }
+        Ok(())
     }
 }
 
 impl PitchClass {
+    /// The pitch class of a MIDI note number.
+    pub fn from_midi(n: u8) -> Self {
+        num::FromPrimitive::from_u8(n % 12).unwrap()
+    }
+
     pub fn to_freq(&self) -> f64 {
-        let a = 220_f64;
-        let a2 = 440_f64;
+        self.to_freq_with_ref(A4)
+    }
+
+    /// The frequency of this pitch class in the fourth octave, tuned to A4 at `a4` Hz.
+    pub fn to_freq_with_ref(&self, a4: f64) -> f64 {
+        let a = a4 / 2.0;
+        let a2 = a4;
         match self {
             PitchClass::C => a * 2.0f64.powf(3.0 / 12.0),
             PitchClass::Cs => a * 2.0f64.powf(4.0 / 12.0),
@@ -160,8 +255,99 @@ impl PitchClass {
 }
 
 impl Note {
+    pub fn new(pitch: PitchClass, octave: Octave) -> Self {
+        Self(pitch, octave, Spelling::default(), 0)
+    }
+
+    /// This note detuned by `cents` hundredths of a semitone, which can be more than a semitone.
+    pub fn with_cents(mut self, cents: i16) -> Self {
+        self.3 = cents;
+        self
+    }
+
+    /// The note as it is written with a flat, e.g. `Bb` rather than `As`.
+    pub fn display_flat(&self) -> String {
+        let name = match self.0 {
+            PitchClass::Cs => "Db".to_string(),
+            PitchClass::Ds => "Eb".to_string(),
+            PitchClass::Fs => "Gb".to_string(),
+            PitchClass::Gs => "Ab".to_string(),
+            PitchClass::As => "Bb".to_string(),
+            pitch => pitch.to_string(),
+        };
+
+        match self.1 {
+            Octave::Four => name,
+            octave => format!("[{}]{}", octave, name),
+        }
+    }
+
+    /// This note shifted by a number of semitones, which can cross several octaves.
+    ///
+    /// The octave is clamped to one through seven, the pitch class still moves.
+    pub fn transpose(self, semitones: i8) -> Self {
+        // the whole offset at once, so the octave moves as often as the pitch wraps around
+        let from_c1 = (self.1 as i32 - 1) * 12 + self.0 as i32 + semitones as i32;
+        let octave = (from_c1.div_euclid(12) + 1).clamp(1, 7);
+
+        let mut note = self;
+        note.0 = num::FromPrimitive::from_i32(from_c1.rem_euclid(12)).unwrap();
+        note.1 = (octave as i8).try_into().unwrap_or_default();
+        note
+    }
+
+    /// The note `semitones` away, None if it lies outside the supported octaves.
+    pub fn checked_transpose(self, semitones: i8) -> Option<Self> {
+        let from_c1 = (self.1 as i32 - 1) * 12 + self.0 as i32 + semitones as i32;
+        if !(0..7 * 12).contains(&from_c1) {
+            return None;
+        }
+
+        Some(self.transpose(semitones))
+    }
+
+    /// The note of a MIDI note number, where 69 is A4.
+    ///
+    /// Octaves outside of one through seven are clamped to the closest of them.
+    pub fn from_midi(n: u8) -> Self {
+        let octave = ((n / 12) as i8 - 1).clamp(1, 7);
+        Self::new(
+            PitchClass::from_midi(n),
+            octave.try_into().unwrap_or_default(),
+        )
+    }
+
+    /// The MIDI note number of this note, where A4 is 69.
+    pub fn to_midi(&self) -> u8 {
+        (self.1 as u8 + 1) * 12 + self.0 as u8
+    }
+
+    /// The note closest to the given frequency, if it lies within the supported octaves.
+    pub fn from_freq(freq: f64) -> Option<Self> {
+        if freq <= 0.0 {
+            return None;
+        }
+
+        // semitones relative to C0, with A4 at 440 Hz
+        let semitones = (12.0 * (freq / 440.0).log2()).round() as i32 + 57;
+        let pitch = num::FromPrimitive::from_i32(semitones.rem_euclid(12))?;
+
+        // Octave::try_from clamps, so check the range here
+        let octave = semitones.div_euclid(12);
+        if !(1..=7).contains(&octave) {
+            return None;
+        }
+
+        Some(Self::new(pitch, (octave as i8).try_into().ok()?))
+    }
+
     pub fn to_freq(&self) -> f64 {
-        let pitch_freq = self.0.to_freq();
+        self.to_freq_with_ref(A4)
+    }
+
+    /// The frequency of this note, tuned to A4 at `a4` Hz.
+    pub fn to_freq_with_ref(&self, a4: f64) -> f64 {
+        let pitch_freq = self.0.to_freq_with_ref(a4) * 2.0f64.powf(self.3 as f64 / 1200.0);
 
         match self.1 {
             Octave::One => pitch_freq / 8.0,
@@ -175,36 +361,48 @@ impl Note {
     }
 
     pub fn get_sample(&self, time: u128, sample_rate: u32) -> f32 {
-        let freq = self.to_freq();
-
-        let n_overtones = 10;
-        let mut sample: f32 = 0.0;
-        for i in 0..n_overtones {
-            let freq_fact = 1.0 + 2.0 * i as f64;
-            let val = ((time as f64 / (sample_rate as f64))
-                * (freq * freq_fact)
-                * 2.0
-                * std::f64::consts::PI)
-                .sin() as f32;
-            // triangle wave
-            // sample += val / (freq_fact as f32).powi(2);
-            // square wave
-            sample += val / freq_fact as f32;
-        }
-
-        sample /= n_overtones as f32;
+        self.get_sample_with_ref(time, sample_rate, A4)
+    }
 
-        // just for fun, try a perfect square wave
-        sample *= 0.0;
+    /// The sample at `time`, tuned to A4 at `a4` Hz.
+    pub fn get_sample_with_ref(&self, time: u128, sample_rate: u32, a4: f64) -> f32 {
+        self.get_sample_with_timbre(time, sample_rate, a4, Timbre::default())
+    }
 
-        // square wave sample:
-        if time as f64 / sample_rate as f64 * freq % 1.0 > 0.5 {
-            sample = 1.0;
-        } else {
-            sample = 0.0;
+    /// The sample at `time` of this note played with `timbre`, tuned to A4 at `a4` Hz.
+    pub fn get_sample_with_timbre(
+        &self,
+        time: u128,
+        sample_rate: u32,
+        a4: f64,
+        timbre: Timbre,
+    ) -> f32 {
+        let freq = self.to_freq_with_ref(a4);
+        let seconds = time as f64 / sample_rate as f64;
+
+        match timbre {
+            // a square wave from 0 to 1, of its first odd harmonics below half the sample rate
+            Timbre {
+                waveform: Waveform::Square,
+                harmonics: None,
+            } => {
+                let sum: f64 = (0..NOTE_HARMONICS)
+                    .map(|i| 1.0 + 2.0 * i as f64)
+                    .take_while(|k| freq * k < sample_rate as f64 / 2.0)
+                    .map(|k| (seconds * freq * k * 2.0 * std::f64::consts::PI).sin() / k)
+                    .sum();
+                (0.5 - 2.0 / std::f64::consts::PI * sum) as f32
+            }
+            // the harmonics above half the sample rate would alias
+            _ => timbre
+                .harmonics()
+                .iter()
+                .filter(|(k, _)| freq * k < sample_rate as f64 / 2.0)
+                .map(|(k, amp)| {
+                    (seconds * freq * k * 2.0 * std::f64::consts::PI).sin() as f32 * amp
+                })
+                .sum(),
         }
-
-        sample
     }
 }
 
@@ -212,15 +410,7 @@ impl Add<u8> for Note {
     type Output = Self;
 
     fn add(self, rhs: u8) -> Self {
-        let mut note = self;
-        let notenum = note.0 as u8;
-        if notenum + rhs > 11 {
-            note.1 = note.1 + 1;
-        }
-        for _ in 0..rhs {
-            note.0 += Acc::Sharp;
-        }
-        note
+        self.transpose(rhs.min(i8::MAX as u8) as i8)
     }
 }
 
@@ -231,33 +421,40 @@ impl FromNode for Chord {
         let root = Note::from_node(&root, source)?;
 
         let mode = node.child_by_field_name("mode");
-        let mode = match mode {
-            Some(mode) => mode
-                .utf8_text(source.as_bytes())
-                .unwrap()
-                .try_into()
-                .unwrap_or_default(),
-            None => Mode::default(),
+        let (mode, implied) = match mode {
+            Some(mode) => Mode::parse_quality(mode.utf8_text(source.as_bytes()).ok()?),
+            None => (Mode::default(), None),
         };
 
         // there could be multiple augmentations, if there are none, this will be an empty vector
         let mut walk = node.walk();
         let augm_nodes = node.children_by_field_name("augm", &mut walk);
-        let aug_vec: Vec<Aug> = augm_nodes
-            .filter_map(|aug| aug.utf8_text(source.as_bytes()).unwrap().try_into().ok())
+        let aug_vec: Vec<Aug> = implied
+            .into_iter()
+            .chain(
+                augm_nodes.filter_map(|aug| aug.utf8_text(source.as_bytes()).ok()?.try_into().ok()),
+            )
             .collect();
         let augm = Augs(aug_vec);
 
-        let bass = node.child_by_field_name("bass");
-        let bass = match bass {
-            Some(bass) => {
-                // over is a note
-                Note::from_node(&bass, source)
-            }
+        // over is a note, a bass which isn't one makes the whole chord malformed
+        let bass = match node.child_by_field_name("bass") {
+            Some(bass) => Some(Note::from_node(&bass, source)?),
             None => None,
         };
 
-        Some(Self(root, mode, augm, bass))
+        // an inversion is written as ^1, ^2, ...
+        let inversion = match node.child_by_field_name("inversion") {
+            Some(inversion) => inversion
+                .utf8_text(source.as_bytes())
+                .ok()?
+                .trim_start_matches('^')
+                .parse()
+                .ok()?,
+            None => 0,
+        };
+
+        Some(Self(root, mode, augm, bass, inversion))
     }
 }
 
@@ -267,6 +464,9 @@ impl Display for Chord {
         for a in &self.2 .0 {
             write!(f, "{}", a)?;
         }
+        if self.4 > 0 {
+            write!(f, "^{}", self.4)?;
+        }
         if let Some(over) = &self.3 {
             write!(f, "/{}", over)?;
         }
@@ -275,29 +475,71 @@ impl Display for Chord {
 }
 
 impl Chord {
-    pub fn to_notes(&self) -> Vec<Note> {
-        let mut notes = Vec::new();
+    /// This chord shifted by a number of semitones, the bass note moves along.
+    pub fn transpose(&self, semitones: i8) -> Self {
+        let mut chord = self.clone();
+        chord.0 = chord.0.transpose(semitones);
+        chord.3 = chord.3.map(|bass| bass.transpose(semitones));
+        chord
+    }
 
-        let mut relatives = Vec::new();
+    /// The mode and augmentations the chord sounds as, after combining the augmentations which
+    /// change the quality of the mode (e.g. m, 7 and b5 make a half-diminished chord).
+    ///
+    /// A 7 over a diminished chord is a diminished seventh, like in lead sheets, write `ø` or m7b5
+    /// for the minor seventh.
+    pub fn quality(&self) -> (Mode, Augs) {
+        let mut augs = self.2 .0.clone();
+        let mut take = |aug: Aug| match augs.iter().position(|a| *a == aug) {
+            Some(i) => {
+                augs.remove(i);
+                true
+            }
+            None => false,
+        };
 
-        relatives.append(&mut self.1.as_relatives());
-        relatives.append(&mut self.2.as_relatives());
+        let mode = match self.1 {
+            Mode::Minor if take(Aug::FlatFive) => {
+                if take(Aug::Seven) {
+                    Mode::HalfDim
+                } else {
+                    Mode::Dim
+                }
+            }
+            Mode::Dim if take(Aug::Seven) => Mode::DimSeven,
+            Mode::Major if take(Aug::SharpFive) => Mode::Aug,
+            Mode::Major if take(Aug::Seven) => Mode::Dominant,
+            mode => mode,
+        };
+
+        (mode, Augs(augs))
+    }
+
+    pub fn to_notes(&self) -> Vec<Note> {
+        let mut notes = Vec::new();
 
         // root note
         notes.push(self.0);
 
         // mode and augmentation notes
-        for relative in relatives {
+        for relative in self.relatives() {
             let note = self.0 + relative;
             notes.push(note);
         }
 
+        // lift the lowest tones by an octave, inversions past the number of tones wrap around
+        let inversion = self.4 as usize % notes.len();
+        for note in &mut notes[..inversion] {
+            note.1 = note.1 + 1;
+        }
+        notes.rotate_left(inversion);
+
         // bass note
         if let Some(bass) = &self.3 {
             let mut bass = *bass;
 
             // lower the bass to the octave below the root
-            bass.1 = notes[0].1 + -1;
+            bass.1 = self.0 .1 + -1;
 
             notes.push(bass);
         }
@@ -305,41 +547,189 @@ impl Chord {
         notes
     }
 
+    /// The intervals of the tones above the root, in semitones from low to high.
+    fn relatives(&self) -> Vec<u8> {
+        let mut relatives = Vec::new();
+
+        let (mode, augs) = self.quality();
+        relatives.append(&mut mode.as_relatives());
+        // an altered fifth replaces the fifth of the mode
+        if augs.0.iter().any(|aug| aug.degree() == 5) {
+            relatives.retain(|relative| *relative != 7);
+        }
+        relatives.append(&mut augs.as_relatives());
+        // the mode and augmentations may share intervals (e.g. aug and 6)
+        relatives.sort_unstable();
+        relatives.dedup();
+        relatives
+    }
+
+    /// The problems of a chord which can be played anyway, to report where it was written.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .2
+            .conflicts()
+            .into_iter()
+            .map(|(a, b)| format!("Conflicting tensions {} and {} in chord {}", a, b, self))
+            .collect();
+        if !self.fits_octaves() {
+            problems.push(format!(
+                "Chord {} reaches above the highest octave, its top notes are clamped",
+                self
+            ));
+        }
+
+        problems
+    }
+
+    /// Whether all tones of the chord lie within the supported octaves, the ones above them are
+    /// clamped to the highest octave.
+    pub fn fits_octaves(&self) -> bool {
+        let relatives = self.relatives();
+        let inversion = self.4 as usize % (relatives.len() + 1);
+
+        std::iter::once(0)
+            .chain(relatives)
+            .enumerate()
+            .all(|(i, relative)| {
+                // the inverted tones are an octave higher
+                let lift = if i < inversion { 12 } else { 0 };
+                self.0.checked_transpose((relative + lift) as i8).is_some()
+            })
+    }
+
     pub fn as_freqs(&self) -> Vec<f64> {
+        self.as_freqs_with_ref(A4)
+    }
+
+    /// The frequencies of the notes of this chord, tuned to A4 at `a4` Hz.
+    pub fn as_freqs_with_ref(&self, a4: f64) -> Vec<f64> {
         let mut freqs = Vec::new();
 
         for note in self.to_notes() {
-            freqs.push(note.to_freq());
+            freqs.push(note.to_freq_with_ref(a4));
         }
 
         freqs
     }
 
     pub fn get_sample(&self, time: u128, sample_rate: u32) -> f32 {
-        let freqs = self.as_freqs();
-
-        let mut sample: f32 = 0.0;
-
-        // TODO: write functionally with `.fold()`?
-        for freq in &freqs {
-            let n_overtones = 15;
-            for i in 0..n_overtones {
-                let freq_fact = 1.0 + 2.0 * i as f64;
-                let val = ((time as f64 / (sample_rate as f64))
-                    * (freq * freq_fact)
-                    * 2.0
-                    * std::f64::consts::PI)
-                    .sin() as f32;
-                // triangle wave
-                // sample += val / (freq_fact as f32).powi(2);
-                // square wave
-                sample += val / freq_fact as f32;
-            }
+        self.get_sample_with_ref(time, sample_rate, A4)
+    }
+
+    /// The sample at `time`, tuned to A4 at `a4` Hz.
+    pub fn get_sample_with_ref(&self, time: u128, sample_rate: u32, a4: f64) -> f32 {
+        self.get_sample_with_timbre(time, sample_rate, a4, Timbre::default())
+    }
+
+    /// The sample at `time` of this chord played with `timbre`, tuned to A4 at `a4` Hz.
+    pub fn get_sample_with_timbre(
+        &self,
+        time: u128,
+        sample_rate: u32,
+        a4: f64,
+        timbre: Timbre,
+    ) -> f32 {
+        let seconds = time as f64 / sample_rate as f64;
+
+        self.audible_partials(timbre, a4, sample_rate)
+            .iter()
+            .map(|(freq, amp)| (seconds * freq * 2.0 * std::f64::consts::PI).sin() as f32 * amp)
+            .sum()
+    }
+
+    /// The sine partials the chord is made of, as frequency and amplitude pairs. The amplitudes
+    /// add up so that the chord is as loud as one of its notes.
+    pub fn partials(&self, timbre: impl Into<Timbre>) -> Vec<(f64, f32)> {
+        self.partials_with_ref(timbre, A4)
+    }
+
+    /// The partials of the chord which can be played at `sample_rate`, those above half the sample
+    /// rate would alias.
+    pub fn audible_partials(
+        &self,
+        timbre: impl Into<Timbre>,
+        a4: f64,
+        sample_rate: u32,
+    ) -> Vec<(f64, f32)> {
+        let nyquist = sample_rate as f64 / 2.0;
+        self.partials_with_ref(timbre, a4)
+            .into_iter()
+            .filter(|(freq, _)| *freq < nyquist)
+            .collect()
+    }
+
+    /// The partials of the chord, tuned to A4 at `a4` Hz.
+    pub fn partials_with_ref(&self, timbre: impl Into<Timbre>, a4: f64) -> Vec<(f64, f32)> {
+        let freqs = self.as_freqs_with_ref(a4);
+        let notes = freqs.len() as f32;
+        let harmonics = timbre.into().harmonics();
+
+        freqs
+            .iter()
+            .flat_map(|freq| {
+                harmonics
+                    .iter()
+                    .map(move |(k, amp)| (freq * k, amp / notes))
+            })
+            .collect()
+    }
+}
+
+impl Waveform {
+    /// The first harmonics of this waveform, as the multiple of the fundamental frequency and the
+    /// amplitude relative to the fundamental.
+    pub fn harmonics(&self) -> Vec<(f64, f32)> {
+        self.first_harmonics(DEFAULT_HARMONICS)
+    }
+
+    /// The first `count` harmonics of this waveform, a sine only has its fundamental.
+    pub fn first_harmonics(&self, count: usize) -> Vec<(f64, f32)> {
+        match self {
+            Waveform::Square => (0..count)
+                .map(|i| 1.0 + 2.0 * i as f64)
+                .map(|k| (k, 1.0 / k as f32))
+                .collect(),
+            Waveform::Sine => vec![(1.0, 1.0)].into_iter().take(count).collect(),
+            Waveform::Saw => (1..=count).map(|k| (k as f64, 1.0 / k as f32)).collect(),
+            Waveform::Triangle => (0..count)
+                .map(|i| {
+                    let k = 1.0 + 2.0 * i as f64;
+                    let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                    (k, sign / (k * k) as f32)
+                })
+                .collect(),
         }
+    }
+}
 
-        sample /= freqs.len() as f32;
+impl Timbre {
+    /// The harmonics which are summed for this timbre.
+    pub fn harmonics(&self) -> Vec<(f64, f32)> {
+        self.waveform
+            .first_harmonics(self.harmonics.unwrap_or(DEFAULT_HARMONICS))
+    }
+}
+
+impl From<Waveform> for Timbre {
+    fn from(waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            harmonics: None,
+        }
+    }
+}
 
-        sample
+impl TryFrom<&str> for Waveform {
+    type Error = &'static str;
+    fn try_from(s: &str) -> Result<Waveform, &'static str> {
+        match s {
+            "square" => Ok(Self::Square),
+            "sine" => Ok(Self::Sine),
+            "saw" | "sawtooth" => Ok(Self::Saw),
+            "triangle" | "tri" => Ok(Self::Triangle),
+            _ => Err("Invalid waveform"),
+        }
     }
 }
 
@@ -369,16 +759,23 @@ impl TryFrom<&str> for Aug {
 
     fn try_from(s: &str) -> Result<Self, &'static str> {
         match s {
+            "b5" | "-5" => Ok(Self::FlatFive),
+            "#5" | "+5" => Ok(Self::SharpFive),
             "6" => Ok(Self::Six),
             "M6" => Ok(Self::MajSix),
             "7" => Ok(Self::Seven),
-            "M7" => Ok(Self::MajSeven),
+            // the major seventh has many spellings on lead sheets
+            "M7" | "maj7" | "ma7" | "j7" | "Δ" | "Δ7" => Ok(Self::MajSeven),
             "9" => Ok(Self::Nine),
-            "M9" => Ok(Self::MajNine),
+            "M9" | "maj9" | "Δ9" => Ok(Self::MajNine),
             "11" => Ok(Self::Eleven),
-            "M11" => Ok(Self::MajEleven),
+            "M11" | "maj11" | "Δ11" => Ok(Self::MajEleven),
             "13" => Ok(Self::Thirteen),
-            "M13" => Ok(Self::MajThirteen),
+            "M13" | "maj13" | "Δ13" => Ok(Self::MajThirteen),
+            "b9" | "-9" => Ok(Self::FlatNine),
+            "#9" | "+9" => Ok(Self::SharpNine),
+            "#11" | "+11" => Ok(Self::SharpEleven),
+            "b13" | "-13" => Ok(Self::FlatThirteen),
             _ => Err("Invalid aug"),
         }
     }
@@ -387,6 +784,8 @@ impl TryFrom<&str> for Aug {
 impl Display for Aug {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::FlatFive => write!(f, "b5"),
+            Self::SharpFive => write!(f, "#5"),
             Self::Six => write!(f, "6"),
             Self::MajSix => write!(f, "M6"),
             Self::Seven => write!(f, "7"),
@@ -397,6 +796,10 @@ impl Display for Aug {
             Self::MajEleven => write!(f, "M11"),
             Self::Thirteen => write!(f, "13"),
             Self::MajThirteen => write!(f, "M13"),
+            Self::FlatNine => write!(f, "b9"),
+            Self::SharpNine => write!(f, "#9"),
+            Self::SharpEleven => write!(f, "#11"),
+            Self::FlatThirteen => write!(f, "b13"),
         }
     }
 }
@@ -404,21 +807,51 @@ impl Display for Aug {
 impl Aug {
     fn as_relatives(&self) -> Vec<u8> {
         match self {
+            Aug::FlatFive => vec![6],
+            Aug::SharpFive => vec![8],
             Aug::Six => vec![8],
             Aug::MajSix => vec![9],
             Aug::Seven => vec![10],
             Aug::MajSeven => vec![11],
-            Aug::Nine => vec![13],
+            Aug::Nine => vec![14],
             Aug::MajNine => vec![14],
-            Aug::Eleven => vec![16],
+            Aug::Eleven => vec![17],
             Aug::MajEleven => vec![17],
-            Aug::Thirteen => vec![20],
+            Aug::Thirteen => vec![21],
             Aug::MajThirteen => vec![21],
+            Aug::FlatNine => vec![13],
+            Aug::SharpNine => vec![15],
+            Aug::SharpEleven => vec![18],
+            Aug::FlatThirteen => vec![20],
         }
     }
+
+    /// The chord degree this augmentation alters, e.g. both 9 and M9 are ninths.
+    fn degree(&self) -> u8 {
+        match self {
+            Aug::FlatFive | Aug::SharpFive => 5,
+            Aug::Six | Aug::MajSix => 6,
+            Aug::Seven | Aug::MajSeven => 7,
+            Aug::Nine | Aug::MajNine | Aug::FlatNine | Aug::SharpNine => 9,
+            Aug::Eleven | Aug::MajEleven | Aug::SharpEleven => 11,
+            Aug::Thirteen | Aug::MajThirteen | Aug::FlatThirteen => 13,
+        }
+    }
+
+    /// Whether both augmentations can be in one chord, which is only the case for different
+    /// degrees, spellings of the same tone, and the flat and sharp ninth of an altered dominant.
+    fn stacks_with(&self, other: &Aug) -> bool {
+        self.as_relatives() == other.as_relatives()
+            || self.degree() != other.degree()
+            || matches!(
+                (self, other),
+                (Aug::FlatNine, Aug::SharpNine) | (Aug::SharpNine, Aug::FlatNine)
+            )
+    }
 }
 
 impl Augs {
+    /// The intervals of all augmentations, sorted and without duplicates.
     fn as_relatives(&self) -> Vec<u8> {
         let mut relatives = Vec::new();
 
@@ -426,8 +859,25 @@ impl Augs {
             relatives.append(&mut aug.as_relatives());
         }
 
+        relatives.sort_unstable();
+        relatives.dedup();
         relatives
     }
+
+    /// Pairs of augmentations which alter the same degree in a different way (e.g. 9 and M9).
+    pub fn conflicts(&self) -> Vec<(Aug, Aug)> {
+        let mut conflicts = Vec::new();
+
+        for (i, a) in self.0.iter().enumerate() {
+            for b in &self.0[i + 1..] {
+                if !a.stacks_with(b) && !conflicts.contains(&(*a, *b)) {
+                    conflicts.push((*a, *b));
+                }
+            }
+        }
+
+        conflicts
+    }
 }
 
 impl TryFrom<&str> for PitchClass {
@@ -471,11 +921,14 @@ impl TryFrom<&str> for Mode {
 
     fn try_from(s: &str) -> Result<Self, &'static str> {
         match s {
-            "m" => Ok(Self::Minor),
-            "dim" => Ok(Self::Dim),
-            "aug" => Ok(Self::Aug),
-            "sus4" => Ok(Self::Sus4),
+            "m" | "-" | "min" | "mi" => Ok(Self::Minor),
+            "dim" | "°" | "o" => Ok(Self::Dim),
+            "aug" | "+" => Ok(Self::Aug),
+            "sus4" | "sus" => Ok(Self::Sus4),
             "sus2" => Ok(Self::Sus2),
+            "ø" | "ø7" | "m7b5" => Ok(Self::HalfDim),
+            "dom" | "dom7" => Ok(Self::Dominant),
+            "dim7" | "°7" | "o7" => Ok(Self::DimSeven),
             _ => Err("Unrecognized mode"),
         }
     }
@@ -490,6 +943,24 @@ impl Mode {
             Self::Aug => vec![4, 8],
             Self::Sus4 => vec![5, 7],
             Self::Sus2 => vec![2, 7],
+            Self::HalfDim => vec![3, 6, 10],
+            Self::Dominant => vec![4, 7, 10],
+            Self::DimSeven => vec![3, 6, 9],
+        }
+    }
+
+    /// Parse the quality of a chord symbol, some symbols (like `Δ` for a major seventh chord)
+    /// are a major chord with a tension.
+    ///
+    /// Unrecognized qualities are major chords.
+    fn parse_quality(s: &str) -> (Mode, Option<Aug>) {
+        if let Ok(mode) = Mode::try_from(s) {
+            return (mode, None);
+        }
+
+        match Aug::try_from(s) {
+            Ok(aug) => (Mode::Major, Some(aug)),
+            Err(_) => (Mode::default(), None),
         }
     }
 }
@@ -522,6 +993,9 @@ impl Display for Mode {
             Self::Aug => write!(f, "aug"),
             Self::Sus4 => write!(f, "sus4"),
             Self::Sus2 => write!(f, "sus2"),
+            Self::HalfDim => write!(f, "ø"),
+            Self::DimSeven => write!(f, "dim7"),
+            Self::Dominant => write!(f, "dom"),
         }
     }
 }
@@ -586,3 +1060,501 @@ impl Add<i8> for Octave {
         octave_num.try_into().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn midi_round_trip() {
+        for (n, note) in [
+            (24, Note::new(PitchClass::C, Octave::One)),
+            (45, Note::new(PitchClass::A, Octave::Two)),
+            (60, Note::new(PitchClass::C, Octave::Four)),
+            (69, Note::new(PitchClass::A, Octave::Four)),
+            (83, Note::new(PitchClass::B, Octave::Five)),
+            (102, Note::new(PitchClass::Fs, Octave::Seven)),
+        ] {
+            assert_eq!(Note::from_midi(n), note);
+            assert_eq!(note.to_midi(), n);
+        }
+
+        // accidentals land on the pitch class they spell
+        let mut d_flat = PitchClass::D;
+        d_flat += Acc::Flat;
+        let mut a_sharp = PitchClass::A;
+        a_sharp += Acc::Sharp;
+        assert_eq!(PitchClass::from_midi(61), d_flat);
+        assert_eq!(PitchClass::from_midi(70), a_sharp);
+        assert_eq!(Note::new(d_flat, Octave::Four).to_midi(), 61);
+
+        // outside of the supported octaves, the octave is clamped to one or seven
+        assert_eq!(
+            Note::from_midi(127),
+            Note::new(PitchClass::G, Octave::Seven)
+        );
+        assert_eq!(Note::from_midi(0), Note::new(PitchClass::C, Octave::One));
+        assert_eq!(Note::from_midi(23), Note::new(PitchClass::B, Octave::One));
+    }
+
+    #[test]
+    fn tuning_reference() {
+        let c4 = Note::new(PitchClass::C, Octave::Four);
+        let a4 = Note::new(PitchClass::A, Octave::Four);
+
+        assert!((c4.to_freq_with_ref(432.0) - 256.87).abs() < 0.01);
+        assert_eq!(a4.to_freq_with_ref(415.0), 415.0);
+        // the default stays at 440
+        assert_eq!(a4.to_freq(), 440.0);
+        assert_eq!(c4.to_freq(), c4.to_freq_with_ref(A4));
+    }
+
+    #[test]
+    fn combined_augs_deduplicate() {
+        let augs = Augs(vec![Aug::Seven, Aug::Nine, Aug::Seven]);
+
+        assert_eq!(augs.as_relatives(), vec![10, 14]);
+        assert!(augs.conflicts().is_empty());
+
+        // 9 and M9 are the same tone, the natural and the flat ninth aren't
+        assert!(Augs(vec![Aug::Nine, Aug::MajNine]).conflicts().is_empty());
+        let augs = Augs(vec![Aug::Nine, Aug::FlatNine]);
+        assert_eq!(augs.conflicts(), vec![(Aug::Nine, Aug::FlatNine)]);
+
+        let c = Note::new(PitchClass::C, Octave::Four);
+        assert_eq!(
+            Chord(c, Mode::Major, augs, None, 0).problems(),
+            vec!["Conflicting tensions 9 and b9 in chord C9b9"]
+        );
+    }
+
+    #[test]
+    fn inversions_lift_lowest_tones() {
+        let c = Note::new(PitchClass::C, Octave::Four);
+        let e = Note::new(PitchClass::E, Octave::Four);
+        let g = Note::new(PitchClass::G, Octave::Four);
+        let up = |note: Note| Note::new(note.0, note.1 + 1);
+
+        let inverted = |inversion| Chord(c, Mode::Major, Augs(vec![]), None, inversion).to_notes();
+
+        assert_eq!(inverted(0), vec![c, e, g]);
+        assert_eq!(inverted(1), vec![e, g, up(c)]);
+        assert_eq!(inverted(2), vec![g, up(c), up(e)]);
+        // a triad has three inversions, the fourth wraps back to root position
+        assert_eq!(inverted(3), inverted(0));
+        assert_eq!(inverted(4), inverted(1));
+
+        // the slash bass stays below the inverted chord
+        let over_g = Chord(c, Mode::Major, Augs(vec![]), Some(g), 1);
+        assert_eq!(
+            over_g.to_notes(),
+            vec![e, g, up(c), Note::new(PitchClass::G, Octave::Three)]
+        );
+        assert_eq!(over_g.to_string(), "C^1/G");
+    }
+
+    #[test]
+    fn chord_symbol_aliases() {
+        let c = Note::new(PitchClass::C, Octave::Four);
+        let pitches = |quality: &str| {
+            let (mode, implied) = Mode::parse_quality(quality);
+            Chord(c, mode, Augs(implied.into_iter().collect()), None, 0)
+                .to_notes()
+                .iter()
+                .map(|note| note.0)
+                .collect::<Vec<PitchClass>>()
+        };
+
+        assert_eq!(pitches("-"), pitches("m"));
+        assert_eq!(pitches("-"), [PitchClass::C, PitchClass::Ds, PitchClass::G]);
+        assert_eq!(
+            pitches("Δ"),
+            [PitchClass::C, PitchClass::E, PitchClass::G, PitchClass::B]
+        );
+        assert_eq!(pitches("Δ"), pitches("maj7"));
+        assert_eq!(
+            pitches("ø"),
+            [
+                PitchClass::C,
+                PitchClass::Ds,
+                PitchClass::Fs,
+                PitchClass::As
+            ]
+        );
+        assert_eq!(pitches("+"), pitches("aug"));
+    }
+
+    #[test]
+    fn half_diminished_and_dominant() {
+        let c = Note::new(PitchClass::C, Octave::Four);
+        let intervals = |chord: Chord| -> Vec<u8> {
+            chord.to_notes().iter().map(|note| note.0 as u8).collect()
+        };
+
+        let m7b5 = Chord(
+            c,
+            Mode::Minor,
+            Augs(vec![Aug::Seven, Aug::FlatFive]),
+            None,
+            0,
+        );
+        assert_eq!(m7b5.quality().0, Mode::HalfDim);
+        assert_eq!(intervals(m7b5), vec![0, 3, 6, 10]);
+        assert_eq!(Mode::try_from("m7b5"), Ok(Mode::HalfDim));
+
+        let dominant = Chord(c, Mode::Major, Augs(vec![Aug::Seven]), None, 0);
+        assert_eq!(dominant.quality().0, Mode::Dominant);
+        assert_eq!(intervals(dominant), vec![0, 4, 7, 10]);
+        assert_eq!(
+            intervals(Chord(c, Mode::Dominant, Augs(vec![]), None, 0)),
+            vec![0, 4, 7, 10]
+        );
+
+        // an altered fifth of a major chord replaces its fifth
+        let flat_five = Chord(c, Mode::Major, Augs(vec![Aug::FlatFive]), None, 0);
+        assert_eq!(intervals(flat_five), vec![0, 4, 6]);
+
+        // a seventh spells as minor over a minor chord, and as diminished over a diminished one
+        let m7 = Chord(c, Mode::Minor, Augs(vec![Aug::Seven]), None, 0);
+        assert_eq!(intervals(m7), vec![0, 3, 7, 10]);
+        let dim7 = Chord(c, Mode::Dim, Augs(vec![Aug::Seven]), None, 0);
+        assert_eq!(dim7.quality().0, Mode::DimSeven);
+        assert_eq!(intervals(dim7), vec![0, 3, 6, 9]);
+    }
+
+    #[test]
+    fn sine_partials_are_the_notes() {
+        let c_minor = Chord(
+            Note::new(PitchClass::C, Octave::Four),
+            Mode::Minor,
+            Augs(vec![]),
+            None,
+            0,
+        );
+
+        let partials = c_minor.partials(Waveform::Sine);
+        let freqs: Vec<f64> = partials.iter().map(|(freq, _)| *freq).collect();
+        assert_eq!(freqs, c_minor.as_freqs());
+        assert!(partials
+            .iter()
+            .all(|(_, amp)| (amp - 1.0 / 3.0).abs() < 1e-6));
+
+        // the square wave adds odd harmonics on top of every note
+        assert_eq!(c_minor.partials(Waveform::Square).len(), 3 * 15);
+    }
+
+    #[test]
+    fn waveform_harmonics() {
+        let first = |waveform: &str| -> Vec<(f64, f32)> {
+            let waveform: Waveform = waveform.try_into().unwrap();
+            waveform.harmonics().into_iter().take(3).collect()
+        };
+
+        assert_eq!(first("sine"), vec![(1.0, 1.0)]);
+        assert_eq!(
+            first("square"),
+            vec![(1.0, 1.0), (3.0, 1.0 / 3.0), (5.0, 1.0 / 5.0)]
+        );
+        assert_eq!(
+            first("saw"),
+            vec![(1.0, 1.0), (2.0, 1.0 / 2.0), (3.0, 1.0 / 3.0)]
+        );
+        assert_eq!(
+            first("triangle"),
+            vec![(1.0, 1.0), (3.0, -1.0 / 9.0), (5.0, 1.0 / 25.0)]
+        );
+        assert!(Waveform::try_from("noise").is_err());
+    }
+
+    #[test]
+    fn one_harmonic_is_a_sine() {
+        let a = Note::new(PitchClass::A, Octave::Four);
+        let timbre = Timbre {
+            waveform: Waveform::Square,
+            harmonics: Some(1),
+        };
+
+        for time in 0..480 {
+            let sine = (time as f64 / 48000.0 * 440.0 * 2.0 * std::f64::consts::PI).sin() as f32;
+            assert!((a.get_sample_with_timbre(time, 48000, A4, timbre) - sine).abs() < 1e-6);
+        }
+
+        let chord = Chord(a, Mode::Major, Augs(vec![]), None, 0);
+        let freqs: Vec<f64> = chord
+            .partials(timbre)
+            .iter()
+            .map(|(freq, _)| *freq)
+            .collect();
+        assert_eq!(freqs, chord.as_freqs());
+        assert_eq!(Waveform::Saw.first_harmonics(4).len(), 4);
+    }
+
+    #[test]
+    fn no_partials_above_nyquist() {
+        // at almost 4 kHz, the square wave reaches up to 60 kHz
+        let high = Chord(
+            Note::new(PitchClass::B, Octave::Seven),
+            Mode::Major,
+            Augs(vec![]),
+            None,
+            0,
+        );
+        assert!(high.as_freqs()[0] > 3900.0);
+
+        let partials = high.partials(Waveform::Square);
+        let audible = high.audible_partials(Waveform::Square, A4, 48000);
+        assert!(audible.len() < partials.len());
+        assert!(audible.iter().all(|(freq, _)| *freq < 24000.0));
+    }
+
+    #[test]
+    fn default_square_is_band_limited() {
+        // tuned up to about 5 kHz, only the fundamental and the third harmonic are below 24 kHz
+        let high = Note::new(PitchClass::B, Octave::Seven);
+        let a4 = 560.0;
+        let freq = high.to_freq_with_ref(a4);
+        assert!(freq > 5000.0 && freq * 5.0 > 24000.0);
+
+        for time in 0..480 {
+            let phase = time as f64 / 48000.0 * freq * 2.0 * std::f64::consts::PI;
+            let expected =
+                0.5 - 2.0 / std::f64::consts::PI * (phase.sin() + (3.0 * phase).sin() / 3.0);
+            let sample = high.get_sample_with_ref(time, 48000, a4);
+            assert!(
+                (sample - expected as f32).abs() < 1e-5,
+                "{} at {}",
+                sample,
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn default_square_sums_note_harmonics() {
+        // far below the sample rate, a low note still only sums its first harmonics
+        let low = Note::new(PitchClass::A, Octave::One);
+        let freq = low.to_freq();
+
+        for time in 0..480 {
+            let phase = time as f64 / 48000.0 * freq * 2.0 * std::f64::consts::PI;
+            let sum: f64 = (0..NOTE_HARMONICS)
+                .map(|i| 1.0 + 2.0 * i as f64)
+                .map(|k| (k * phase).sin() / k)
+                .sum();
+            let expected = 0.5 - 2.0 / std::f64::consts::PI * sum;
+            let sample = low.get_sample(time, 48000);
+            assert!(
+                (sample - expected as f32).abs() < 1e-5,
+                "{} at {}",
+                sample,
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn altered_tensions_stack() {
+        let c = Note::new(PitchClass::C, Octave::Four);
+        let augs: Vec<Aug> = ["7", "b9", "#9", "#11", "b13"]
+            .into_iter()
+            .map(|aug| aug.try_into().unwrap())
+            .collect();
+        assert_eq!(
+            augs.iter().map(Aug::to_string).collect::<Vec<_>>(),
+            vec!["7", "b9", "#9", "#11", "b13"]
+        );
+
+        let altered = Chord(c, Mode::Major, Augs(augs), None, 0);
+        assert!(altered.2.conflicts().is_empty());
+        let intervals: Vec<i32> = altered
+            .to_notes()
+            .iter()
+            .map(|note| note.to_midi() as i32 - c.to_midi() as i32)
+            .collect();
+        assert_eq!(intervals, vec![0, 4, 7, 10, 13, 15, 18, 20]);
+    }
+
+    #[test]
+    fn flat_ninth_differs_from_ninth() {
+        let c = Note::new(PitchClass::C, Octave::Four);
+        let chord = |augs: &[&str]| {
+            let augs = augs.iter().map(|aug| (*aug).try_into().unwrap()).collect();
+            Chord(c, Mode::Major, Augs(augs), None, 0).to_notes()
+        };
+
+        assert_ne!(chord(&["7", "9"]), chord(&["7", "b9"]));
+        assert_ne!(chord(&["7", "9", "13"]), chord(&["7", "b9", "b13"]));
+        assert_eq!(
+            chord(&["7", "9"]).last().unwrap().to_midi(),
+            c.to_midi() + 14
+        );
+    }
+
+    #[test]
+    fn adding_semitones_crosses_octaves() {
+        let c4 = Note::new(PitchClass::C, Octave::Four);
+
+        for (semitones, expected, freq) in [
+            (12, Note::new(PitchClass::C, Octave::Five), 523.25),
+            (13, Note::new(PitchClass::Cs, Octave::Five), 554.37),
+            (21, Note::new(PitchClass::A, Octave::Five), 880.0),
+        ] {
+            let note = c4 + semitones;
+            assert_eq!(note, expected, "C4 + {} semitones", semitones);
+            assert!(
+                (note.to_freq() - freq).abs() < 0.01,
+                "C4 + {} semitones is at {} Hz, instead of {} Hz",
+                semitones,
+                note.to_freq(),
+                freq
+            );
+        }
+
+        // a thirteenth above A4 passes two Cs, so it is two octaves up
+        assert_eq!(
+            Note::new(PitchClass::A, Octave::Four) + 21,
+            Note::new(PitchClass::Fs, Octave::Six)
+        );
+    }
+
+    #[test]
+    fn transpose_crosses_octaves() {
+        let c_major = Chord(
+            Note::new(PitchClass::C, Octave::Four),
+            Mode::Major,
+            Augs(vec![]),
+            None,
+            0,
+        );
+        let g_major = c_major.transpose(7);
+        assert_eq!(
+            g_major.to_notes(),
+            vec![
+                Note::new(PitchClass::G, Octave::Four),
+                Note::new(PitchClass::B, Octave::Four),
+                Note::new(PitchClass::D, Octave::Five),
+            ]
+        );
+        assert_eq!(g_major.transpose(-7), c_major);
+
+        let a = Note::new(PitchClass::A, Octave::Four);
+        assert_eq!(a.transpose(3), Note::new(PitchClass::C, Octave::Five));
+        assert_eq!(a.transpose(-10), Note::new(PitchClass::B, Octave::Three));
+        assert_eq!(a.transpose(27), Note::new(PitchClass::C, Octave::Seven));
+        // past the supported octaves, the octave is clamped
+        assert_eq!(a.transpose(48), Note::new(PitchClass::A, Octave::Seven));
+        assert_eq!(a.transpose(-48), Note::new(PitchClass::A, Octave::One));
+    }
+
+    #[test]
+    fn cents_detune_notes() {
+        let a = Note::new(PitchClass::A, Octave::Four);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        let next = Note::new(PitchClass::As, Octave::Four);
+        assert!(close(a.with_cents(100).to_freq(), next.to_freq()));
+        assert!(close(
+            a.with_cents(-100).to_freq(),
+            Note::new(PitchClass::Gs, Octave::Four).to_freq()
+        ));
+        // more than a semitone works the same
+        assert!(close(a.with_cents(1200).to_freq(), 880.0));
+        assert!(close(
+            a.with_cents(50).to_freq(),
+            440.0 * 2.0f64.powf(1.0 / 24.0)
+        ));
+
+        assert_ne!(a.with_cents(50), a);
+        assert_eq!(a.with_cents(-30).to_string(), "A-30");
+        assert_eq!(
+            Note::new(PitchClass::C, Octave::Three)
+                .with_cents(50)
+                .to_string(),
+            "[3]C+50"
+        );
+    }
+
+    #[test]
+    fn thirteenth_spans_octaves() {
+        let c13 = |octave| {
+            Chord(
+                Note::new(PitchClass::C, octave),
+                Mode::Major,
+                Augs(vec![Aug::Seven, Aug::MajThirteen]),
+                None,
+                0,
+            )
+        };
+
+        // the major thirteenth is an octave and a major sixth up, in the next octave
+        let notes = c13(Octave::Four).to_notes();
+        assert_eq!(notes.last(), Some(&Note::new(PitchClass::A, Octave::Five)));
+        assert_eq!(notes.last().unwrap().to_midi(), 60 + 21);
+        assert!(c13(Octave::Four).fits_octaves());
+
+        // from the seventh octave, the thirteenth would be above the highest octave
+        assert!(c13(Octave::Six).fits_octaves());
+        assert!(!c13(Octave::Seven).fits_octaves());
+        assert!(c13(Octave::Six).problems().is_empty());
+        assert_eq!(c13(Octave::Seven).problems().len(), 1);
+        let c = Note::new(PitchClass::C, Octave::Seven);
+        assert_eq!(c.checked_transpose(21), None);
+        assert_eq!(
+            c.checked_transpose(11),
+            Some(Note::new(PitchClass::B, Octave::Seven))
+        );
+    }
+
+    #[test]
+    fn flats_print_as_flats() {
+        let b_flat = Note(PitchClass::As, Octave::Four, Spelling::Flat, 0);
+        let b_flat_minor = Chord(b_flat, Mode::Minor, Augs(vec![]), None, 0);
+
+        assert_eq!(b_flat_minor.to_string(), "Bbm");
+        assert_eq!(Note::new(PitchClass::As, Octave::Four).to_string(), "As");
+        assert_eq!(
+            Note::new(PitchClass::Gs, Octave::Two).display_flat(),
+            "[2]Ab"
+        );
+        // the spelling doesn't change which note it is
+        assert_eq!(b_flat, Note::new(PitchClass::As, Octave::Four));
+    }
+
+    #[test]
+    fn half_diminished_frequencies() {
+        let c = Note::new(PitchClass::C, Octave::Four);
+        let m7b5 = Chord(
+            c,
+            Mode::Minor,
+            Augs(vec![Aug::Seven, Aug::FlatFive]),
+            None,
+            0,
+        );
+
+        // C4, Eb4, Gb4 and Bb4
+        let expected = [261.63, 311.13, 369.99, 466.16];
+        let freqs = m7b5.as_freqs();
+        assert_eq!(freqs.len(), expected.len());
+        for (freq, expected) in freqs.iter().zip(expected) {
+            assert!(
+                (freq - expected).abs() < 0.01,
+                "Expected {} Hz, got {} Hz",
+                expected,
+                freq
+            );
+        }
+    }
+
+    #[test]
+    fn note_from_freq() {
+        assert_eq!(
+            Note::from_freq(440.0),
+            Some(Note::new(PitchClass::A, Octave::Four))
+        );
+        assert_eq!(
+            Note::from_freq(263.0),
+            Some(Note::new(PitchClass::C, Octave::Four))
+        );
+        assert_eq!(Note::from_freq(10.0), None);
+    }
+}
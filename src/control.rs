@@ -0,0 +1,118 @@
+/*!
+* Live control from the terminal: tapping the tempo, and muting and soloing tracks.
+*
+* Every line typed is a command, an empty line (just pressing Enter) taps the tempo.
+*/
+
+use crate::pipeline::Pipeline;
+
+/// Number of most recent taps the tempo is averaged over.
+const MAX_TAPS: usize = 8;
+/// Longest time between two taps, in seconds, a longer pause starts counting again.
+const MAX_TAP_GAP: f64 = 2.0;
+
+/// Averages the time between taps into a tempo.
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct TapTempo {
+    // times of the most recent taps, in seconds
+    taps: Vec<f64>,
+}
+
+/// A command typed in the terminal.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    /// Tap the tempo, with an empty line or `t`.
+    Tap,
+    /// Mute or unmute a track, with `m <name>`.
+    Mute(String),
+    /// Only hear one track, with `s <name>`, or all of them again with `s`.
+    Solo(Option<String>),
+}
+
+impl TapTempo {
+    /// Tap at `time` seconds, returns the tempo in bpm once there are enough taps.
+    pub fn tap(&mut self, time: f64) -> Option<f32> {
+        if self
+            .taps
+            .last()
+            .is_some_and(|last| time - last > MAX_TAP_GAP || time <= *last)
+        {
+            self.taps.clear();
+        }
+
+        self.taps.push(time);
+        if self.taps.len() > MAX_TAPS {
+            self.taps.remove(0);
+        }
+
+        self.tempo()
+    }
+
+    /// The tempo of the taps so far, in bpm, None before the second tap.
+    pub fn tempo(&self) -> Option<f32> {
+        let (first, last) = (self.taps.first()?, self.taps.last()?);
+        if self.taps.len() < 2 {
+            return None;
+        }
+
+        let interval = (last - first) / (self.taps.len() - 1) as f64;
+        Some((60.0 / interval) as f32)
+    }
+}
+
+impl TryFrom<&str> for Command {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut words = value.split_whitespace();
+        match (words.next(), words.next()) {
+            (None | Some("t"), None) => Ok(Self::Tap),
+            (Some("m"), Some(name)) => Ok(Self::Mute(name.to_string())),
+            (Some("s"), name) => Ok(Self::Solo(name.map(str::to_string))),
+            _ => Err(format!("Unknown command: {}", value.trim())),
+        }
+    }
+}
+
+impl Command {
+    /// Run the command on the pipeline, at `time` seconds (which taps are timed with).
+    pub fn apply(&self, pipeline: &mut Pipeline, tap_tempo: &mut TapTempo, time: f64) {
+        match self {
+            Command::Tap => {
+                if let Some(tempo) = tap_tempo.tap(time) {
+                    log::info!("Tempo: {:.1} bpm", tempo);
+                    pipeline.set_tempo(tempo);
+                }
+            }
+            Command::Mute(name) => match pipeline.toggle_mute(name) {
+                Some(true) => log::info!("Muted {}", name),
+                Some(false) => log::info!("Unmuted {}", name),
+                None => log::warn!("Unknown playable: {}", name),
+            },
+            Command::Solo(name) => pipeline.set_solo(name.as_deref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taps_average_to_tempo() {
+        let mut tap_tempo = TapTempo::default();
+
+        assert_eq!(tap_tempo.tap(10.0), None);
+        // half a second between taps is 120 bpm, the uneven taps are averaged
+        assert!((tap_tempo.tap(10.45).unwrap() - 60.0 / 0.45).abs() < 1e-3);
+        tap_tempo.tap(11.0);
+        let tempo = tap_tempo.tap(11.5).unwrap();
+        assert!((tempo - 120.0).abs() < 1e-3, "Taps averaged to {}", tempo);
+
+        // after a long pause, the taps start over
+        assert_eq!(tap_tempo.tap(20.0), None);
+        assert!((tap_tempo.tap(21.0).unwrap() - 60.0).abs() < 1e-3);
+        assert_eq!(Command::try_from(""), Ok(Command::Tap));
+        assert_eq!(Command::try_from("s"), Ok(Command::Solo(None)));
+    }
+}
@@ -0,0 +1,39 @@
+/*!
+* Problems found while building a pipeline, which don't stop it from playing.
+*/
+
+use std::{fmt, path::PathBuf};
+
+/// A problem in the source, with the position it was found at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The included file the problem is in, None if it is in the input itself.
+    pub file: Option<PathBuf>,
+    /// Line of the problem, starting at 1.
+    pub line: usize,
+    /// Column of the problem, starting at 1.
+    pub column: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Create a diagnostic at the start of `node`.
+    pub fn new(node: &tree_sitter::Node, message: impl Into<String>) -> Self {
+        let position = node.start_position();
+        Self {
+            file: None,
+            line: position.row + 1,
+            column: position.column + 1,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{} ", file.display())?;
+        }
+        write!(f, "line {}:{}: {}", self.line, self.column, self.message)
+    }
+}
@@ -0,0 +1,73 @@
+/*!
+* Attack, decay, sustain and release envelopes, which shape the level of synthesized notes and chords.
+*/
+
+/// An ADSR envelope, the times are in seconds and the sustain is a level in [0, 1].
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Envelope {
+    attack: f32,
+    decay: f32,
+    sustain: f32,
+    release: f32,
+}
+
+impl Envelope {
+    /// Create an envelope, negative times are taken as zero.
+    pub fn new(attack: f32, decay: f32, sustain: f32, release: f32) -> Self {
+        Self {
+            attack: attack.max(0.0),
+            decay: decay.max(0.0),
+            sustain: sustain.clamp(0.0, 1.0),
+            release: release.max(0.0),
+        }
+    }
+
+    /// The level of a held voice, `elapsed` seconds after it was triggered.
+    pub fn level(&self, elapsed: f32) -> f32 {
+        if elapsed < self.attack {
+            return elapsed / self.attack;
+        }
+
+        let elapsed = elapsed - self.attack;
+        if elapsed < self.decay {
+            return 1.0 + (self.sustain - 1.0) * elapsed / self.decay;
+        }
+
+        self.sustain
+    }
+
+    /// The level of a voice which was held for `held` seconds, `elapsed` seconds after it was
+    /// released.
+    pub fn released_level(&self, held: f32, elapsed: f32) -> f32 {
+        if elapsed >= self.release {
+            return 0.0;
+        }
+
+        self.level(held) * (1.0 - elapsed / self.release)
+    }
+
+    /// Whether a voice is silent `elapsed` seconds after it was released.
+    pub fn is_released(&self, elapsed: f32) -> bool {
+        elapsed >= self.release
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attack_ramps_to_full_level() {
+        let envelope = Envelope::new(0.01, 0.1, 0.7, 0.2);
+
+        assert_eq!(envelope.level(0.0), 0.0);
+        assert!((envelope.level(0.005) - 0.5).abs() < 1e-6);
+        assert!((envelope.level(0.01) - 1.0).abs() < 1e-6);
+        let attack: Vec<f32> = (0..=100).map(|i| envelope.level(i as f32 * 1e-4)).collect();
+        assert!(attack.windows(2).all(|w| w[1] >= w[0]));
+
+        assert!((envelope.level(1.0) - 0.7).abs() < 1e-6);
+        assert!((envelope.released_level(1.0, 0.1) - 0.35).abs() < 1e-6);
+        assert_eq!(envelope.released_level(1.0, 0.2), 0.0);
+    }
+}
@@ -2,13 +2,16 @@
 * Grid module implements the grid sequencer grid parsing
 */
 
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::HashMap, fmt::Display, sync::Arc};
 
-use rand::Rng;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 use crate::{
-    chromatic::{Chord, Note},
-    sampler::{SamplePlayer, SampleSet},
+    chromatic::{Chord, Note, Timbre, Waveform, A4},
+    diagnostic::Diagnostic,
+    envelope::Envelope,
+    groove::Groove,
+    sampler::{Boundary, Sample, SampleLength, SamplePlayer, SampleSet, StealPolicy, VoicePool},
     util::FromNode,
 };
 
@@ -20,36 +23,189 @@ pub enum GridToken {
     #[default]
     Pause,
     Prob(f32, SamplePlayer),
+    // play one of the alternatives, each chosen with a probability (in percent), or nothing
+    Choice(Vec<(f32, GridToken)>),
     Chord(Chord),
     Note(Note),
     Repeat,
+    // hold the sounding chord or note, and don't attack the same one again after it
+    Tie,
     Todo(String),
 }
 
+/// What a token which couldn't be parsed plays instead.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum Fallback {
+    #[default]
+    Rest,
+    /// Keep playing the token before it, like a repeat.
+    Sustain,
+}
+
+impl TryFrom<&str> for Fallback {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "rest" | "pause" => Ok(Fallback::Rest),
+            "sustain" | "repeat" => Ok(Fallback::Sustain),
+            _ => Err("Unknown fallback"),
+        }
+    }
+}
+
+/// How often the chance of the Prob tokens is rolled.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum ProbEval {
+    /// Every Prob token is rolled each time it comes around.
+    #[default]
+    Step,
+    /// Every Prob token is rolled once per bar, and plays the same each time it comes around in
+    /// that bar.
+    Bar,
+    /// Every Prob token is rolled once per pass through the grid.
+    Loop,
+}
+
+impl TryFrom<&str> for ProbEval {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "step" => Ok(ProbEval::Step),
+            "bar" => Ok(ProbEval::Bar),
+            "loop" => Ok(ProbEval::Loop),
+            _ => Err("Unknown probability evaluation"),
+        }
+    }
+}
+
+/// The number of sample voices a grid can play at once, unless set otherwise.
+const DEFAULT_POLYPHONY: usize = 16;
+
+/// The most steps a counted rest, like `_4`, can last.
+const MAX_REST_STEPS: usize = 256;
+
 #[derive(Debug)]
 pub struct Grid {
     pub tokens: Vec<GridToken>,
     next_scheduled: usize,
-    now_playing: usize,
+    // the token which is sounding, None if its step was dropped
+    now_playing: Option<usize>,
+    // the alternative which was chosen, if the sounding token is a choice
+    chosen: Option<usize>,
+    // time at which the sounding chord or note was attacked
+    voice_start: u128,
+    // whether the previous step was a tie
+    tied: bool,
+    // gain of the sounding chord or note, from the groove
+    velocity: f32,
+    // timing and velocity offsets of the steps
+    groove: Option<Groove>,
     tempo: f32,
     time_sign: (u32, u32),
     // the note length of one token
     note_length: (u32, u32),
     samples_per_hit: Option<u32>,
+    // the samples that are sounding, which were copied from their token when they were hit
+    voices: VoicePool,
+    // multiplier for the probability of all Prob tokens
+    density: f32,
+    // how loud the Prob tokens play instead of being rolled, from 0.0 to 1.0, None rolls them
+    fill: Option<f32>,
+    // chance that any sounding step is silenced on a pass
+    drop: f32,
+    rng: StdRng,
+    // how often the Prob tokens are rolled
+    prob_eval: ProbEval,
+    // the roll of each Prob token, by index, with the bar or pass it was made in
+    rolls: Vec<Option<(i128, f64)>>,
+    // rate at which the output is sampled and held, for a lo-fi sound
+    hold_rate: Option<u32>,
+    // the held output, with the index of the hold interval it was taken in
+    held: Option<(u128, f32)>,
+    // reference frequency of A4 for the notes and chords, in Hz
+    tuning: f64,
+    // the waveform and harmonics the notes and chords are played with
+    timbre: Timbre,
+    // the envelope of the notes and chords, None plays them at a constant level
+    envelope: Option<Envelope>,
+    // the chord or note which rings out after it was followed, with the time it was attacked, the
+    // time it was released and its velocity
+    releasing: Option<(GridToken, u128, u128, f32)>,
+    // the tokens which couldn't be parsed, by index, with the problem at their position
+    failed: Vec<(usize, Diagnostic)>,
+    // problems of tokens which are played anyway, like chords with conflicting tensions
+    problems: Vec<Diagnostic>,
+    // the velocity of each token, from 0.0 to 1.0, the tokens past its end play at full velocity
+    velocities: Vec<f32>,
+    // time at which the sounding step triggered a sample voice
+    struck: Option<u128>,
+    // time at which the hit that is held by repeats was triggered, its voice stops at the next step
+    sustained: Option<u128>,
+    // how long the samples sound after they are hit
+    sample_length: SampleLength,
+    // what the last frames of the samples are interpolated with, also of those mapped later, None
+    // leaves each sample at its own
+    boundary: Option<Boundary>,
 }
 
 impl GridToken {
-    fn get_sample(&mut self, time: u128, sample_rate: u32) -> f32 {
+    /// The sample of a chord or note, the samples of hits are played by the voices.
+    fn synth_sample(&self, time: u128, sample_rate: u32, a4: f64, timbre: Timbre) -> f32 {
         match self {
-            GridToken::Hit(s) | GridToken::Prob(_, s) => s.get_sample(time, sample_rate),
-            GridToken::Pause => 0.0,
-            GridToken::Chord(c) => c.get_sample(time, sample_rate),
-            GridToken::Note(n) => n.get_sample(time, sample_rate),
-            _ => panic!("This token doesn't have a sample"),
+            GridToken::Chord(c) => c.get_sample_with_timbre(time, sample_rate, a4, timbre),
+            GridToken::Note(n) => n.get_sample_with_timbre(time, sample_rate, a4, timbre),
+            _ => panic!("This token isn't synthesized"),
         }
     }
 }
 
+impl GridToken {
+    /// Parse a raw grid token, which can span multiple steps.
+    ///
+    /// A rest with a count, like `_4`, is a pause which lasts that many steps. A count of zero,
+    /// or of more than [`MAX_REST_STEPS`], can't be parsed.
+    fn from_raw(text: &str) -> Option<Vec<GridToken>> {
+        if let Some(Ok(count)) = text.strip_prefix('_').map(str::parse::<usize>) {
+            return (1..=MAX_REST_STEPS)
+                .contains(&count)
+                .then(|| vec![GridToken::Pause; count]);
+        }
+
+        Some(vec![text.try_into().unwrap_or_default()])
+    }
+
+    /// A choice between alternatives, the ones without a probability share what the others
+    /// leave of 100%.
+    pub fn choice(alternatives: Vec<(Option<f32>, GridToken)>) -> GridToken {
+        let given: f32 = alternatives.iter().filter_map(|(p, _)| *p).sum();
+        let unweighted = alternatives.iter().filter(|(p, _)| p.is_none()).count();
+        let share = (100.0 - given).max(0.0) / unweighted.max(1) as f32;
+
+        GridToken::Choice(
+            alternatives
+                .into_iter()
+                .map(|(p, token)| (p.unwrap_or(share).max(0.0), token))
+                .collect(),
+        )
+    }
+
+    /// The alternative of a choice which `roll` (in [0, 100)) lands on, None for the part of 100%
+    /// that isn't given to any alternative. Probabilities adding up to more than 100% are scaled
+    /// down.
+    fn choose(alternatives: &[(f32, GridToken)], roll: f32) -> Option<usize> {
+        let total: f32 = alternatives.iter().map(|(p, _)| p).sum();
+        let roll = roll * total.max(100.0) / 100.0;
+
+        let mut cumulative = 0.0;
+        alternatives.iter().position(|(p, _)| {
+            cumulative += p;
+            roll < cumulative
+        })
+    }
+}
+
 impl TryFrom<&str> for GridToken {
     type Error = &'static str;
 
@@ -57,6 +213,7 @@ impl TryFrom<&str> for GridToken {
         match value {
             "_" => Ok(Self::Pause),
             "&" => Ok(Self::Repeat),
+            "~" => Ok(Self::Tie),
             x => Ok(Self::Todo(x.to_string())),
         }
     }
@@ -70,12 +227,64 @@ impl Default for Grid {
             note_length: (1, 16),
             time_sign: (4, 4),
             samples_per_hit: None,
-            now_playing: 0,
+            now_playing: Some(0),
+            chosen: None,
+            voice_start: 0,
+            tied: false,
+            velocity: 1.0,
+            groove: None,
             next_scheduled: 0,
+            voices: VoicePool::new(DEFAULT_POLYPHONY, StealPolicy::default()),
+            density: 1.0,
+            fill: None,
+            drop: 0.0,
+            rng: StdRng::from_entropy(),
+            prob_eval: ProbEval::default(),
+            rolls: Vec::new(),
+            hold_rate: None,
+            held: None,
+            tuning: A4,
+            timbre: Timbre::default(),
+            envelope: None,
+            releasing: None,
+            failed: Vec::new(),
+            problems: Vec::new(),
+            velocities: Vec::new(),
+            struck: None,
+            sustained: None,
+            sample_length: SampleLength::default(),
+            boundary: None,
         }
     }
 }
 
+/// Parse a velocity, which is either a MIDI velocity (0 to 127) or a level (0.0 to 1.0).
+fn parse_velocity(text: &str) -> Option<f32> {
+    let text = text.strip_prefix('!').unwrap_or(text);
+    if text.contains('.') {
+        text.parse()
+            .ok()
+            .filter(|level| (0.0..=1.0).contains(level))
+    } else {
+        text.parse::<u8>()
+            .ok()
+            .filter(|velocity| *velocity <= 127)
+            .map(|velocity| velocity as f32 / 127.0)
+    }
+}
+
+/// Parse a playback speed, as a fraction like `3/2` or a number like `0.5`, which is positive.
+pub(crate) fn parse_speed(text: &str) -> Option<f32> {
+    let text = text.trim().trim_start_matches('*').trim();
+    let speed = match text.split_once('/') {
+        Some((numer, denom)) => {
+            numer.trim().parse::<f32>().ok()? / denom.trim().parse::<f32>().ok()?
+        }
+        None => text.parse().ok()?,
+    };
+    Some(speed).filter(|speed: &f32| speed.is_finite() && *speed > 0.0)
+}
+
 impl FromNode for Grid {
     fn from_node(node: &tree_sitter::Node, source: &str) -> Option<Self>
     where
@@ -84,43 +293,96 @@ impl FromNode for Grid {
         let mut walk = node.walk();
         let token_iter = node.children_by_field_name("token", &mut walk);
 
-        let tokens: Vec<GridToken> = token_iter
-            .map(|token| -> Option<GridToken> {
-                let Some(token) = token.child(0) else {
-                    return None;
-                };
-                let kind = token.kind();
+        let mut tokens = Vec::new();
+        let mut velocities = Vec::new();
+        let mut failed = Vec::new();
+        let mut problems = Vec::new();
+        for token_node in token_iter {
+            let token = token_node.child(0).and_then(|token| {
                 let token_text = token.utf8_text(source.as_bytes()).unwrap();
 
-                match kind {
-                    "raw_token" => token_text.try_into().ok(),
+                // a velocity is written after the token, like 1!64 or Cm7!0.5
+                let velocity = match token_node.child_by_field_name("velocity") {
+                    Some(velocity) => parse_velocity(velocity.utf8_text(source.as_bytes()).ok()?)?,
+                    None => 1.0,
+                };
+                let tokens = match token.kind() {
+                    "raw_token" => match token_text.rsplit_once('!') {
+                        Some((text, velocity)) => {
+                            return Some((GridToken::from_raw(text)?, parse_velocity(velocity)?))
+                        }
+                        None => GridToken::from_raw(token_text)?,
+                    },
                     "chord" => {
-                        let res = Chord::from_node(&token, source);
-                        res.map(GridToken::Chord)
+                        let chord = Chord::from_node(&token, source)?;
+                        problems.extend(
+                            chord
+                                .problems()
+                                .into_iter()
+                                .map(|problem| Diagnostic::new(&token, problem)),
+                        );
+                        vec![GridToken::Chord(chord)]
                     }
-                    "single_note" => {
-                        let res = Note::from_node(&token, source);
-                        res.map(GridToken::Note)
-                    }
-                    &_ => None,
+                    "single_note" => vec![GridToken::Note(Note::from_node(&token, source)?)],
+                    &_ => return None,
+                };
+                Some((tokens, velocity))
+            });
+
+            match token {
+                Some((token, velocity)) => {
+                    velocities.extend(std::iter::repeat_n(velocity, token.len()));
+                    tokens.extend(token);
                 }
-            })
-            // replace None with default value (Pause)
-            .map(|token| token.unwrap_or_default())
-            .collect();
+                None => {
+                    // replace unparsable tokens with the default value (Pause), until the
+                    // fallback of the grid is set
+                    let text = token_node.utf8_text(source.as_bytes()).unwrap_or_default();
+                    failed.push((
+                        tokens.len(),
+                        Diagnostic::new(&token_node, format!("Unparsable grid token: {}", text)),
+                    ));
+                    tokens.push(GridToken::default());
+                    velocities.push(1.0);
+                }
+            }
+        }
 
         Some(Self {
             tokens,
+            velocities,
+            failed,
+            problems,
             ..Default::default()
         })
     }
 }
 
+/// Prints the tokens one bar per line, with the beats set apart by wider spaces.
 impl Display for Grid {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let beat = self.tokens_per_beat();
+        let bar = beat * self.time_sign.0.max(1) as usize;
+
         writeln!(f, "{{")?;
-        for i in &self.tokens {
-            writeln!(f, "   {}", i)?;
+        for (b, tokens) in self.tokens.chunks(bar).enumerate() {
+            write!(f, "   ")?;
+            for (i, token) in tokens.iter().enumerate() {
+                match i {
+                    0 => {}
+                    i if i % beat == 0 => write!(f, "   ")?,
+                    _ => write!(f, " ")?,
+                }
+                write!(f, "{}", token)?;
+                // velocities are printed as MIDI velocities
+                match self.velocities.get(b * bar + i) {
+                    Some(velocity) if *velocity < 1.0 => {
+                        write!(f, "!{}", (velocity * 127.0).round())?
+                    }
+                    _ => {}
+                }
+            }
+            writeln!(f)?;
         }
         write!(f, "}}")
     }
@@ -131,9 +393,11 @@ impl Display for GridToken {
         match self {
             GridToken::Hit(_) => write!(f, "x"),
             GridToken::Pause => write!(f, "_"),
-            GridToken::Prob(_, _) => write!(f, "?"),
+            GridToken::Prob(p, _) => write!(f, "x?{}", p),
+            GridToken::Choice(_) => write!(f, "?"),
             GridToken::Chord(c) => write!(f, "{}", c),
             GridToken::Repeat => write!(f, "&"),
+            GridToken::Tie => write!(f, "~"),
             GridToken::Todo(s) => write!(f, "{}", s),
             GridToken::Note(n) => write!(f, "{}", n),
         }
@@ -150,36 +414,154 @@ impl Grid {
             return 0.0;
         }
 
-        let index =
-            ((time / self.samples_per_hit.unwrap() as u128) % (self.tokens.len() as u128)) as usize;
+        let samples_per_hit = self.samples_per_hit.unwrap() as u128;
+        let step = match &self.groove {
+            Some(groove) => groove.step_at(time as f64 / samples_per_hit as f64),
+            None => (time / samples_per_hit) as i128,
+        };
+        let index = step.rem_euclid(self.tokens.len() as i128) as usize;
+        let velocity = self
+            .groove
+            .as_ref()
+            .map_or(1.0, |groove| groove.step(step).velocity)
+            * self.velocities.get(index).copied().unwrap_or(1.0);
 
         // for the first sample of a new grid index, we need to set the now_playing index
         if index == self.next_scheduled {
+            let dropped = match self.tokens[index] {
+                GridToken::Pause | GridToken::Repeat | GridToken::Tie => false,
+                _ => self.drop > 0.0 && self.rng.gen_bool(self.drop.into()),
+            };
+
+            // a chord or note that is tied to itself keeps sounding instead of being attacked
+            let tied = match self.tokens[index] {
+                GridToken::Repeat => self.tied,
+                _ => std::mem::replace(&mut self.tied, self.tokens[index] == GridToken::Tie),
+            };
+            let legato = tied
+                && self
+                    .now_playing
+                    .is_some_and(|i| self.tokens[i] == self.tokens[index]);
+
+            // the sounding chord or note rings out for the release of the envelope
+            let released = match self.tokens[index] {
+                GridToken::Repeat | GridToken::Tie => None,
+                _ if legato || self.envelope.is_none() => None,
+                _ => self
+                    .sounding_synth()
+                    .map(|token| (token.clone(), self.voice_start, time, self.velocity)),
+            };
+            if released.is_some() {
+                self.releasing = released;
+            }
+
+            // a hit which was held by repeats fades out at the next step
+            if self.tokens[index] != GridToken::Repeat {
+                if let Some(start) = self.sustained.take() {
+                    self.voices.release_started_at(start, time);
+                }
+                self.struck = None;
+            }
+
+            let roll = match self.tokens[index] {
+                GridToken::Prob(..) => self.prob_roll(step, index),
+                _ => None,
+            };
+
             // we need to check whether we should play the next token or not
             match &mut self.tokens[index] {
+                // a repeat sustains the sounding token without attacking it again, at the start of
+                // the grid that is the token it wraps around to
+                GridToken::Repeat => {
+                    if self.now_playing.is_some_and(|i| {
+                        matches!(self.tokens[i], GridToken::Repeat | GridToken::Tie)
+                    }) {
+                        self.now_playing = self.sounding_index(index);
+                    }
+                    self.sustained = self.sustained.or(self.struck);
+                }
+                // a tie continues playing the current token
+                GridToken::Tie => {}
+                _ if dropped => {
+                    self.now_playing = None;
+                }
                 GridToken::Prob(p, s) => {
-                    let mut rng = rand::thread_rng();
-                    let should_play = rng.gen_bool((*p / 100.0).into());
-                    if should_play {
-                        self.now_playing = index;
+                    let probability = Self::play_probability(*p, self.density);
+                    // with a fill intensity, the hit always plays and its level follows its chance
+                    let gain = match self.fill {
+                        Some(fill) => Self::fill_gain(probability as f32, fill),
+                        None => {
+                            let should_play = match roll {
+                                Some(roll) => roll < probability,
+                                None => self.rng.gen_bool(probability),
+                            };
+                            if should_play {
+                                1.0
+                            } else {
+                                0.0
+                            }
+                        }
+                    };
+                    if gain > 0.0 {
+                        self.now_playing = Some(index);
                         // hit the new sample
-                        s.hit(time);
+                        self.voices.trigger(s, time, velocity * gain);
+                        self.struck = Some(time);
                     }
                 }
-                // For a repeat, continue playing the current token
-                GridToken::Repeat => {}
                 GridToken::Hit(s) => {
-                    self.now_playing = index;
+                    self.now_playing = Some(index);
                     // hit the new sample
-                    s.hit(time);
+                    self.voices.trigger(s, time, velocity);
+                    self.struck = Some(time);
+                }
+                GridToken::Choice(alternatives) => {
+                    self.chosen = GridToken::choose(alternatives, self.rng.gen_range(0.0..100.0));
+                    match self.chosen.map(|i| &mut alternatives[i].1) {
+                        Some(GridToken::Hit(s)) => {
+                            self.voices.trigger(s, time, velocity);
+                            self.struck = Some(time);
+                        }
+                        Some(GridToken::Chord(_) | GridToken::Note(_)) => {
+                            self.voice_start = time;
+                            self.velocity = velocity;
+                        }
+                        _ => {}
+                    }
+                    self.now_playing = self.chosen.map(|_| index);
+                }
+                GridToken::Chord(_) | GridToken::Note(_) => {
+                    if !legato {
+                        self.voice_start = time;
+                        self.velocity = velocity;
+                    }
+                    self.now_playing = Some(index);
                 }
                 _ => {
-                    self.now_playing = index;
+                    self.now_playing = Some(index);
                 }
             }
 
             self.next_scheduled = (index + 1) % self.tokens.len();
 
+            // a gated or looping sample is released at the end of its step, or of the repeats
+            // after it
+            let looping = self
+                .voices
+                .players_started_at(time)
+                .any(SamplePlayer::loops);
+            if (self.sample_length == SampleLength::Step || looping) && self.struck == Some(time) {
+                let len = self.tokens.len();
+                let steps = 1
+                    + (1..len)
+                        .take_while(|offset| {
+                            self.tokens[(index + offset) % len] == GridToken::Repeat
+                        })
+                        .count();
+                self.voices
+                    .release_started_at(time, time + steps as u128 * samples_per_hit);
+            }
+
             // if let GridToken::Hit(s) | GridToken::Prob(_, s) = &self.tokens[index] {
             //     println!("{}", s.sample.name);
             // } else if let GridToken::Chord(c) = &self.tokens[index] {
@@ -187,22 +569,279 @@ impl Grid {
             // }
         }
 
-        self.tokens[self.now_playing].get_sample(time, sample_rate)
+        // samples ring out in their own voices, notes and chords play while they are scheduled
+        let synth = match self.sounding_synth() {
+            Some(token) => {
+                let elapsed = time - self.voice_start;
+                let level = self.envelope.map_or(1.0, |envelope| {
+                    envelope.level(elapsed as f32 / sample_rate as f32)
+                });
+                token.synth_sample(elapsed, sample_rate, self.tuning, self.timbre)
+                    * self.velocity
+                    * level
+            }
+            None => 0.0,
+        };
+
+        let sample = synth + self.release_sample(time, sample_rate);
+        let sample = sample + self.voices.get_sample(time, sample_rate);
+
+        self.hold(sample, time, sample_rate)
+    }
+
+    /// The chord or note which is sounding, samples are played by the voices instead.
+    fn sounding_synth(&self) -> Option<&GridToken> {
+        let sounding = match self.now_playing.map(|i| &self.tokens[i]) {
+            Some(GridToken::Choice(alternatives)) => self
+                .chosen
+                .and_then(|c| alternatives.get(c))
+                .map(|(_, token)| token),
+            token => token,
+        };
+        sounding.filter(|token| matches!(token, GridToken::Chord(_) | GridToken::Note(_)))
+    }
+
+    /// The sample of the chord or note which is ringing out, it is dropped once it is silent.
+    fn release_sample(&mut self, time: u128, sample_rate: u32) -> f32 {
+        let (Some(envelope), Some((token, start, released, velocity))) =
+            (self.envelope, &self.releasing)
+        else {
+            return 0.0;
+        };
+
+        let seconds = |samples: u128| samples as f32 / sample_rate as f32;
+        let since = seconds(time - released);
+        if envelope.is_released(since) {
+            self.releasing = None;
+            return 0.0;
+        }
+
+        token.synth_sample(time - start, sample_rate, self.tuning, self.timbre)
+            * velocity
+            * envelope.released_level(seconds(released - start), since)
+    }
+
+    /// Sample and hold the output at the hold rate, if it is below the output sample rate.
+    fn hold(&mut self, sample: f32, time: u128, sample_rate: u32) -> f32 {
+        let Some(rate) = self.hold_rate.filter(|rate| *rate < sample_rate) else {
+            return sample;
+        };
+
+        let interval = time * rate as u128 / sample_rate as u128;
+        match self.held {
+            Some((held_interval, held)) if held_interval == interval => held,
+            _ => {
+                self.held = Some((interval, sample));
+                sample
+            }
+        }
+    }
+
+    /// Let all tokens which play a sample with the same name use the given sample instead.
+    ///
+    /// A voice which is currently sounding finishes with the sample it was started with.
+    pub fn swap_sample(&mut self, sample: &Arc<Sample>) {
+        self.players_mut().for_each(|s| {
+            if s.sample.name == sample.name {
+                s.sample = sample.clone();
+            }
+        });
+    }
+
+    /// Shift all notes and chords of this grid by a number of semitones.
+    pub fn transpose(&mut self, semitones: i8) {
+        self.tokens_mut().for_each(|token| match token {
+            GridToken::Chord(c) => *c = c.transpose(semitones),
+            GridToken::Note(n) => *n = n.transpose(semitones),
+            _ => {}
+        });
+    }
+
+    /// Choose what the last frames of all samples of this grid are interpolated with.
+    pub fn set_boundary(&mut self, boundary: Boundary) {
+        self.boundary = Some(boundary);
+        self.players_mut()
+            .for_each(|s| *s = s.clone().with_boundary(boundary));
+    }
+
+    /// Play the samples of this grid until their end, or only for the step they are hit on.
+    pub fn set_sample_length(&mut self, sample_length: SampleLength) {
+        self.sample_length = sample_length;
+    }
+
+    /// Play all samples of this grid backwards.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.players_mut()
+            .for_each(|s| *s = s.clone().with_reverse(reverse));
+    }
+
+    /// Loop all samples of this grid from the given frame, None plays them once.
+    pub fn set_loop(&mut self, start: Option<usize>) {
+        self.players_mut()
+            .for_each(|s| *s = s.clone().with_loop(start));
+    }
+
+    /// All tokens, with choices replaced by their alternatives.
+    fn tokens_mut(&mut self) -> impl Iterator<Item = &mut GridToken> {
+        self.tokens.iter_mut().flat_map(|token| match token {
+            GridToken::Choice(alternatives) => alternatives.iter_mut().map(|(_, t)| t).collect(),
+            token => vec![token],
+        })
+    }
+
+    /// The sample players of all tokens, including the alternatives of choices.
+    fn players_mut(&mut self) -> impl Iterator<Item = &mut SamplePlayer> {
+        self.tokens_mut().filter_map(|token| match token {
+            GridToken::Hit(s) | GridToken::Prob(_, s) => Some(s),
+            _ => None,
+        })
+    }
+
+    /// The roll which decides the Prob token at `index` in the bar or pass `step` is in, None if
+    /// it is rolled each time it comes around.
+    fn prob_roll(&mut self, step: i128, index: usize) -> Option<f64> {
+        let period = match self.prob_eval {
+            ProbEval::Step => return None,
+            ProbEval::Bar => step.div_euclid(self.tokens_per_bar() as i128),
+            ProbEval::Loop => step.div_euclid(self.tokens.len() as i128),
+        };
+
+        // the tokens can change when the grid is reloaded
+        self.rolls.resize(self.tokens.len(), None);
+        match self.rolls[index] {
+            Some((rolled, roll)) if rolled == period => Some(roll),
+            _ => {
+                let roll = self.rng.gen();
+                self.rolls[index] = Some((period, roll));
+                Some(roll)
+            }
+        }
+    }
+
+    /// Chance that a Prob token with probability `p` (in percent) plays, scaled by the density.
+    fn play_probability(p: f32, density: f32) -> f64 {
+        (p / 100.0 * density).clamp(0.0, 1.0).into()
+    }
+
+    /// Level of a Prob token that plays with `probability` at a fill intensity of `fill`.
+    ///
+    /// At half intensity it plays at its chance, which it averages to when rolled, an intensity
+    /// of 0.0 silences it and 1.0 plays it at full level.
+    fn fill_gain(probability: f32, fill: f32) -> f32 {
+        let fill = fill.clamp(0.0, 1.0);
+        if fill < 0.5 {
+            probability * 2.0 * fill
+        } else {
+            probability + (1.0 - probability) * (2.0 * fill - 1.0)
+        }
     }
 
     fn calc_samples_per_token(&mut self, sample_rate: u32) {
+        // NOTE: this is the only place where samples_per_hit is set!
+        self.samples_per_hit = Some(self.samples_per_token(sample_rate));
+    }
+
+    /// The number of tokens in one beat of the time signature, 1 if a token doesn't fit a beat a
+    /// whole number of times.
+    fn tokens_per_beat(&self) -> usize {
+        let per_beat = self.note_length.1 / (self.time_sign.1 * self.note_length.0).max(1);
+        (per_beat as usize).max(1)
+    }
+
+    /// The number of tokens in one bar of the time signature, at least 1.
+    fn tokens_per_bar(&self) -> usize {
+        let per_bar =
+            self.time_sign.0 * self.note_length.1 / (self.time_sign.1 * self.note_length.0).max(1);
+        (per_bar as usize).max(1)
+    }
+
+    /// The length of one token in samples.
+    fn samples_per_token(&self, sample_rate: u32) -> u32 {
         let note_len = self.note_length.0 as f32 / self.note_length.1 as f32;
         let beat = self.time_sign.1 as f32;
         let val = note_len * beat * 60.0 * (sample_rate as f32) / self.tempo;
-        // NOTE: this is the only place where samples_per_hit is set!
-        self.samples_per_hit = Some(val as u32);
+        val as u32
     }
 
-    pub fn map_from_node(&mut self, node: &tree_sitter::Node, source: &str, sampleset: &SampleSet) {
+    /// Jump to the given time, the step at that time is played from its start on the next sample.
+    pub fn seek(&mut self, time: u128, sample_rate: u32) {
+        self.voices.clear();
+        self.releasing = None;
+        self.struck = None;
+        self.sustained = None;
+        self.tied = false;
+        self.held = None;
+        self.voice_start = time;
+
+        if self.tokens.is_empty() {
+            return;
+        }
+
+        let len = self.tokens.len();
+        let index = ((time / self.samples_per_token(sample_rate) as u128) % len as u128) as usize;
+
+        self.now_playing = self.sounding_index(index);
+        self.next_scheduled = index;
+    }
+
+    /// Continue at the time `to` from where the grid was at `from`, without stopping what sounds,
+    /// e.g. when the tempo changes.
+    pub fn shift(&mut self, from: u128, to: u128) {
+        let shift = |time: u128| (time + to).saturating_sub(from);
+        self.voices.shift(from, to);
+        self.voice_start = shift(self.voice_start);
+        self.struck = self.struck.map(shift);
+        self.sustained = self.sustained.map(shift);
+        if let Some((_, attack, release, _)) = &mut self.releasing {
+            *attack = shift(*attack);
+            *release = shift(*release);
+        }
+    }
+
+    /// The index of the token which sounds at the given index, a repeat or tie keeps playing the
+    /// token before it, wrapping around to the last tokens.
+    fn sounding_index(&self, index: usize) -> Option<usize> {
+        let len = self.tokens.len();
+        (0..len)
+            .map(|offset| (index + len - offset) % len)
+            .find(|i| !matches!(self.tokens[*i], GridToken::Repeat | GridToken::Tie))
+    }
+
+    /// The length of one pass through all tokens, in samples.
+    pub fn loop_duration(&self, sample_rate: u32) -> u128 {
+        self.tokens.len() as u128 * self.samples_per_token(sample_rate) as u128
+    }
+
+    /// Whether the step at the given time holds a hit, i.e. anything but a pause.
+    ///
+    /// Repeats and ties extend the preceding token, so they are open if that token is.
+    pub fn gate_open(&self, time: u128, sample_rate: u32) -> bool {
+        if self.tokens.is_empty() {
+            return false;
+        }
+
+        let len = self.tokens.len();
+        let index = ((time / self.samples_per_token(sample_rate) as u128) % len as u128) as usize;
+
+        // walk back over the repeats and ties to find the token that is sounding
+        (0..len)
+            .map(|offset| &self.tokens[(index + len - offset) % len])
+            .find(|token| !matches!(token, GridToken::Repeat | GridToken::Tie))
+            .is_some_and(|token| *token != GridToken::Pause)
+    }
+
+    /// Replace the mapped tokens of this grid, returning the entries which couldn't be mapped.
+    pub fn map_from_node(
+        &mut self,
+        node: &tree_sitter::Node,
+        source: &str,
+        sampleset: &SampleSet,
+    ) -> Vec<Diagnostic> {
         let mut walk = node.walk();
         let map_entry_iter = node.children_by_field_name("pair", &mut walk);
 
         let mut map = HashMap::new();
+        let mut diagnostics = Vec::new();
 
         map_entry_iter.for_each(|entry| {
             // entry is a pair of key and value
@@ -218,49 +857,157 @@ impl Grid {
 
             let key_text = key.utf8_text(source.as_bytes()).unwrap();
 
-            // value is either a sample, or a chord
-            match value.kind() {
-                "sample" => {
-                    // get the sample name
-                    let name = value.child_by_field_name("name").unwrap();
-                    let value_text = name.utf8_text(source.as_bytes()).unwrap();
-                    let sample = sampleset.samples.get(value_text).unwrap();
-                    let sampleplayer = SamplePlayer::new(sample.clone());
-
-                    if let Some(p) = value.child_by_field_name("probability") {
-                        let Some(p) = p.child(0) else {
-                            return;
-                        };
-                        let p_text = p.utf8_text(source.as_bytes()).unwrap();
-                        let p: f32 = p_text.parse().unwrap();
-                        map.insert(key_text.to_string(), GridToken::Prob(p, sampleplayer));
-                    } else {
-                        map.insert(key_text.to_string(), GridToken::Hit(sampleplayer));
-                    }
+            // value is a sample, a chord, or a choice between them
+            let token = match value.kind() {
+                "choice" => {
+                    let mut alt_walk = value.walk();
+                    let alternatives: Vec<_> = value
+                        .children_by_field_name("alternative", &mut alt_walk)
+                        .filter_map(|alternative| alternative.child(0))
+                        .filter_map(|alternative| {
+                            Self::value_from_node(&alternative, source, sampleset, &mut diagnostics)
+                        })
+                        .collect();
+                    GridToken::choice(alternatives)
                 }
-                "chord" => {
-                    let res = Chord::from_node(&value, source);
-                    if let Some(chord) = res {
-                        map.insert(key_text.to_string(), GridToken::Chord(chord));
+                _ => match Self::value_from_node(&value, source, sampleset, &mut diagnostics) {
+                    Some((Some(p), GridToken::Hit(sampleplayer))) => {
+                        GridToken::Prob(p, sampleplayer)
                     }
-                }
-                &_ => {}
-            }
+                    Some((_, token)) => token,
+                    None => return,
+                },
+            };
+            map.insert(key_text.to_string(), token);
         });
 
-        // set the todos to the mapped values
+        self.resolve_keys(&map);
+
+        diagnostics
+    }
+
+    /// Replace the keys in the grid with the tokens they are mapped to.
+    ///
+    /// A key can be followed by a probability in percent, like `x?50`, which makes a mapped sample
+    /// play with that probability instead of the one in the map.
+    fn resolve_keys(&mut self, map: &HashMap<String, GridToken>) {
         self.tokens.iter_mut().for_each(|token| {
-            if let GridToken::Todo(key) = token {
-                let Some(value) = map.get(key) else {
-                    return;
+            let GridToken::Todo(key) = token else {
+                return;
+            };
+            let (key, p) = match key.split_once('?') {
+                Some((key, p)) if !key.is_empty() => (key, p.parse::<f32>().ok()),
+                _ => (key.as_str(), None),
+            };
+            let Some(value) = map.get(key) else {
+                return;
+            };
+            *token = match (value, p) {
+                (GridToken::Hit(s) | GridToken::Prob(_, s), Some(p)) => {
+                    GridToken::Prob(p, s.clone())
+                }
+                (value, _) => value.clone(),
+            };
+        });
+
+        // the samples which were just mapped get the boundary which was set before
+        if let Some(boundary) = self.boundary {
+            self.set_boundary(boundary);
+        }
+    }
+
+    /// The token a map value stands for, with its probability if it has one.
+    fn value_from_node(
+        value: &tree_sitter::Node,
+        source: &str,
+        sampleset: &SampleSet,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<(Option<f32>, GridToken)> {
+        match value.kind() {
+            "sample" => {
+                // get the sample name
+                let name = value.child_by_field_name("name").unwrap();
+                let value_text = name.utf8_text(source.as_bytes()).unwrap();
+                let Some(mut sampleplayer) = sampleset.player(value_text) else {
+                    diagnostics.push(Diagnostic::new(
+                        &name,
+                        format!("Unknown sample: {}", value_text),
+                    ));
+                    return None;
+                };
+
+                // the key can play the sample at another pitch
+                if let Some(pitch) = value.child_by_field_name("pitch") {
+                    match Note::from_node(&pitch, source) {
+                        Some(note) if sampleplayer.sample.root().is_some() => {
+                            sampleplayer = sampleplayer.with_pitch(note);
+                        }
+                        Some(_) => diagnostics.push(Diagnostic::new(
+                            &pitch,
+                            format!("Sample {} has no pitch to map from", value_text),
+                        )),
+                        None => diagnostics.push(Diagnostic::new(&pitch, "Malformed pitch")),
+                    }
+                }
+
+                // a stereo sample can play a single channel, like `kick ch 1` for the right one
+                if let Some(channel) = value.child_by_field_name("channel") {
+                    let channel_text = channel.utf8_text(source.as_bytes()).unwrap();
+                    match channel_text.parse::<usize>() {
+                        Ok(c) if c < sampleplayer.sample.channel_count() => {
+                            sampleplayer = sampleplayer.with_channel(c);
+                        }
+                        _ => diagnostics.push(Diagnostic::new(
+                            &channel,
+                            format!("Sample {} has no channel {}", value_text, channel_text),
+                        )),
+                    }
+                }
+
+                // and at another speed, like `kick * 1/2`
+                if let Some(speed) = value.child_by_field_name("speed") {
+                    let speed_text = speed.utf8_text(source.as_bytes()).unwrap();
+                    match parse_speed(speed_text) {
+                        Some(speed) => sampleplayer = sampleplayer.scale_speed(speed),
+                        None => diagnostics.push(Diagnostic::new(
+                            &speed,
+                            format!("Invalid speed: {}", speed_text),
+                        )),
+                    }
+                }
+
+                let p = match value.child_by_field_name("probability") {
+                    Some(p) => {
+                        let p_text = p.child(0)?.utf8_text(source.as_bytes()).unwrap();
+                        Some(p_text.parse().unwrap())
+                    }
+                    None => None,
                 };
-                *token = value.clone();
+                Some((p, GridToken::Hit(sampleplayer)))
             }
-        });
+            "chord" => match Chord::from_node(value, source) {
+                Some(chord) => {
+                    diagnostics.extend(
+                        chord
+                            .problems()
+                            .into_iter()
+                            .map(|problem| Diagnostic::new(value, problem)),
+                    );
+                    Some((None, GridToken::Chord(chord)))
+                }
+                None => {
+                    diagnostics.push(Diagnostic::new(value, "Malformed chord"));
+                    None
+                }
+            },
+            _ => None,
+        }
     }
 
     pub fn set_tempo(&mut self, tempo: f32) {
         self.tempo = tempo;
+        // the token length is recomputed on the next sample
+        self.samples_per_hit = None;
     }
 
     pub fn set_note_length(&mut self, note_length: (u32, u32)) {
@@ -271,6 +1018,99 @@ impl Grid {
         self.time_sign = time_sign;
     }
 
+    /// Limit the number of sample voices which can sound at the same time.
+    pub fn set_polyphony(&mut self, max_voices: usize) {
+        self.voices.set_max_voices(max_voices);
+    }
+
+    /// Choose which voice is stopped when the polyphony limit is reached.
+    pub fn set_steal_policy(&mut self, policy: StealPolicy) {
+        self.voices.set_policy(policy);
+    }
+
+    /// Scale the probability of all Prob tokens, thinning out (< 1.0) or filling in (> 1.0) the
+    /// pattern.
+    pub fn set_density(&mut self, density: f32) {
+        self.density = density.max(0.0);
+    }
+
+    /// Fade the Prob tokens in and out with a fill intensity (0.0 to 1.0), instead of rolling
+    /// whether they play. None rolls them again.
+    pub fn set_fill(&mut self, fill: Option<f32>) {
+        self.fill = fill.map(|fill| fill.clamp(0.0, 1.0));
+    }
+
+    /// Shift the onsets and velocities of the steps by a groove template, None plays straight.
+    pub fn set_groove(&mut self, groove: Option<Groove>) {
+        self.groove = groove;
+    }
+
+    /// Roll the Prob tokens per step, or once per bar or pass and hold that decision.
+    pub fn set_prob_eval(&mut self, prob_eval: ProbEval) {
+        self.prob_eval = prob_eval;
+        self.rolls.clear();
+    }
+
+    /// Give every sounding step a chance to be dropped on each pass.
+    pub fn set_drop(&mut self, drop: f32) {
+        self.drop = drop.clamp(0.0, 1.0);
+    }
+
+    /// Seed the random choices of this grid, so they are the same on every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Run the grid at a lower sample rate (in Hz), by holding each of its output samples.
+    ///
+    /// Rates at or above the output sample rate leave the output untouched.
+    pub fn set_hold_rate(&mut self, rate: u32) {
+        self.hold_rate = Some(rate.max(1));
+        self.held = None;
+    }
+
+    /// Tune the notes and chords of this grid to A4 at `a4` Hz.
+    pub fn set_tuning(&mut self, a4: f64) {
+        self.tuning = a4;
+    }
+
+    /// Play the tokens which couldn't be parsed as a rest, or as a repeat of the token before them.
+    pub fn set_fallback(&mut self, fallback: Fallback) {
+        let token = match fallback {
+            Fallback::Rest => GridToken::Pause,
+            Fallback::Sustain => GridToken::Repeat,
+        };
+        for (index, _) in &self.failed {
+            self.tokens[*index] = token.clone();
+        }
+    }
+
+    /// The problems with the tokens which couldn't be parsed.
+    pub fn failed_tokens(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.failed.iter().map(|(_, diagnostic)| diagnostic)
+    }
+
+    /// The problems with tokens which are played anyway, like chords with conflicting tensions.
+    pub fn problems(&self) -> &[Diagnostic] {
+        &self.problems
+    }
+
+    /// Shape the level of the notes and chords of this grid with an envelope.
+    pub fn set_envelope(&mut self, envelope: Envelope) {
+        self.envelope = Some(envelope);
+        self.releasing = None;
+    }
+
+    /// Play the notes and chords of this grid with `waveform`.
+    pub fn set_waveform(&mut self, waveform: Waveform) {
+        self.timbre.waveform = waveform;
+    }
+
+    /// Sum `harmonics` harmonics of the waveform for the notes and chords of this grid.
+    pub fn set_harmonics(&mut self, harmonics: usize) {
+        self.timbre.harmonics = Some(harmonics);
+    }
+
     pub fn set_tempo_and_time(&mut self, tempo: f32, time_sign: (u32, u32)) {
         self.tempo = tempo;
         self.time_sign = time_sign;
@@ -280,6 +1120,8 @@ impl Grid {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chromatic::{Octave, PitchClass};
+    use crate::groove::GrooveStep;
     use tree_sitter::Parser;
 
     fn get_test_tree() -> (String, tree_sitter::Tree) {
@@ -319,4 +1161,655 @@ mod tests {
         );
         assert!(matches!(grid.tokens[3], GridToken::Chord(_)));
     }
+
+    #[test]
+    fn velocity_scales_tokens() {
+        let note = Note::new(PitchClass::A, Octave::Four);
+        let click = Arc::new(Sample::new("click.wav", vec![1.0; 100], 48000));
+        let grid = |velocity| Grid {
+            tokens: vec![
+                GridToken::Note(note),
+                GridToken::Hit(SamplePlayer::new(click.clone())),
+            ],
+            velocities: vec![velocity, velocity],
+            ..Default::default()
+        };
+        let mut full = grid(1.0);
+        let mut half = grid(0.5);
+
+        // a step of the note, and the start of the hit
+        for time in 0..6100 {
+            assert_eq!(
+                half.get_sample(time, 48000),
+                0.5 * full.get_sample(time, 48000)
+            );
+        }
+        assert_eq!(parse_velocity("!64"), Some(64.0 / 127.0));
+        assert_eq!(parse_velocity("0.5"), Some(0.5));
+        assert_eq!(parse_velocity("!128"), None);
+        assert!(half.to_string().contains("A!64"));
+    }
+
+    #[test]
+    fn sustain_fallback_repeats() {
+        let note = Note::new(PitchClass::A, Octave::Four);
+        let failed = Diagnostic {
+            file: None,
+            line: 1,
+            column: 8,
+            message: "Unparsable grid token: Hx".to_string(),
+        };
+        let grid = |fallback| {
+            let mut grid = Grid {
+                tokens: vec![GridToken::Note(note), GridToken::default()],
+                failed: vec![(1, failed.clone())],
+                ..Default::default()
+            };
+            grid.set_fallback(fallback);
+            grid
+        };
+
+        let mut rest = grid(Fallback::Rest);
+        let mut sustain = grid(Fallback::Sustain);
+        assert_eq!(rest.tokens[1], GridToken::Pause);
+        assert_eq!(sustain.tokens[1], GridToken::Repeat);
+        assert_eq!(sustain.failed_tokens().collect::<Vec<_>>(), vec![&failed]);
+
+        // the second step starts after 6000 samples at the default tempo
+        for time in 0..12000 {
+            let held = note.get_sample(time, 48000);
+            assert_eq!(sustain.get_sample(time, 48000), held);
+            let rested = rest.get_sample(time, 48000);
+            if time >= 6000 {
+                assert_eq!(rested, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn envelope_shapes_notes() {
+        let note = Note::new(PitchClass::A, Octave::Four);
+        let mut grid = Grid {
+            tokens: vec![GridToken::Note(note), GridToken::Pause],
+            ..Default::default()
+        };
+        // a 10 ms attack, and a release that outlasts the pause
+        grid.set_envelope(Envelope::new(0.01, 0.0, 1.0, 0.5));
+
+        let output: Vec<f32> = (0..12000)
+            .map(|time| grid.get_sample(time, 48000))
+            .collect();
+
+        // the band-limited square overshoots its edges by less than a tenth
+        let attack = 480;
+        assert!(output[..attack]
+            .iter()
+            .enumerate()
+            .all(|(time, sample)| sample.abs() <= 1.1 * time as f32 / attack as f32 + 1e-6));
+        assert!(output[attack..6000].iter().any(|sample| *sample >= 1.0));
+        // the note rings out during the pause
+        assert!(output[6000..].iter().any(|sample| *sample > 0.0));
+    }
+
+    #[test]
+    fn tie_keeps_phase() {
+        let note = Note::new(PitchClass::C, Octave::Four);
+        let grid = |hold: GridToken| Grid {
+            tokens: vec![GridToken::Note(note), hold, GridToken::Note(note)],
+            ..Default::default()
+        };
+        let mut tied = grid(GridToken::Tie);
+        let mut repeated = grid(GridToken::Repeat);
+
+        // the third step starts after 12000 samples at the default tempo
+        for time in 0..12000 {
+            tied.get_sample(time, 48000);
+            repeated.get_sample(time, 48000);
+        }
+
+        for time in 12000..12500 {
+            let continuous = note.get_sample(time, 48000);
+            let attacked = note.get_sample(time - 12000, 48000);
+            assert_eq!(tied.get_sample(time, 48000), continuous);
+            assert_eq!(repeated.get_sample(time, 48000), attacked);
+        }
+    }
+
+    #[test]
+    fn seek_plays_step() {
+        let note = |pitch| GridToken::Note(Note::new(pitch, Octave::Four));
+        let mut grid = Grid {
+            tokens: vec![
+                note(PitchClass::C),
+                note(PitchClass::E),
+                note(PitchClass::G),
+                GridToken::Repeat,
+            ],
+            ..Default::default()
+        };
+
+        // play the first step for a while, then jump into the repeat of the third
+        for time in 0..100 {
+            grid.get_sample(time, 48000);
+        }
+        grid.seek(3 * 6000 + 100, 48000);
+        grid.get_sample(3 * 6000 + 100, 48000);
+
+        assert_eq!(grid.now_playing, Some(2));
+        assert_eq!(grid.next_scheduled, 0);
+    }
+
+    #[test]
+    fn groove_shifts_onsets() {
+        let click = Arc::new(Sample::new("click.wav", vec![1.0; 10], 48000));
+        let mut grid = Grid {
+            tokens: vec![GridToken::Hit(SamplePlayer::new(click)); 2],
+            ..Default::default()
+        };
+        grid.set_groove(Some(Groove::new(vec![
+            GrooveStep::default(),
+            GrooveStep {
+                timing: 0.5,
+                velocity: 0.5,
+            },
+        ])));
+
+        let output: Vec<f32> = (0..12000)
+            .map(|time| grid.get_sample(time, 48000))
+            .collect();
+
+        // the second step is half a step (3000 samples) late, and half as loud
+        assert_eq!(output[0], 1.0);
+        assert!(output[6000..9000].iter().all(|s| *s == 0.0));
+        assert_eq!(output[9000], 0.5);
+    }
+
+    #[test]
+    fn counted_rest() {
+        assert_eq!(GridToken::from_raw("_4"), Some(vec![GridToken::Pause; 4]));
+        assert_eq!(GridToken::from_raw("_"), Some(vec![GridToken::Pause]));
+        assert_eq!(GridToken::from_raw("&"), Some(vec![GridToken::Repeat]));
+    }
+
+    #[test]
+    fn counted_rest_is_limited() {
+        assert_eq!(GridToken::from_raw("_0"), None);
+        assert_eq!(GridToken::from_raw("_999999999"), None);
+        assert_eq!(
+            GridToken::from_raw(&format!("_{}", MAX_REST_STEPS)).map(|tokens| tokens.len()),
+            Some(MAX_REST_STEPS)
+        );
+    }
+
+    #[test]
+    fn swap_sample_keeps_voice() {
+        let old = Arc::new(Sample::new("kick.wav", vec![1.0; 16], 48000));
+        let mut grid = Grid {
+            tokens: vec![GridToken::Hit(SamplePlayer::new(old.clone()))],
+            ..Default::default()
+        };
+
+        // trigger the voice
+        grid.get_sample(0, 48000);
+
+        let new = Arc::new(Sample::new("kick.wav", vec![0.5; 16], 48000));
+        grid.swap_sample(&new);
+
+        assert!(Arc::ptr_eq(
+            &grid.voices.players().next().unwrap().sample,
+            &old
+        ));
+        match &grid.tokens[0] {
+            GridToken::Hit(s) => assert!(Arc::ptr_eq(&s.sample, &new)),
+            _ => panic!("Token is no longer a hit"),
+        }
+    }
+
+    #[test]
+    fn repeat_sustains_hit() {
+        let pad = Arc::new(Sample::new("pad.wav", vec![1.0; 48000], 48000));
+        let mut grid = Grid {
+            tokens: vec![
+                GridToken::Hit(SamplePlayer::new(pad)),
+                GridToken::Repeat,
+                GridToken::Repeat,
+                GridToken::Pause,
+            ],
+            ..Default::default()
+        };
+
+        // the hit rings over its own step and both repeats, without being hit again
+        for time in 0..18000 {
+            assert_eq!(grid.get_sample(time, 48000), 1.0, "Silent at {}", time);
+        }
+        assert_eq!(grid.voices.len(), 1);
+
+        // the pause fades it out, although the sample is longer
+        let fade: Vec<f32> = (18000..24000)
+            .map(|time| grid.get_sample(time, 48000))
+            .collect();
+        assert!(fade[0] > 0.9, "Cut off at the pause: {}", fade[0]);
+        assert!(
+            fade.windows(2).all(|w| w[1] <= w[0]),
+            "The fade is not monotone"
+        );
+        assert_eq!(fade[480..].iter().sum::<f32>(), 0.0);
+    }
+
+    #[test]
+    fn repeat_wraps_to_last_token() {
+        let note = Note::new(PitchClass::A, Octave::Four);
+        let mut grid = Grid {
+            tokens: vec![GridToken::Repeat, GridToken::Pause, GridToken::Note(note)],
+            ..Default::default()
+        };
+
+        grid.get_sample(0, 48000);
+        assert_eq!(grid.now_playing, Some(2));
+    }
+
+    #[test]
+    fn tempo_change_keeps_voices() {
+        let ramp = Arc::new(Sample::new(
+            "ramp.wav",
+            (0..48000).map(|i| i as f32).collect(),
+            48000,
+        ));
+        let mut grid = Grid {
+            tokens: vec![
+                GridToken::Hit(SamplePlayer::new(ramp)),
+                GridToken::Repeat,
+                GridToken::Repeat,
+                GridToken::Repeat,
+            ],
+            ..Default::default()
+        };
+
+        for time in 0..1000 {
+            grid.get_sample(time, 48000);
+        }
+
+        // at half the tempo the same position in the bar is twice as late
+        grid.set_tempo(60.0);
+        grid.shift(1000, 2000);
+        assert_eq!(grid.get_sample(2000, 48000), 1000.0);
+        assert_eq!(grid.voices.len(), 1);
+    }
+
+    #[test]
+    fn hit_plays_from_first_frame() {
+        let ramp = Arc::new(Sample::new(
+            "ramp.wav",
+            (0..100).map(|i| 1.0 + i as f32).collect(),
+            48000,
+        ));
+        let mut grid = Grid {
+            tokens: vec![
+                GridToken::Pause,
+                GridToken::Pause,
+                GridToken::Hit(SamplePlayer::new(ramp)),
+                GridToken::Pause,
+            ],
+            ..Default::default()
+        };
+
+        for time in 0..12000 {
+            assert_eq!(grid.get_sample(time, 48000), 0.0);
+        }
+
+        // the sample starts at slot 2, not at the time since the start of the grid
+        assert_eq!(grid.get_sample(12000, 48000), 1.0);
+        assert_eq!(grid.get_sample(12001, 48000), 2.0);
+    }
+
+    #[test]
+    fn hit_is_resampled_to_output_rate() {
+        // every frame holds its own index, so the output shows where the player reads
+        let frames: Vec<f32> = (0..2000).map(|i| i as f32).collect();
+        let read_at = |sample_rate| {
+            let sample = Arc::new(Sample::new("ramp.wav", frames.clone(), sample_rate));
+            let mut grid = Grid {
+                tokens: vec![GridToken::Hit(SamplePlayer::new(sample)), GridToken::Pause],
+                ..Default::default()
+            };
+            (0..=1000)
+                .map(|time| grid.get_sample(time, 48000))
+                .last()
+                .unwrap()
+        };
+
+        // a 44.1 kHz sample advances 44100 / 48000 frames per output sample
+        assert!((read_at(48000) - 1000.0).abs() < 1e-2);
+        assert!(
+            (read_at(44100) - 918.75).abs() < 1e-2,
+            "44.1 kHz sample is read at {}",
+            read_at(44100)
+        );
+    }
+
+    #[test]
+    fn step_length_cuts_sample() {
+        let pad = Arc::new(Sample::new("pad.wav", vec![1.0; 48000], 48000));
+        let mut grid = Grid {
+            tokens: vec![GridToken::Hit(SamplePlayer::new(pad)), GridToken::Pause],
+            ..Default::default()
+        };
+        grid.set_sample_length(SampleLength::Step);
+
+        for time in 0..6000 {
+            assert_eq!(grid.get_sample(time, 48000), 1.0, "Cut early at {}", time);
+        }
+
+        // the release fades it out in 5 ms, although the sample is a second long
+        let release: Vec<f32> = (6000..6240)
+            .map(|time| grid.get_sample(time, 48000))
+            .collect();
+        assert!(release.windows(2).all(|pair| pair[1] < pair[0]));
+        assert_eq!(grid.get_sample(6240, 48000), 0.0);
+        assert!(grid.voices.is_empty());
+    }
+
+    #[test]
+    fn consecutive_hits_overlap() {
+        let kick = Arc::new(Sample::new("kick.wav", vec![1.0; 20000], 48000));
+        let mut grid = Grid {
+            tokens: vec![
+                GridToken::Hit(SamplePlayer::new(kick.clone())),
+                GridToken::Hit(SamplePlayer::new(kick)),
+            ],
+            ..Default::default()
+        };
+
+        // one token is 6000 samples long at the default tempo
+        for time in 0..6000 {
+            assert_eq!(grid.get_sample(time, 48000), 1.0);
+        }
+        let both = grid.get_sample(6000, 48000);
+
+        assert_eq!(grid.voices.len(), 2);
+        assert!(
+            (both - 2.0).abs() < 1e-6,
+            "Both hits should ring, got {}",
+            both
+        );
+    }
+
+    #[test]
+    fn seeded_drops_repeat() {
+        let hits = |seed| -> Vec<bool> {
+            let click = Arc::new(Sample::new("click.wav", vec![1.0; 10], 48000));
+            let mut grid = Grid {
+                tokens: vec![GridToken::Hit(SamplePlayer::new(click)); 16],
+                ..Default::default()
+            };
+            grid.set_drop(0.5);
+            grid.set_seed(seed);
+
+            // one token is 6000 samples long at the default tempo
+            (0..16 * 6000)
+                .map(|time| grid.get_sample(time, 48000))
+                .step_by(6000)
+                .map(|s| s != 0.0)
+                .collect()
+        };
+
+        let first = hits(7);
+
+        assert_eq!(first, hits(7), "Same seed dropped different steps");
+        assert!(first.contains(&true) && first.contains(&false));
+    }
+
+    #[test]
+    fn loop_roll_holds_prob() {
+        let click = Arc::new(Sample::new("click.wav", vec![1.0; 10], 48000));
+        let tokens: Vec<GridToken> = [20.0, 50.0, 50.0, 80.0]
+            .into_iter()
+            .map(|p| GridToken::Prob(p, SamplePlayer::new(click.clone())))
+            .collect();
+        // the passes of a bar or loop, which token was heard at every step
+        let passes = |prob_eval, passes: usize| -> Vec<Vec<bool>> {
+            let mut grid = Grid {
+                tokens: tokens.clone(),
+                ..Default::default()
+            };
+            grid.set_prob_eval(prob_eval);
+            grid.set_seed(3);
+
+            // one token is 6000 samples long at the default tempo
+            (0..passes)
+                .map(|pass| {
+                    (pass * 4 * 6000..(pass + 1) * 4 * 6000)
+                        .map(|time| grid.get_sample(time as u128, 48000))
+                        .step_by(6000)
+                        .map(|s| s != 0.0)
+                        .collect()
+                })
+                .collect()
+        };
+
+        // a bar of 16 steps is four passes, in which every token keeps its roll
+        let bars = passes(ProbEval::Bar, 64);
+        for bar in bars.chunks(4) {
+            assert!(
+                bar.iter().all(|pass| *pass == bar[0]),
+                "Prob tokens were decided differently within a bar: {:?}",
+                bar
+            );
+        }
+
+        // each token is rolled on its own, with its own chance
+        let loops = passes(ProbEval::Loop, 64);
+        assert!(
+            loops.iter().any(|pass| pass[1] != pass[2]),
+            "The Prob tokens were tied together"
+        );
+        let heard = |token: usize| loops.iter().filter(|pass| pass[token]).count();
+        assert!(heard(0) < heard(3));
+        assert!(heard(0) > 0 && heard(3) < loops.len());
+    }
+
+    #[test]
+    fn fill_intensity_raises_level() {
+        let level = |fill| -> f32 {
+            let click = Arc::new(Sample::new("click.wav", vec![1.0; 6000], 48000));
+            let mut grid = Grid {
+                tokens: vec![
+                    GridToken::Hit(SamplePlayer::new(click.clone())),
+                    GridToken::Prob(30.0, SamplePlayer::new(click.clone())),
+                    GridToken::Prob(60.0, SamplePlayer::new(click)),
+                    GridToken::Pause,
+                ],
+                ..Default::default()
+            };
+            grid.set_fill(Some(fill));
+
+            // one loop of four tokens, 6000 samples each at the default tempo
+            (0..4 * 6000)
+                .map(|time| grid.get_sample(time, 48000).abs())
+                .sum()
+        };
+
+        let levels: Vec<f32> = [0.0, 0.25, 0.5, 0.75, 1.0].into_iter().map(level).collect();
+        assert!(
+            levels.windows(2).all(|pair| pair[0] < pair[1]),
+            "Levels don't rise with the fill intensity: {:?}",
+            levels
+        );
+        // only the hit plays without any fill, and the prob tokens at their chance halfway
+        assert!((levels[0] - 6000.0).abs() < 1.0);
+        assert!((levels[2] - 6000.0 * (1.0 + 0.3 + 0.6)).abs() < 10.0);
+    }
+
+    #[test]
+    fn probability_round_trips() {
+        let click = SamplePlayer::new(Arc::new(Sample::new("click.wav", vec![1.0; 10], 48000)));
+        let prob = GridToken::Prob(50.0, click.clone());
+        assert_eq!(prob.to_string(), "x?50");
+
+        let mut grid = Grid {
+            tokens: GridToken::from_raw(&prob.to_string()).unwrap(),
+            ..Default::default()
+        };
+        grid.resolve_keys(&HashMap::from([("x".to_string(), GridToken::Hit(click))]));
+
+        assert_eq!(grid.tokens, vec![prob]);
+    }
+
+    #[test]
+    fn boundary_applies_to_later_maps() {
+        let click = SamplePlayer::new(Arc::new(Sample::new("click.wav", vec![1.0; 10], 48000)));
+        let mut grid = Grid {
+            tokens: GridToken::from_raw("x").unwrap(),
+            ..Default::default()
+        };
+        grid.set_boundary(Boundary::Clamp);
+        grid.resolve_keys(&HashMap::from([(
+            "x".to_string(),
+            GridToken::Hit(click.clone()),
+        )]));
+
+        assert_eq!(
+            grid.tokens,
+            vec![GridToken::Hit(click.with_boundary(Boundary::Clamp))]
+        );
+    }
+
+    #[test]
+    fn choice_follows_weights() {
+        let a = Note::new(PitchClass::A, Octave::Four);
+        let c = Note::new(PitchClass::C, Octave::Four);
+        let mut grid = Grid {
+            tokens: vec![
+                GridToken::choice(vec![
+                    (Some(30.0), GridToken::Note(a)),
+                    (None, GridToken::Note(c)),
+                ]),
+                GridToken::Pause,
+            ],
+            ..Default::default()
+        };
+        grid.set_seed(3);
+
+        // one token is 6000 samples long at the default tempo, so a pass is 12000 samples
+        let passes = 4000;
+        let mut counts = [0; 2];
+        for pass in 0..passes {
+            grid.get_sample(pass * 12000, 48000);
+            counts[grid.chosen.unwrap()] += 1;
+            grid.get_sample(pass * 12000 + 6000, 48000);
+        }
+
+        let share = counts[0] as f32 / passes as f32;
+        assert!(
+            (share - 0.3).abs() < 0.03,
+            "The 30% alternative was chosen {} of the time",
+            share
+        );
+
+        // what is left of 100% is silence
+        let GridToken::Choice(alternatives) =
+            GridToken::choice(vec![(Some(25.0), GridToken::Note(a))])
+        else {
+            panic!("Not a choice");
+        };
+        assert_eq!(GridToken::choose(&alternatives, 10.0), Some(0));
+        assert_eq!(GridToken::choose(&alternatives, 60.0), None);
+    }
+
+    #[test]
+    fn density_scales_probability() {
+        let click = Arc::new(Sample::new("click.wav", vec![1.0; 10], 48000));
+        // the share of the rolls that played, over many passes of four Prob tokens
+        let hit_rate = |density| -> f64 {
+            let mut grid = Grid {
+                tokens: vec![GridToken::Prob(50.0, SamplePlayer::new(click.clone())); 4],
+                ..Default::default()
+            };
+            grid.set_seed(11);
+            grid.set_density(density);
+
+            // one token is 6000 samples long at the default tempo
+            let hits = (0..4 * 6000 * 250)
+                .map(|time| grid.get_sample(time as u128, 48000))
+                .step_by(6000)
+                .filter(|s| *s != 0.0)
+                .count();
+            hits as f64 / 1000.0
+        };
+
+        let full = hit_rate(1.0);
+        let thinned = hit_rate(0.5);
+        assert!((full - 0.5).abs() < 0.06, "Density 1 played {}", full);
+        assert!(
+            (thinned - 0.25).abs() < 0.06,
+            "Density 0.5 played {}",
+            thinned
+        );
+        assert_eq!(hit_rate(0.0), 0.0);
+        assert_eq!(hit_rate(2.0), 1.0);
+    }
+
+    #[test]
+    fn hold_rate_is_piecewise_constant() {
+        let mut grid = Grid {
+            tokens: vec![
+                GridToken::Note(Note::new(PitchClass::A, Octave::Four)),
+                GridToken::Repeat,
+            ],
+            ..Default::default()
+        };
+        grid.set_hold_rate(8000);
+
+        // at 48 kHz, every output sample is held for 6 samples
+        let output: Vec<f32> = (0..600).map(|time| grid.get_sample(time, 48000)).collect();
+
+        for chunk in output.chunks(6) {
+            assert!(
+                chunk.iter().all(|s| *s == chunk[0]),
+                "Output changed within a hold interval: {:?}",
+                chunk
+            );
+        }
+        assert!(
+            output.chunks(6).any(|chunk| chunk[0] != output[0]),
+            "Held output never changes"
+        );
+    }
+
+    #[test]
+    fn loop_duration_spans_all_tokens() {
+        let grid = Grid {
+            tokens: vec![GridToken::Pause; 4],
+            ..Default::default()
+        };
+
+        let token = grid.samples_per_token(48000) as u128;
+        assert_eq!(token, 6000);
+        assert_eq!(grid.loop_duration(48000), 4 * token);
+        assert_eq!(Grid::default().loop_duration(48000), 0);
+    }
+
+    #[test]
+    fn display_groups_beats() {
+        let beat = [
+            GridToken::Pause,
+            GridToken::Repeat,
+            GridToken::Tie,
+            GridToken::Pause,
+        ];
+        let mut grid = Grid {
+            tokens: beat.iter().cycle().take(20).cloned().collect(),
+            ..Default::default()
+        };
+
+        // four sixteenths per beat, four beats per bar
+        let bar = "_ & ~ _   _ & ~ _   _ & ~ _   _ & ~ _";
+        assert_eq!(grid.to_string(), format!("{{\n   {}\n   _ & ~ _\n}}", bar));
+
+        // two eighths per beat
+        grid.set_note_length((1, 8));
+        let lines: Vec<String> = grid.to_string().lines().map(str::to_string).collect();
+        assert_eq!(lines[1], "   _ &   ~ _   _ &   ~ _");
+        assert_eq!(lines.len(), 5);
+    }
 }
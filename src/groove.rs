@@ -0,0 +1,151 @@
+/*!
+* Groove templates: per-step timing and velocity offsets, like an MPC groove.
+*/
+
+/// The offsets of a single step in a groove.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GrooveStep {
+    /// Shift of the onset, as a fraction of a step (positive is later), in (-1, 1).
+    pub timing: f32,
+    /// Gain of the step, 1.0 plays it unchanged.
+    pub velocity: f32,
+}
+
+/// A groove template, which repeats every `steps.len()` steps.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Groove {
+    steps: Vec<GrooveStep>,
+}
+
+impl Default for GrooveStep {
+    fn default() -> Self {
+        Self {
+            timing: 0.0,
+            velocity: 1.0,
+        }
+    }
+}
+
+impl TryFrom<&str> for GrooveStep {
+    type Error = &'static str;
+
+    /// Parse a step as `timing` or `timing:velocity`, e.g. `0.2:0.8`.
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let (timing, velocity) = match value.split_once(':') {
+            Some((timing, velocity)) => (timing, Some(velocity)),
+            None => (value, None),
+        };
+
+        let timing: f32 = timing.trim().parse().map_err(|_| "Invalid groove timing")?;
+        let velocity: f32 = match velocity {
+            Some(v) => v.trim().parse().map_err(|_| "Invalid groove velocity")?,
+            None => 1.0,
+        };
+
+        if timing.abs() >= 1.0 {
+            return Err("Groove timing must be less than one step");
+        }
+
+        Ok(Self { timing, velocity })
+    }
+}
+
+impl Groove {
+    pub fn new(steps: Vec<GrooveStep>) -> Self {
+        Self { steps }
+    }
+
+    /// A swing at `percentage` (50 is straight, 66.7 is a triplet feel) of pairs of notes which
+    /// are `length` steps long, e.g. a length of 2 swings eighths in a grid of sixteenths.
+    pub fn swing(percentage: f32, length: usize) -> Self {
+        let ratio = percentage / 100.0;
+        let length = length.max(1);
+        let steps = (0..2 * length)
+            .map(|i| {
+                let straight = i as f32;
+                let swung = if i < length {
+                    straight * 2.0 * ratio
+                } else {
+                    length as f32 * 2.0 * ratio + (straight - length as f32) * 2.0 * (1.0 - ratio)
+                };
+                GrooveStep {
+                    timing: swung - straight,
+                    velocity: 1.0,
+                }
+            })
+            .collect();
+
+        Self { steps }
+    }
+
+    /// The groove with the given name, like `swing 16` or `triplet-feel`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "swing 16" | "swing16" => Some(Self::swing(60.0, 1)),
+            "swing 8" | "swing8" => Some(Self::swing(60.0, 2)),
+            "triplet-feel" | "triplet" => Some(Self::swing(200.0 / 3.0, 1)),
+            "straight" => Some(Self::new(Vec::new())),
+            _ => None,
+        }
+    }
+
+    /// The offsets of the given step.
+    pub fn step(&self, step: i128) -> GrooveStep {
+        if self.steps.is_empty() {
+            return GrooveStep::default();
+        }
+
+        self.steps[step.rem_euclid(self.steps.len() as i128) as usize]
+    }
+
+    /// The step that is sounding at `position` (in steps since the start), with the onsets
+    /// shifted by the template.
+    pub fn step_at(&self, position: f64) -> i128 {
+        let step = position.floor() as i128;
+        let onset = |step: i128| step as f64 + self.step(step).timing as f64;
+
+        if position >= onset(step + 1) {
+            // the next step was pulled forward
+            step + 1
+        } else if position < onset(step) {
+            // this step is pushed back, the previous one is still sounding
+            step - 1
+        } else {
+            step
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_steps() {
+        assert_eq!(
+            GrooveStep::try_from("0.2:0.8"),
+            Ok(GrooveStep {
+                timing: 0.2,
+                velocity: 0.8
+            })
+        );
+        assert_eq!(GrooveStep::try_from("-0.1").map(|s| s.velocity), Ok(1.0));
+        assert!(GrooveStep::try_from("1.5").is_err());
+    }
+
+    #[test]
+    fn triplet_feel_delays_off_beats() {
+        let groove = Groove::preset("triplet-feel").unwrap();
+
+        // the off-beat sixteenth lands on the last note of a triplet, a third of a step late
+        assert_eq!(groove.step(0).timing, 0.0);
+        assert!(
+            (groove.step(1).timing - 1.0 / 3.0).abs() < 1e-6,
+            "Off-beat is shifted by {} steps",
+            groove.step(1).timing
+        );
+        assert_eq!(groove.step_at(1.3), 0);
+        assert_eq!(groove.step_at(1.34), 1);
+        assert!(Groove::preset("shuffle-ish").is_none());
+    }
+}
@@ -1,10 +1,16 @@
 pub mod audio_engine;
+pub mod automation;
 pub mod chromatic;
+pub mod control;
+pub mod diagnostic;
+pub mod envelope;
 pub mod grid;
+pub mod groove;
 pub mod scale;
 // TODO: find a better name for this module
 pub mod pipeline;
 pub mod postproc;
 pub mod sampler;
+pub mod source;
 pub mod util;
 
@@ -1,12 +1,16 @@
 use std::{
-    path::PathBuf,
+    fs,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     thread,
+    time::Instant,
 };
 
 use breakers::{
     audio_engine,
-    pipeline::{Pipeline, PipelineConfig},
+    control::{Command, TapTempo},
+    pipeline::{Pipeline, PipelineConfig, ReloadStatus, RenderLength, RenderStats},
+    source::{IncrementalParser, InputSource},
 };
 use clap::Parser as ClapParser;
 use notify::{
@@ -18,11 +22,125 @@ use tree_sitter::Parser;
 #[derive(clap::Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// The breaker file to play, or `-` to read it from stdin
     #[arg(required = true)]
     input_file: String,
 
     #[arg(short, long, default_value = "samples")]
     sample_dir: String,
+
+    /// Master volume, applied to the summed output
+    #[arg(short, long, default_value_t = 1.0)]
+    volume: f32,
+
+    /// Normalize the peaks of the output to this level in dBFS, e.g. -1.0 (off by default)
+    #[arg(long, allow_hyphen_values = true)]
+    headroom: Option<f32>,
+
+    /// Loop the whole piece every this many bars, e.g. to play over a backing track
+    #[arg(long)]
+    loop_bars: Option<u32>,
+
+    /// Length of the crossfade from the end of the loop into its start, in milliseconds
+    #[arg(long, requires = "loop_bars", default_value_t = 10.0)]
+    loop_crossfade: f32,
+
+    /// Control the playback from the terminal: Enter taps the tempo, `m <track>` mutes a track
+    /// and `s <track>` solos it (`s` hears all tracks again)
+    #[arg(long, conflicts_with = "render")]
+    control: bool,
+
+    /// Render to this WAV file instead of playing (4 bars, unless a length is given)
+    #[arg(long)]
+    render: Option<PathBuf>,
+
+    /// Length of the render in seconds
+    #[arg(long, requires = "render", conflicts_with_all = ["render_bars", "render_loops"])]
+    render_seconds: Option<f32>,
+
+    /// Length of the render in bars
+    #[arg(long, requires = "render", conflicts_with = "render_loops")]
+    render_bars: Option<u32>,
+
+    /// Length of the render in loops of the longest grid
+    #[arg(long, requires = "render")]
+    render_loops: Option<u32>,
+}
+
+impl Args {
+    fn render_length(&self) -> RenderLength {
+        if let Some(seconds) = self.render_seconds {
+            RenderLength::Seconds(seconds)
+        } else if let Some(loops) = self.render_loops {
+            RenderLength::Loops(loops)
+        } else {
+            RenderLength::Bars(self.render_bars.unwrap_or(4))
+        }
+    }
+}
+
+/// Read commands from stdin and run them on the pipeline, until stdin is closed.
+fn spawn_control(pipeline: Arc<Mutex<Pipeline>>) {
+    thread::spawn(move || {
+        let start = Instant::now();
+        let mut tap_tempo = TapTempo::default();
+
+        for line in std::io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            match Command::try_from(line.as_str()) {
+                Ok(command) => command.apply(
+                    &mut pipeline.lock().unwrap(),
+                    &mut tap_tempo,
+                    start.elapsed().as_secs_f64(),
+                ),
+                Err(err) => log::warn!("{err}"),
+            }
+        }
+    });
+}
+
+/// Render the pipeline offline and write it to a mono WAV file, returning the levels of the
+/// render.
+/// Watch the directories of the included files which aren't watched yet, `watched` holds the
+/// directories which are.
+fn watch_includes(
+    watcher: &mut RecommendedWatcher,
+    includes: &[PathBuf],
+    watched: &mut Vec<PathBuf>,
+) {
+    for dir in includes.iter().filter_map(|file| file.parent()) {
+        if watched.iter().any(|watched| dir.starts_with(watched)) {
+            continue;
+        }
+        match watcher.watch(dir, RecursiveMode::NonRecursive) {
+            Ok(()) => watched.push(dir.to_path_buf()),
+            Err(err) => log::warn!("Could not watch {}: {}", dir.display(), err),
+        }
+    }
+}
+
+fn render(
+    pipeline: &mut Pipeline,
+    length: RenderLength,
+    file: &Path,
+) -> Result<RenderStats, hound::Error> {
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: pipeline.sample_rate(),
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float,
+    };
+
+    let samples = pipeline.render(length);
+    let mut writer = hound::WavWriter::create(file, spec)?;
+    for sample in &samples {
+        writer.write_sample(*sample)?;
+    }
+
+    writer.finalize()?;
+    Ok(RenderStats::new(&samples))
 }
 
 fn main() {
@@ -36,27 +154,74 @@ fn main() {
         .unwrap();
 
     log::info!("Starting up from file: {}", args.input_file);
-    let input_file = PathBuf::try_from(args.input_file).unwrap();
+    let input = InputSource::from(args.input_file.as_str());
 
     // read file
-    let source_code = std::fs::read_to_string(&input_file).unwrap();
+    let (source_code, mut lines) = match input.read() {
+        Ok(read) => read,
+        Err(err) => {
+            log::error!("Could not read the input: {err}");
+            return;
+        }
+    };
 
     // parse
-    let mut tree = parser.parse(&source_code, None).unwrap();
+    let mut parsed = match IncrementalParser::new(parser, source_code) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            log::error!("{err}");
+            return;
+        }
+    };
 
     log::info!("Sampling directory: {}", args.sample_dir);
     let pipeline_config = PipelineConfig {
-        samples_dir: args.sample_dir,
+        samples_dir: args.sample_dir.clone(),
+        master_gain: args.volume,
+        headroom: args.headroom,
+        ..Default::default()
     };
 
     // create the pipeline and the audio output engine
     let Ok((mut pipeline, source)) =
-        Pipeline::from_tree(&tree, &source_code, Some(&pipeline_config))
+        Pipeline::from_tree(parsed.tree(), parsed.source(), Some(&pipeline_config))
     else {
         log::warn!("Pipeline creation failed, exiting");
         return;
     };
-    let (_stream, config) = audio_engine::start(source);
+    for diagnostic in pipeline.diagnostics() {
+        log::warn!("{}", lines.relocate(diagnostic));
+    }
+    pipeline.set_loop(args.loop_bars, args.loop_crossfade);
+
+    if let Some(file) = &args.render {
+        match render(&mut pipeline, args.render_length(), file) {
+            Ok(stats) => {
+                log::info!(
+                    "Rendered to {}, peak {:.3} (true peak {:.3})",
+                    file.display(),
+                    stats.peak,
+                    stats.true_peak
+                );
+                if stats.clips() {
+                    log::warn!(
+                        "The render clipped on {} samples, lower the gains (or use --headroom)",
+                        stats.clipped
+                    );
+                }
+            }
+            Err(err) => log::error!("Could not render to {}: {err}", file.display()),
+        }
+        return;
+    }
+
+    let (_stream, config, _meters) = match audio_engine::start(source) {
+        Ok(output) => output,
+        Err(err) => {
+            log::error!("Could not start the audio engine: {err}");
+            return;
+        }
+    };
 
     // notify the pipeline of the output config
     pipeline.set_output_config(&config);
@@ -68,7 +233,7 @@ fn main() {
 
     // run the pipeline thread
     let shared_pipeline_thread = shared_pipeline.clone();
-    thread::spawn(move || loop {
+    let pipeline_thread = thread::spawn(move || loop {
         let mut p = shared_pipeline_thread.lock().unwrap();
 
         let Ok(_) = p.send_sample() else {
@@ -76,6 +241,22 @@ fn main() {
         };
     });
 
+    if args.control {
+        if input == InputSource::Stdin {
+            log::warn!(
+                "The source is read from stdin, so it can't be controlled from the terminal"
+            );
+        } else {
+            spawn_control(shared_pipeline.clone());
+        }
+    }
+
+    // stdin can't change, so there is nothing to watch: just keep playing
+    let Some(input_file) = input.watch_path() else {
+        let _ = pipeline_thread.join();
+        return;
+    };
+
     // set up file watcher
     let (tx, rx) = std::sync::mpsc::channel();
 
@@ -85,6 +266,22 @@ fn main() {
         .watch(input_file.parent().unwrap(), RecursiveMode::Recursive)
         .unwrap();
 
+    // and the included files, which can be outside of the directory of the input
+    let mut watched: Vec<PathBuf> = fs::canonicalize(input_file)
+        .ok()
+        .and_then(|file| file.parent().map(Path::to_path_buf))
+        .into_iter()
+        .collect();
+    watch_includes(&mut watcher, lines.includes(), &mut watched);
+
+    // also watch the samples, so they can be swapped while playing
+    let samples_dir = std::fs::canonicalize(&pipeline_config.samples_dir).ok();
+    if let Some(dir) = &samples_dir {
+        if let Err(err) = watcher.watch(dir, RecursiveMode::Recursive) {
+            log::warn!("Could not watch the samples directory: {}", err);
+        }
+    }
+
     let target_event_kind = EventKind::Modify(ModifyKind::Data(DataChange::Any));
 
     for msg in rx {
@@ -93,23 +290,61 @@ fn main() {
                 if event.kind != target_event_kind {
                     continue;
                 }
-                if event
-                    .paths
-                    .iter()
-                    .any(|path| path.file_name() == input_file.file_name())
-                {
-                    let source_code = std::fs::read_to_string(&input_file).unwrap();
-                    tree = parser.parse(&source_code, None).unwrap();
+                if event.paths.iter().any(|path| {
+                    path.file_name() == input_file.file_name()
+                        || fs::canonicalize(path).is_ok_and(|path| lines.includes().contains(&path))
+                }) {
+                    let source_code = match input.read() {
+                        Ok((source_code, new_lines)) => {
+                            lines = new_lines;
+                            watch_includes(&mut watcher, lines.includes(), &mut watched);
+                            source_code
+                        }
+                        Err(err) => {
+                            log::warn!("Could not read the input: {err}");
+                            continue;
+                        }
+                    };
+                    // only reparse what changed, and skip saves which didn't change anything
+                    match parsed.update(source_code) {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(err) => {
+                            log::error!("{err}, keeping the previous pipeline");
+                            shared_pipeline.lock().unwrap().reload_failed();
+                            continue;
+                        }
+                    }
                     let Ok((new_p, _)) =
-                        Pipeline::from_tree(&tree, &source_code, Some(&pipeline_config))
+                        Pipeline::from_tree(parsed.tree(), parsed.source(), Some(&pipeline_config))
                     else {
                         log::warn!("Pipeline creation failed");
+                        shared_pipeline.lock().unwrap().reload_failed();
                         continue;
                     };
+                    for diagnostic in new_p.diagnostics() {
+                        log::warn!("{}", lines.relocate(diagnostic));
+                    }
                     {
                         let mut p = shared_pipeline.lock().unwrap();
-                        p.update(new_p);
-                        log::info!("Tree was updated!");
+                        match p.update(new_p) {
+                            ReloadStatus::Clean => log::info!("Tree was updated!"),
+                            ReloadStatus::Diagnostics(n) => {
+                                log::info!("Tree was updated, with {n} problems")
+                            }
+                            ReloadStatus::Failed => {}
+                        }
+                    }
+                }
+
+                for path in event.paths.iter().filter(|path| {
+                    samples_dir
+                        .as_ref()
+                        .is_some_and(|dir| path.starts_with(dir))
+                }) {
+                    let mut p = shared_pipeline.lock().unwrap();
+                    if p.reload_sample(path) {
+                        log::info!("Sample {} was reloaded!", path.display());
                     }
                 }
             }
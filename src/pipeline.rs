@@ -1,7 +1,9 @@
 use std::{
-    collections::HashMap,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     error::Error,
     fs,
+    hash::{Hash, Hasher},
+    path::Path,
     sync::{
         mpsc::{self, Receiver, SendError, SyncSender},
         Arc,
@@ -10,40 +12,265 @@ use std::{
 };
 
 use crate::{
-    grid::Grid,
-    postproc::{Effect, FIRBuilder, Gain},
-    sampler::{Sample, SampleSet},
+    automation::{Automation, Curve, Ramp},
+    chromatic::{Chord, Note, Timbre, A4},
+    diagnostic::Diagnostic,
+    envelope::Envelope,
+    grid::{parse_speed, Grid},
+    groove::{Groove, GrooveStep},
+    postproc::{
+        normalize_trim, true_peak, AutoGain, Aux, Compressor, Delay, Effect, FIRBuilder, Gain,
+        Reverb, DEFAULT_ATTACK, DEFAULT_DELAY_MIX, DEFAULT_RELEASE, MAX_FEEDBACK, MAX_FIR_LENGTH,
+    },
+    sampler::{RateMismatch, Sample, SampleSet, SkippedSample, Slice},
     util::FromNode,
 };
 
-const PLAYABLES: [&str; 1] = ["grid"];
+#[cfg(feature = "baked-reverb")]
+use crate::postproc::Convolution;
+
+const PLAYABLES: [&str; 3] = ["grid", "standalone_chord", "standalone_note"];
+
+/// Default largest gain a source can set (+24 dB).
+pub const MAX_GAIN: f32 = 16.0;
+
+/// Time the level of a compressor is measured over, in milliseconds.
+const COMPRESSOR_WINDOW_MS: u32 = 10;
 
+/// Default length of the fade when pausing, stopping or resuming, in milliseconds.
+pub const DEFAULT_FADE_MS: f32 = 5.0;
+
+// most playables are grids, so boxing them wouldn't save anything
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
 pub enum Playable {
     Grid(Grid),
+    /// A chord which sounds continuously, e.g. as a pad.
+    Chord(Chord),
+    /// A note which sounds continuously.
+    Note(Note),
+}
+
+impl Playable {
+    /// The grid of this playable, None for the playables which aren't sequenced.
+    pub fn as_grid_mut(&mut self) -> Option<&mut Grid> {
+        match self {
+            Playable::Grid(g) => Some(g),
+            _ => None,
+        }
+    }
+
+    /// Shift all notes and chords of this playable by a number of semitones.
+    fn transpose(&mut self, semitones: i8) {
+        match self {
+            Playable::Grid(g) => g.transpose(semitones),
+            Playable::Chord(c) => *c = c.transpose(semitones),
+            Playable::Note(n) => *n = n.transpose(semitones),
+        }
+    }
+
+    /// The sample at `time`, grids are tuned and get their timbre when they are built, so they
+    /// ignore `a4` and `timbre`.
+    fn get_sample(&mut self, time: u128, sample_rate: u32, a4: f64, timbre: Timbre) -> f32 {
+        match self {
+            Playable::Grid(g) => g.get_sample(time, sample_rate),
+            Playable::Chord(c) => c.get_sample_with_timbre(time, sample_rate, a4, timbre),
+            Playable::Note(n) => n.get_sample_with_timbre(time, sample_rate, a4, timbre),
+        }
+    }
+}
+
+fn not_a_grid(node: &tree_sitter::Node, target: &str) -> Diagnostic {
+    Diagnostic::new(node, format!("{} is not a grid", target))
 }
 
 pub struct Pipeline {
     pub playables: HashMap<String, Playable>,
-    effects: HashMap<String, Vec<Effect>>,
+    // the effect chain of each playable, each effect with its id
+    effects: HashMap<String, Vec<(String, Effect)>>,
+    // the copies of a track that run through their own effects, and are summed back with it
+    auxes: HashMap<String, Aux>,
+    // the submix each playable is summed into, the effects of the submix run on the sum
+    submix_of: HashMap<String, String>,
+    // the sum of the members of each submix, for the sample which is being computed
+    submix_sums: HashMap<String, f32>,
+    // the effect parameters which are ramping
+    automations: Vec<Automation>,
     pub mix: HashMap<String, f32>,
+    // fades of the mix of a track, with the time they started
+    fades: HashMap<String, (u128, Ramp)>,
+    // target playable -> grid which gates it
+    gates: HashMap<String, String>,
+    // whether the gate of each target is currently open
+    gate_open: HashMap<String, bool>,
     pub time: u128,
+    // gain applied to the summed output of all playables
+    master_gain: f32,
+    // peak level the output is normalized to, in dBFS, None leaves it as it is
+    headroom: Option<f32>,
+    // the adaptive trim of the output while playing, renders are trimmed as a whole instead
+    auto_gain: Option<AutoGain>,
+    samples: SampleSet,
+    // samples which are resampled while playing
+    rate_mismatches: Vec<RateMismatch>,
+    // files in the samples directory which could not be loaded
+    skipped_samples: Vec<SkippedSample>,
+    // problems in the source this pipeline was built from
+    diagnostics: Vec<Diagnostic>,
     bar_length: u128,
+    // tempo of the grids, in bpm
+    tempo: f32,
+    time_signature: (u32, u32),
     sample_rate: u32,
+    // reference frequency of A4, in Hz
+    tuning: f64,
+    // the timbres the standalone chords and notes are played with, the default if they aren't here
+    timbres: HashMap<String, Timbre>,
     sink: SyncSender<f32>,
     next: Option<Box<Pipeline>>,
+    // playables which were added while playing, and join at the next bar
+    joining: Vec<(String, Playable)>,
+    transport: Transport,
+    // the level of the fade in and out of the transport changes, 1.0 is fully audible
+    fade: f32,
+    fade_ms: f32,
+    // None until the source is reloaded for the first time
+    last_reload: Option<ReloadStatus>,
+    // seed of the randomness of all grids, None seeds them from entropy
+    seed: Option<u64>,
+    // playables which are silenced while playing, they keep running in time
+    muted: HashSet<String>,
+    // the only playable (or submix) which is heard, if one is soloed
+    soloed: Option<String>,
+    // the snapshot which is recalled at the next bar
+    recalling: Option<PipelineSnapshot>,
+    // the loop of the whole pipeline, None plays on
+    looping: Option<PipelineLoop>,
+}
+
+/// A loop of the whole pipeline, whose end crossfades into its start.
+struct PipelineLoop {
+    bars: u32,
+    crossfade_ms: f32,
+    // the first samples of the loop, which its end fades into
+    head: Vec<f32>,
+}
+
+/// Whether the pipeline is playing, the output fades when this changes.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum Transport {
+    #[default]
+    Playing,
+    /// Silent, and continues from the same time when resumed.
+    Paused,
+    /// Silent, and starts from the beginning when resumed.
+    Stopped,
+}
+
+/// How the last reload of the source went.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ReloadStatus {
+    /// The new pipeline is used, and its source has no problems.
+    Clean,
+    /// The new pipeline is used, but this many problems were found in its source.
+    Diagnostics(usize),
+    /// The source could not be parsed or built, the previous pipeline keeps playing.
+    Failed,
+}
+
+/// How much to render offline, in time or in musical units.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RenderLength {
+    Seconds(f32),
+    Bars(u32),
+    /// Loops of the longest grid.
+    Loops(u32),
+}
+
+/// Oversampling with which the true peak of a render is measured.
+const TRUE_PEAK_OVERSAMPLING: usize = 4;
+
+/// Levels of a render, to find out whether it clipped.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RenderStats {
+    /// The largest absolute sample.
+    pub peak: f32,
+    /// The number of samples at or beyond full scale, which clip when written as integers.
+    pub clipped: usize,
+    /// The peak between the samples too, measured with oversampling.
+    pub true_peak: f32,
+}
+
+impl RenderStats {
+    pub fn new(samples: &[f32]) -> Self {
+        Self {
+            peak: samples.iter().fold(0.0, |peak, s| s.abs().max(peak)),
+            clipped: samples.iter().filter(|s| s.abs() >= 1.0).count(),
+            true_peak: true_peak(samples, TRUE_PEAK_OVERSAMPLING),
+        }
+    }
+
+    /// Whether the render clipped, or would clip once it is reconstructed.
+    pub fn clips(&self) -> bool {
+        self.clipped > 0 || self.true_peak > 1.0
+    }
+}
+
+/// The mix levels, effect parameters and tempo of a pipeline, to switch back to while playing.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PipelineSnapshot {
+    mix: HashMap<String, f32>,
+    // the parameters of the effects of each track, by effect id
+    params: HashMap<String, Vec<(String, &'static str, f32)>>,
+    tempo: f32,
+}
+
+/// Which of the playables with the same name is kept.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum Duplicates {
+    /// The last one replaces the earlier ones.
+    #[default]
+    KeepLast,
+    KeepFirst,
 }
 
 pub struct PipelineConfig {
     pub samples_dir: String,
+    pub master_gain: f32,
+    /// Maximum number of coefficients of the filters
+    pub fir_max_length: usize,
+    /// Largest gain (and mix) a source can set, larger values are clamped
+    pub max_gain: f32,
+    /// Length of the fade when pausing, stopping or resuming, in milliseconds
+    pub fade_ms: f32,
+    /// Which playable is kept when several have the same name
+    pub duplicates: Duplicates,
+    /// Peak level the output is normalized to, in dBFS (off if None)
+    pub headroom: Option<f32>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            samples_dir: "samples".to_string(),
+            master_gain: 1.0,
+            fir_max_length: MAX_FIR_LENGTH,
+            max_gain: MAX_GAIN,
+            fade_ms: DEFAULT_FADE_MS,
+            duplicates: Duplicates::default(),
+            headroom: None,
+        }
+    }
 }
 
-fn get_samples(config: &PipelineConfig) -> HashMap<String, Arc<Sample>> {
+/// Load all samples in the samples directory, along with the files which could not be loaded.
+fn get_samples(config: &PipelineConfig) -> (SampleSet, Vec<SkippedSample>) {
     let mut samples = HashMap::new();
+    let mut skipped = Vec::new();
 
     let Ok(paths) = fs::read_dir(config.samples_dir.clone()) else {
         log::info!("No samples directory found, skipping sample loading");
-        return samples;
+        return (SampleSet::new(samples), skipped);
     };
 
     for path in paths {
@@ -52,15 +279,33 @@ fn get_samples(config: &PipelineConfig) -> HashMap<String, Arc<Sample>> {
         let name = path.path();
         let name = name.file_stem().unwrap().to_str().unwrap();
 
-        let Some(sample) = Sample::try_new(&path.path()) else {
-            continue;
+        let sample = match Sample::try_new(&path.path()) {
+            Ok(sample) => sample,
+            Err(reason) => {
+                let skip = SkippedSample {
+                    file: path.path(),
+                    reason,
+                };
+                log::warn!("{}", skip);
+                skipped.push(skip);
+                continue;
+            }
         };
         let sample = Arc::new(sample);
 
         samples.insert(name.to_string(), sample);
     }
 
-    samples
+    log::info!(
+        "Loaded {} samples, skipped {} files",
+        samples.len(),
+        skipped.len()
+    );
+
+    // sorted, as the directory order differs between platforms
+    skipped.sort_by(|a: &SkippedSample, b| a.file.cmp(&b.file));
+
+    (SampleSet::new(samples), skipped)
 }
 
 fn rescale_mix(mix: &mut HashMap<String, f32>) {
@@ -71,8 +316,120 @@ fn rescale_mix(mix: &mut HashMap<String, f32>) {
     }
 }
 
+/// The seed of the grid called `name`, so grids with the same pattern don't follow the same rolls.
+fn grid_seed(seed: u64, name: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (seed, name).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Run `input` through the effect chain of `target`, and sum it with its aux if it has one.
+fn process_effects(
+    effects: &mut HashMap<String, Vec<(String, Effect)>>,
+    auxes: &mut HashMap<String, Aux>,
+    target: &str,
+    input: f32,
+) -> f32 {
+    let mut output = input;
+    if let Some(effects) = effects.get_mut(target) {
+        for (_, effect) in effects {
+            output = effect.process(output);
+        }
+    }
+
+    match auxes.get_mut(target) {
+        Some(aux) => aux.process(output),
+        None => output,
+    }
+}
+
+/// Add an effect to the end of the chain of `target`.
+///
+/// The effect is identified by the setter which created it, if the chain already contains an
+/// effect with that id, its position in the chain is appended (e.g. `gain2`).
+fn push_effect(
+    effects: &mut HashMap<String, Vec<(String, Effect)>>,
+    target: &str,
+    id: &str,
+    effect: Effect,
+) {
+    // add the effect to the list of effects (or create new list if none exists)
+    let chain = effects.entry(target.to_string()).or_default();
+
+    let id = if chain.iter().any(|(other, _)| other == id) {
+        format!("{}{}", id, chain.len())
+    } else {
+        id.to_string()
+    };
+
+    chain.push((id, effect));
+}
+
+/// Collect the syntax errors below `node`, only descending into the nodes which contain one.
+fn syntax_errors(node: &tree_sitter::Node, diagnostics: &mut Vec<Diagnostic>) {
+    if node.is_error() {
+        diagnostics.push(Diagnostic::new(node, "Syntax error"));
+        return;
+    }
+    if node.is_missing() {
+        diagnostics.push(Diagnostic::new(node, format!("Missing {}", node.kind())));
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.has_error() {
+            syntax_errors(&child, diagnostics);
+        }
+    }
+}
+
+/// Limit a gain from the source to `max_gain`, so a typo like `1e30` can't blow up the output.
+fn clamp_gain(
+    node: &tree_sitter::Node,
+    value: f32,
+    max_gain: f32,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> f32 {
+    if value.abs() <= max_gain {
+        return value;
+    }
+
+    let clamped = if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(-max_gain, max_gain)
+    };
+    diagnostics.push(Diagnostic::new(
+        node,
+        format!("Gain {} is clamped to {}", value, clamped),
+    ));
+
+    clamped
+}
+
+/// Parse the tempo (in beats per minute) and the time signature of a `tempo` directive.
+fn parse_tempo(bpm: &str, count: &str, note: &str) -> Result<(f32, (u32, u32)), String> {
+    let bpm: f32 = match bpm.parse() {
+        Ok(bpm) if bpm > 0.0 && f32::is_finite(bpm) => bpm,
+        _ => return Err(format!("Invalid tempo: {}", bpm)),
+    };
+
+    match (count.parse::<u32>(), note.parse::<u32>()) {
+        (Ok(count), Ok(note)) if count > 0 && note > 0 => Ok((bpm, (count, note))),
+        _ => Err(format!(
+            "Invalid time signature: {}/{}, both need to be positive integers",
+            count, note
+        )),
+    }
+}
+
+/// The length of a bar in samples, the tempo counts the notes of the time signature (e.g. eighth
+/// notes in 6/8).
+///
+/// A tempo so fast that a bar is shorter than a sample gives 0.
 fn samples_per_bar(tempo: f32, time_signature: (u32, u32), sample_rate: u32) -> u32 {
-    60 * sample_rate * time_signature.0 / tempo as u32
+    (60.0 * sample_rate as f64 * time_signature.0 as f64 / tempo as f64) as u32
 }
 
 impl Pipeline {
@@ -83,22 +440,51 @@ impl Pipeline {
     ) -> Result<(Self, Receiver<f32>), Box<dyn Error>> {
         // initialize playables and effects
         let mut playables: HashMap<String, Playable> = HashMap::new();
-        let mut effects: HashMap<String, Vec<Effect>> = HashMap::new();
+        let mut effects: HashMap<String, Vec<(String, Effect)>> = HashMap::new();
+        let mut gates: HashMap<String, String> = HashMap::new();
+        let mut auxes: HashMap<String, Aux> = HashMap::new();
+        let mut submix_of: HashMap<String, String> = HashMap::new();
+        let mut submix_sums: HashMap<String, f32> = HashMap::new();
+        // the project wide groove, and the grids which opted out of it
+        let mut groove = None;
+        let mut straight: Vec<String> = Vec::new();
+        // the fades of the mix levels, in bars as the tempo can still change
+        let mut fades: Vec<(String, (f32, f32), f32, Curve)> = Vec::new();
+        // the semitones to shift playables by, once the maps put their chords in the grids
+        let mut transposes: Vec<(String, i8)> = Vec::new();
+        let mut diagnostics = Vec::new();
 
         let sample_rate = 48000;
 
-        let mut bar_length = samples_per_bar(120.0, (4, 4), sample_rate);
+        let mut tempo = 120.0;
+        let mut time_signature = (4, 4);
+        let mut bar_length = samples_per_bar(tempo, time_signature, sample_rate);
+        let mut tuning = A4;
+        let mut timbres: HashMap<String, Timbre> = HashMap::new();
 
         // NOTE: should probably only load those samples that haven't been loaded yet...
         //       because this function runs every time the declaration file changes
 
-        let mut samples = HashMap::new();
+        let mut samples = SampleSet::new(HashMap::new());
+        let mut skipped_samples = Vec::new();
+        let mut master_gain = 1.0;
+        let mut fir_max_length = MAX_FIR_LENGTH;
+        let mut max_gain = MAX_GAIN;
+        let mut fade_ms = DEFAULT_FADE_MS;
+        let mut duplicates = Duplicates::default();
+        let mut headroom = None;
 
         if let Some(config) = config {
-            samples = get_samples(config);
+            (samples, skipped_samples) = get_samples(config);
+            master_gain = config.master_gain;
+            fir_max_length = config.fir_max_length;
+            max_gain = config.max_gain;
+            fade_ms = config.fade_ms;
+            duplicates = config.duplicates;
+            headroom = config.headroom;
         }
 
-        let samples = SampleSet { samples };
+        syntax_errors(&tree.root_node(), &mut diagnostics);
 
         let mut cursor = tree.root_node().walk();
         for node in tree.root_node().children(&mut cursor) {
@@ -109,15 +495,134 @@ impl Pipeline {
                 let playable = match node.kind() {
                     "grid" => {
                         let grid = Grid::from_node(&node, source).unwrap();
-                        Playable::Grid(grid)
+                        diagnostics.extend(grid.failed_tokens().cloned());
+                        diagnostics.extend(grid.problems().iter().cloned());
+                        Some(Playable::Grid(grid))
                     }
+                    "standalone_chord" => node.child_by_field_name("chord").and_then(|chord| {
+                        let parsed = Chord::from_node(&chord, source)?;
+                        diagnostics.extend(
+                            parsed
+                                .problems()
+                                .into_iter()
+                                .map(|problem| Diagnostic::new(&chord, problem)),
+                        );
+                        Some(Playable::Chord(parsed))
+                    }),
+                    "standalone_note" => node
+                        .child_by_field_name("note")
+                        .and_then(|note| Note::from_node(&note, source))
+                        .map(Playable::Note),
                     _ => panic!("Unknown playable"),
                 };
+                let Some(playable) = playable else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Malformed {}", node.kind().replace('_', " ")),
+                    ));
+                    continue;
+                };
+
+                if playables.contains_key(name) {
+                    let kept = match duplicates {
+                        Duplicates::KeepLast => "the earlier one is replaced",
+                        Duplicates::KeepFirst => "this one is ignored",
+                    };
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Duplicate playable {}, {}", name, kept),
+                    ));
+                    if duplicates == Duplicates::KeepFirst {
+                        continue;
+                    }
+                }
 
                 playables.insert(name.to_string(), playable);
             }
         }
 
+        // submixes have to be known before their effects are set
+        let mut cursor = tree.root_node().walk();
+        for node in tree.root_node().children(&mut cursor) {
+            if node.kind() == "submix" {
+                let name = node.child_by_field_name("name").unwrap();
+                let name = name.utf8_text(source.as_bytes()).unwrap();
+                if playables.contains_key(name) {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Submix {} has the name of a playable", name),
+                    ));
+                    continue;
+                }
+
+                let mut walk = node.walk();
+                for member in node.children_by_field_name("member", &mut walk) {
+                    let member = member.utf8_text(source.as_bytes()).unwrap();
+                    if !playables.contains_key(member) {
+                        diagnostics.push(Diagnostic::new(
+                            &node,
+                            format!("Unknown playable: {}", member),
+                        ));
+                    } else if let Some(other) = submix_of.get(member) {
+                        diagnostics.push(Diagnostic::new(
+                            &node,
+                            format!("{} is already in submix {}", member, other),
+                        ));
+                    } else {
+                        submix_of.insert(member.to_string(), name.to_string());
+                    }
+                }
+                submix_sums.insert(name.to_string(), 0.0);
+            }
+        }
+
+        // slices and the speeds of samples have to be known before the maps refer to them
+        let mut cursor = tree.root_node().walk();
+        for node in tree.root_node().children(&mut cursor) {
+            if node.kind() == "slice" {
+                let text = |field| {
+                    node.child_by_field_name(field)
+                        .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                };
+                let (Some(name), Some(sample), Some(start), Some(end)) =
+                    (text("name"), text("sample"), text("start"), text("end"))
+                else {
+                    diagnostics.push(Diagnostic::new(&node, "Incomplete slice"));
+                    continue;
+                };
+
+                let (Ok(start), Ok(end)) = (start.parse(), end.parse()) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Invalid slice frames: {} {}", start, end),
+                    ));
+                    continue;
+                };
+
+                let slice = Slice {
+                    sample: sample.to_string(),
+                    start,
+                    end,
+                };
+                samples.slices.insert(name.to_string(), slice);
+            } else if node.kind() == "speed" {
+                // the speed statement of a grid sets its note length instead
+                let target = node.child_by_field_name("name").unwrap();
+                let target = target.utf8_text(source.as_bytes()).unwrap();
+                if playables.contains_key(target) || !samples.contains(target) {
+                    continue;
+                }
+
+                let speed = node.child(2).unwrap();
+                let speed = speed.utf8_text(source.as_bytes()).unwrap();
+                let Some(speed) = parse_speed(speed) else {
+                    diagnostics.push(Diagnostic::new(&node, format!("Invalid speed: {}", speed)));
+                    continue;
+                };
+                samples.speeds.insert(target.to_string(), speed);
+            }
+        }
+
         let mut mix = playables
             .keys()
             .map(|i| (i.to_string(), 1.0))
@@ -129,10 +634,17 @@ impl Pipeline {
                 let target = node.child_by_field_name("name").unwrap();
                 let target = target.utf8_text(source.as_bytes()).unwrap();
 
-                let playable = playables.get_mut(target).unwrap();
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
 
-                match playable {
-                    Playable::Grid(g) => g.map_from_node(&node, source, &samples),
+                match playable.as_grid_mut() {
+                    Some(g) => diagnostics.extend(g.map_from_node(&node, source, &samples)),
+                    None => diagnostics.push(not_a_grid(&node, target)),
                 }
             } else if node.kind() == "tempo" {
                 let bpm = node.child_by_field_name("bpm").unwrap();
@@ -144,29 +656,68 @@ impl Pipeline {
                 let note = node.child_by_field_name("note").unwrap();
                 let note = note.utf8_text(source.as_bytes()).unwrap();
 
-                bar_length = samples_per_bar(
-                    bpm.parse().unwrap(),
-                    (count.parse().unwrap(), note.parse().unwrap()),
-                    sample_rate,
-                );
+                let (bpm, signature) = match parse_tempo(bpm, count, note) {
+                    Ok(tempo) => tempo,
+                    Err(err) => {
+                        diagnostics.push(Diagnostic::new(&node, err));
+                        continue;
+                    }
+                };
+
+                let bar = samples_per_bar(bpm, signature, sample_rate);
+                if bar == 0 {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!(
+                            "Tempo {} is too fast, a bar would be shorter than a sample",
+                            bpm
+                        ),
+                    ));
+                    continue;
+                }
+
+                tempo = bpm;
+                time_signature = signature;
+                bar_length = bar;
 
                 // set this information in all grids
                 playables
-                    .iter_mut()
-                    .filter(|x| match x.1 {
-                        Playable::Grid(_) => true,
-                    })
-                    .for_each(|(_, x)| match x {
-                        Playable::Grid(g) => g.set_tempo_and_time(
-                            bpm.parse().unwrap(),
-                            (count.parse().unwrap(), note.parse().unwrap()),
-                        ),
-                    });
+                    .values_mut()
+                    .filter_map(Playable::as_grid_mut)
+                    .for_each(|g| g.set_tempo_and_time(bpm, signature));
+            } else if node.kind() == "tune" {
+                let value = node.child_by_field_name("value").unwrap();
+                let value = value.utf8_text(source.as_bytes()).unwrap();
+
+                match value.parse::<f64>() {
+                    Ok(a4) if a4.is_finite() && a4 > 0.0 => tuning = a4,
+                    _ => {
+                        diagnostics
+                            .push(Diagnostic::new(&node, format!("Invalid tuning: {}", value)));
+                        continue;
+                    }
+                }
+
+                // tune the notes and chords of all grids
+                playables
+                    .values_mut()
+                    .filter_map(Playable::as_grid_mut)
+                    .for_each(|g| g.set_tuning(tuning));
             } else if node.kind() == "speed" {
                 let target = node.child_by_field_name("name").unwrap();
                 let target = target.utf8_text(source.as_bytes()).unwrap();
 
-                let playable = playables.get_mut(target).unwrap();
+                // the speed of a sample was set, or reported, before the maps
+                if samples.contains(target) && !playables.contains_key(target) {
+                    continue;
+                }
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
 
                 let sign = node.child(2).unwrap();
                 let numer = sign.child_by_field_name("numer").unwrap();
@@ -177,8 +728,9 @@ impl Pipeline {
                 let numer: i16 = numer.parse().unwrap();
                 let denom: i16 = denom.parse().unwrap();
 
-                match playable {
-                    Playable::Grid(g) => g.set_note_length((numer as u32, denom as u32)),
+                match playable.as_grid_mut() {
+                    Some(g) => g.set_note_length((numer as u32, denom as u32)),
+                    None => diagnostics.push(not_a_grid(&node, target)),
                 }
             } else if node.kind() == "mix" {
                 let target = node.child_by_field_name("name").unwrap();
@@ -186,14 +738,347 @@ impl Pipeline {
 
                 let value = node.child_by_field_name("value").unwrap();
                 let value = value.utf8_text(source.as_bytes()).unwrap();
-                let value = value.parse().unwrap();
+                let Ok(value) = value.parse() else {
+                    diagnostics.push(Diagnostic::new(&node, format!("Invalid mix: {}", value)));
+                    continue;
+                };
+                let value = clamp_gain(&node, value, max_gain, &mut diagnostics);
 
                 mix.insert(target.to_string(), value);
+            } else if node.kind() == "fade" {
+                let text = |field| {
+                    node.child_by_field_name(field)
+                        .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                };
+                let (Some(target), Some(from), Some(to), Some(bars)) =
+                    (text("name"), text("from"), text("to"), text("bars"))
+                else {
+                    diagnostics.push(Diagnostic::new(&node, "Incomplete fade"));
+                    continue;
+                };
+
+                if !playables.contains_key(target) {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                }
+
+                let curve = match text("curve").map(Curve::try_from) {
+                    None => Curve::default(),
+                    Some(Ok(curve)) => curve,
+                    Some(Err(err)) => {
+                        diagnostics.push(Diagnostic::new(&node, err));
+                        continue;
+                    }
+                };
+
+                let (Ok(from), Ok(to), Ok(bars)) = (from.parse(), to.parse(), bars.parse()) else {
+                    diagnostics.push(Diagnostic::new(&node, "Invalid fade"));
+                    continue;
+                };
+                let from = clamp_gain(&node, from, max_gain, &mut diagnostics);
+                let to = clamp_gain(&node, to, max_gain, &mut diagnostics);
+
+                fades.push((target.to_string(), (from, to), bars, curve));
+            } else if node.kind() == "transpose" {
+                let target = node.child_by_field_name("name").unwrap();
+                let target = target.utf8_text(source.as_bytes()).unwrap();
+                let value = node.child_by_field_name("value").unwrap();
+                let value = value.utf8_text(source.as_bytes()).unwrap();
+
+                if !playables.contains_key(target) {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                }
+                let Ok(semitones) = value.parse() else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Invalid transposition: {}", value),
+                    ));
+                    continue;
+                };
+
+                transposes.push((target.to_string(), semitones));
+            } else if node.kind() == "wave" {
+                let target = node.child_by_field_name("name").unwrap();
+                let target = target.utf8_text(source.as_bytes()).unwrap();
+                let value = node.child_by_field_name("value").unwrap();
+                let value = value.utf8_text(source.as_bytes()).unwrap();
+
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
+                let Ok(waveform) = value.try_into() else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown waveform: {}", value),
+                    ));
+                    continue;
+                };
+
+                match playable.as_grid_mut() {
+                    Some(g) => g.set_waveform(waveform),
+                    None => timbres.entry(target.to_string()).or_default().waveform = waveform,
+                }
+            } else if node.kind() == "harmonics" {
+                let target = node.child_by_field_name("name").unwrap();
+                let target = target.utf8_text(source.as_bytes()).unwrap();
+                let value = node.child_by_field_name("value").unwrap();
+                let value = value.utf8_text(source.as_bytes()).unwrap();
+
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
+                let harmonics = match value.parse::<usize>() {
+                    Ok(harmonics) if harmonics > 0 => harmonics,
+                    _ => {
+                        diagnostics.push(Diagnostic::new(
+                            &node,
+                            format!("Invalid number of harmonics: {}", value),
+                        ));
+                        continue;
+                    }
+                };
+
+                match playable.as_grid_mut() {
+                    Some(g) => g.set_harmonics(harmonics),
+                    None => {
+                        timbres.entry(target.to_string()).or_default().harmonics = Some(harmonics)
+                    }
+                }
+            } else if node.kind() == "env" {
+                let text = |field| {
+                    node.child_by_field_name(field)
+                        .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                };
+                let Some(target) = text("name") else {
+                    diagnostics.push(Diagnostic::new(&node, "Incomplete envelope"));
+                    continue;
+                };
+                let stages: Vec<Option<f32>> = ["attack", "decay", "sustain", "release"]
+                    .into_iter()
+                    .map(|field| text(field)?.parse().ok())
+                    .collect();
+                let [Some(attack), Some(decay), Some(sustain), Some(release)] = stages[..] else {
+                    diagnostics.push(Diagnostic::new(&node, "Invalid envelope"));
+                    continue;
+                };
+
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
+
+                match playable.as_grid_mut() {
+                    Some(g) => g.set_envelope(Envelope::new(attack, decay, sustain, release)),
+                    None => diagnostics.push(not_a_grid(&node, target)),
+                }
+            } else if node.kind() == "comp" {
+                let text = |field| {
+                    node.child_by_field_name(field)
+                        .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                };
+                let Some(target) = text("name") else {
+                    diagnostics.push(Diagnostic::new(&node, "Incomplete compressor"));
+                    continue;
+                };
+                let (Some(Ok(ratio)), Some(Ok(threshold))) = (
+                    text("ratio").map(str::parse::<f32>),
+                    text("threshold").map(str::parse::<f32>),
+                ) else {
+                    diagnostics.push(Diagnostic::new(&node, "Invalid compressor"));
+                    continue;
+                };
+                // the attack and release can be left out
+                let attack = text("attack").and_then(|t| t.parse().ok());
+                let release = text("release").and_then(|t| t.parse().ok());
+
+                if !playables.contains_key(target) {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                }
+
+                let window = (sample_rate / 1000 * COMPRESSOR_WINDOW_MS).max(1) as usize;
+                let mut compressor = Compressor::new(ratio.max(1.0), threshold, window);
+                compressor.set_times(
+                    attack.unwrap_or(DEFAULT_ATTACK),
+                    release.unwrap_or(DEFAULT_RELEASE),
+                    sample_rate as f32,
+                );
+                push_effect(&mut effects, target, "comp", Effect::Compressor(compressor));
+            } else if node.kind() == "delay" {
+                let text = |field| {
+                    node.child_by_field_name(field)
+                        .and_then(|n| n.utf8_text(source.as_bytes()).ok())
+                };
+                let Some(target) = text("name") else {
+                    diagnostics.push(Diagnostic::new(&node, "Incomplete delay"));
+                    continue;
+                };
+                let (Some(Ok(ms)), Some(Ok(feedback))) = (
+                    text("time").map(str::parse::<f32>),
+                    text("feedback").map(str::parse::<f32>),
+                ) else {
+                    diagnostics.push(Diagnostic::new(&node, "Invalid delay"));
+                    continue;
+                };
+                // the mix can be left out
+                let mix = text("mix").and_then(|t| t.parse().ok());
+
+                if !playables.contains_key(target) {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                }
+                // echoes which are as loud as the one before them would never die out
+                if feedback >= 1.0 {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!(
+                            "Feedback {} is too high, it is lowered to {}",
+                            feedback, MAX_FEEDBACK
+                        ),
+                    ));
+                }
+
+                let delay_samples = (ms.max(0.0) * sample_rate as f32 / 1000.0) as usize;
+                let delay = Delay::new(delay_samples, feedback, mix.unwrap_or(DEFAULT_DELAY_MIX));
+                push_effect(&mut effects, target, "delay", Effect::Delay(delay));
+            } else if node.kind() == "samplerate" {
+                let target = node.child_by_field_name("name").unwrap();
+                let target = target.utf8_text(source.as_bytes()).unwrap();
+
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
+
+                let value = node.child_by_field_name("value").unwrap();
+                let value = value.utf8_text(source.as_bytes()).unwrap();
+                let rate = match value.parse::<u32>() {
+                    Ok(rate) if rate > 0 => rate,
+                    _ => {
+                        diagnostics.push(Diagnostic::new(
+                            &node,
+                            format!("Invalid sample rate: {}", value),
+                        ));
+                        continue;
+                    }
+                };
+
+                match playable.as_grid_mut() {
+                    Some(g) => g.set_hold_rate(rate),
+                    None => diagnostics.push(not_a_grid(&node, target)),
+                }
+            } else if node.kind() == "reverse" {
+                let target = node.child_by_field_name("name").unwrap();
+                let target = target.utf8_text(source.as_bytes()).unwrap();
+
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
+
+                match playable.as_grid_mut() {
+                    Some(g) => g.set_reverse(true),
+                    None => diagnostics.push(not_a_grid(&node, target)),
+                }
+            } else if node.kind() == "loop" {
+                let target = node.child_by_field_name("name").unwrap();
+                let target = target.utf8_text(source.as_bytes()).unwrap();
+
+                let Some(playable) = playables.get_mut(target) else {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                };
+
+                // `true` loops the whole sample, a frame loops from that frame on
+                let value = node.child_by_field_name("value").unwrap();
+                let value = value.utf8_text(source.as_bytes()).unwrap();
+                let start = match value {
+                    "true" => Some(0),
+                    "false" => None,
+                    _ => match value.parse::<usize>() {
+                        Ok(frame) => Some(frame),
+                        Err(_) => {
+                            diagnostics
+                                .push(Diagnostic::new(&node, format!("Invalid loop: {}", value)));
+                            continue;
+                        }
+                    },
+                };
+
+                match playable.as_grid_mut() {
+                    Some(g) => g.set_loop(start),
+                    None => diagnostics.push(not_a_grid(&node, target)),
+                }
+            } else if node.kind() == "groove" {
+                let mut walk = node.walk();
+                let words: Vec<&str> = node
+                    .children_by_field_name("step", &mut walk)
+                    .map(|step| step.utf8_text(source.as_bytes()).unwrap())
+                    .collect();
+                let steps: Result<Vec<GrooveStep>, _> =
+                    words.iter().map(|step| (*step).try_into()).collect();
+
+                // the steps can also name a preset, like `groove swing 16`
+                match (steps, Groove::preset(&words.join(" "))) {
+                    (Ok(steps), _) => groove = Some(Groove::new(steps)),
+                    (Err(_), Some(preset)) => groove = Some(preset),
+                    (Err(err), None) => diagnostics.push(Diagnostic::new(&node, err)),
+                }
+            } else if node.kind() == "volume" {
+                let value = node.child_by_field_name("value").unwrap();
+                let value = value.utf8_text(source.as_bytes()).unwrap();
+
+                let Ok(value) = value.parse() else {
+                    diagnostics.push(Diagnostic::new(&node, format!("Invalid volume: {}", value)));
+                    continue;
+                };
+                master_gain = clamp_gain(&node, value, max_gain, &mut diagnostics);
             } else if node.kind() == "setter" {
                 let target = node.child_by_field_name("name").unwrap();
                 let target = target.utf8_text(source.as_bytes()).unwrap();
 
-                let _playable = playables.get_mut(target).unwrap();
+                // a submix only has effects, the other properties find that it is not a grid
+                let mut playable = playables.get_mut(target);
+                if playable.is_none() && !submix_sums.contains_key(target) {
+                    diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown playable: {}", target),
+                    ));
+                    continue;
+                }
 
                 let property = node.child_by_field_name("prop").unwrap();
                 let property = property.utf8_text(source.as_bytes()).unwrap();
@@ -206,56 +1091,341 @@ impl Pipeline {
                         let value = value.parse().unwrap();
                         // TODO: implement variable sample rate (set_output_config should propagate to all effects)
                         let fir = FIRBuilder::new()
+                            .max_length(fir_max_length)
                             .low_pass(value, sample_rate as f32)
                             .build();
 
-                        // add the effect to the list of effects (or create new list if none exists)
-                        effects
-                            .entry(target.to_string())
-                            .or_default()
-                            .push(Effect::FIR(fir));
+                        push_effect(&mut effects, target, property, Effect::FIR(fir));
                     }
                     "hp_cutoff" => {
                         let value = value.parse().unwrap();
                         let fir = FIRBuilder::new()
+                            .max_length(fir_max_length)
                             .high_pass(value, sample_rate as f32)
                             .build();
 
-                        // add the effect to the list of effects (or create new list if none exists)
-                        effects
-                            .entry(target.to_string())
-                            .or_default()
-                            .push(Effect::FIR(fir));
+                        push_effect(&mut effects, target, property, Effect::FIR(fir));
+                    }
+                    "groove" if value == "off" => {
+                        straight.push(target.to_string());
+                    }
+                    "groove" => {
+                        diagnostics.push(Diagnostic::new(
+                            &node,
+                            format!("Unknown groove setting: {}", value),
+                        ));
+                    }
+                    "gate" => {
+                        gates.insert(target.to_string(), value.to_string());
+                    }
+                    "polyphony" => {
+                        let value = match value.parse::<usize>() {
+                            Ok(voices) if voices > 0 => voices,
+                            _ => {
+                                diagnostics.push(Diagnostic::new(
+                                    &node,
+                                    format!("Invalid polyphony: {}", value),
+                                ));
+                                continue;
+                            }
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_polyphony(value),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "steal" => {
+                        let Ok(policy) = value.try_into() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Unknown steal policy: {}", value),
+                            ));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_steal_policy(policy),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "boundary" => {
+                        let Ok(boundary) = value.try_into() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Unknown boundary: {}", value),
+                            ));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_boundary(boundary),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "sample_length" => {
+                        let Ok(sample_length) = value.try_into() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Unknown sample length: {}", value),
+                            ));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_sample_length(sample_length),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "probability" => {
+                        let Ok(prob_eval) = value.try_into() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Unknown probability evaluation: {}", value),
+                            ));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_prob_eval(prob_eval),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "fallback" => {
+                        let Ok(fallback) = value.try_into() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Unknown fallback: {}", value),
+                            ));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_fallback(fallback),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "humanize_drop" => {
+                        let Ok(value) = value.parse() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Invalid humanize drop: {}", value),
+                            ));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_drop(value),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "seed" => {
+                        let Ok(value) = value.parse() else {
+                            diagnostics
+                                .push(Diagnostic::new(&node, format!("Invalid seed: {}", value)));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_seed(value),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "fill_intensity" => {
+                        let fill = match value {
+                            "off" => None,
+                            value => {
+                                let Ok(fill) = value.parse() else {
+                                    diagnostics.push(Diagnostic::new(
+                                        &node,
+                                        format!("Invalid fill intensity: {}", value),
+                                    ));
+                                    continue;
+                                };
+                                Some(fill)
+                            }
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_fill(fill),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
+                    }
+                    "density" => {
+                        let Ok(value) = value.parse() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Invalid density: {}", value),
+                            ));
+                            continue;
+                        };
+                        match playable.as_mut().and_then(|p| p.as_grid_mut()) {
+                            Some(g) => g.set_density(value),
+                            None => diagnostics.push(not_a_grid(&node, target)),
+                        }
                     }
                     // other effects will come here
                     "gain" => {
-                        let value = value.parse().unwrap();
+                        let Ok(value) = value.parse() else {
+                            diagnostics
+                                .push(Diagnostic::new(&node, format!("Invalid gain: {}", value)));
+                            continue;
+                        };
+                        let value = clamp_gain(&node, value, max_gain, &mut diagnostics);
                         let gain = Gain::new(value);
 
-                        effects
-                            .entry(target.to_string())
-                            .or_default()
-                            .push(Effect::Gain(gain));
+                        push_effect(&mut effects, target, property, Effect::Gain(gain));
+                    }
+                    "reverb" => {
+                        let Ok(value) = value.parse() else {
+                            diagnostics
+                                .push(Diagnostic::new(&node, format!("Invalid reverb: {}", value)));
+                            continue;
+                        };
+                        let reverb = Reverb::new(value, sample_rate as f32);
+
+                        push_effect(&mut effects, target, property, Effect::Reverb(reverb));
+                    }
+                    "convolution" => {
+                        #[cfg(feature = "baked-reverb")]
+                        {
+                            let Ok(mix) = value.parse() else {
+                                diagnostics.push(Diagnostic::new(
+                                    &node,
+                                    format!("Invalid convolution mix: {}", value),
+                                ));
+                                continue;
+                            };
+                            push_effect(
+                                &mut effects,
+                                target,
+                                property,
+                                Effect::Convolution(Convolution::baked(mix, sample_rate as f32)),
+                            );
+                        }
+                        #[cfg(not(feature = "baked-reverb"))]
+                        diagnostics.push(Diagnostic::new(
+                            &node,
+                            format!(
+                                "No impulse response to convolve {} with, build with the \
+                                 baked-reverb feature",
+                                target
+                            ),
+                        ));
+                    }
+                    "early_reflections" | "reflection_spacing" | "tail" => {
+                        // these shape the last reverb of the chain
+                        let reverb = effects.get_mut(target).and_then(|chain| {
+                            chain
+                                .iter_mut()
+                                .rev()
+                                .find(|(_, effect)| matches!(effect, Effect::Reverb(_)))
+                        });
+                        let Some((_, reverb)) = reverb else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("{} has no reverb to set the {} of", target, property),
+                            ));
+                            continue;
+                        };
+
+                        let param = match property {
+                            "reflection_spacing" => "spacing",
+                            _ => property,
+                        };
+                        let result = match value.parse() {
+                            Ok(value) => reverb.set_param(param, value),
+                            Err(_) => Err("Invalid value"),
+                        };
+                        if let Err(err) = result {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("{} for the reverb {}: {}", err, property, value),
+                            ));
+                            continue;
+                        }
+                    }
+                    "parallel" => {
+                        let Ok(value) = value.parse() else {
+                            diagnostics.push(Diagnostic::new(
+                                &node,
+                                format!("Invalid parallel compression: {}", value),
+                            ));
+                            continue;
+                        };
+                        auxes.insert(target.to_string(), Aux::parallel_compression(value));
                     }
-                    _ => (),
+                    _ => diagnostics.push(Diagnostic::new(
+                        &node,
+                        format!("Unknown property: {}", property),
+                    )),
                 }
             }
         }
 
-        rescale_mix(&mut mix);
+        for (target, semitones) in transposes {
+            if let Some(playable) = playables.get_mut(&target) {
+                playable.transpose(semitones);
+            }
+        }
 
-        let (s_tx, rx) = mpsc::sync_channel(2048);
+        for (name, playable) in playables.iter_mut() {
+            if straight.contains(name) {
+                continue;
+            }
+            if let Some(g) = playable.as_grid_mut() {
+                g.set_groove(groove.clone());
+            }
+        }
+
+        rescale_mix(&mut mix);
+
+        let fades = fades
+            .into_iter()
+            .map(|(target, (from, to), bars, curve)| {
+                let length = (bars.max(0.0) * bar_length as f32) as u128;
+                (target, (0, Ramp::new(from, to, length, curve)))
+            })
+            .collect();
+
+        // the pre-passes find their problems first, so restore the source order
+        diagnostics.sort_by_key(|d: &Diagnostic| (d.line, d.column));
+
+        let (s_tx, rx) = mpsc::sync_channel(2048);
+
+        let gate_open = gates.keys().map(|k| (k.to_string(), true)).collect();
+
+        let rate_mismatches = samples.rate_mismatches(sample_rate);
 
         Ok((
             Self {
                 playables,
                 mix,
+                fades,
+                gates,
+                gate_open,
+                samples,
+                rate_mismatches,
+                skipped_samples,
+                diagnostics,
                 time: 0,
+                master_gain,
+                headroom,
+                auto_gain: headroom.map(|db| AutoGain::new(db, sample_rate as f32)),
                 bar_length: bar_length as u128,
+                tempo,
+                time_signature,
                 sink: s_tx,
                 effects,
+                auxes,
+                automations: Vec::new(),
                 sample_rate,
+                tuning,
+                timbres,
+                submix_of,
+                submix_sums,
                 next: None,
+                joining: Vec::new(),
+                transport: Transport::default(),
+                fade: 1.0,
+                fade_ms,
+                last_reload: None,
+                seed: None,
+                muted: HashSet::new(),
+                soloed: None,
+                recalling: None,
+                looping: None,
             },
             rx,
         ))
@@ -263,10 +1433,461 @@ impl Pipeline {
 
     pub fn set_output_config(&mut self, config: &cpal::SupportedStreamConfig) {
         self.sample_rate = config.sample_rate().0;
+        self.set_headroom(self.headroom);
+        self.rate_mismatches = self.samples.rate_mismatches(self.sample_rate);
+        for mismatch in &self.rate_mismatches {
+            log::warn!("{}", mismatch);
+        }
+    }
+
+    /// The files in the samples directory which could not be loaded, and why.
+    pub fn skipped_samples(&self) -> &[SkippedSample] {
+        &self.skipped_samples
+    }
+
+    /// The problems found in the source while building this pipeline, in source order.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The samples which don't match the output sample rate, found when they were loaded.
+    pub fn rate_mismatches(&self) -> &[RateMismatch] {
+        &self.rate_mismatches
+    }
+
+    /// Set the gain of the summed output, 1.0 leaves the mix untouched.
+    pub fn set_master_gain(&mut self, gain: f32) {
+        self.master_gain = gain;
+    }
+
+    /// Normalize the peaks of the output to `headroom` dBFS, None turns the normalization off.
+    ///
+    /// While playing, the trim adapts to the level of the output. A render is trimmed as a whole.
+    pub fn set_headroom(&mut self, headroom: Option<f32>) {
+        self.headroom = headroom;
+        self.auto_gain = headroom.map(|db| AutoGain::new(db, self.sample_rate as f32));
+    }
+
+    /// Only let `target` sound on the steps where the `gate` grid has a hit.
+    pub fn set_gate(&mut self, target: &str, gate: &str) {
+        self.gates.insert(target.to_string(), gate.to_string());
+        self.gate_open.insert(target.to_string(), true);
+    }
+
+    /// Reload a sample file which changed on disk.
+    ///
+    /// New hits use the new data, while the voices that are sounding finish with the old data.
+    pub fn reload_sample(&mut self, file: &Path) -> bool {
+        let Some(sample) = self.samples.reload(file) else {
+            return false;
+        };
+
+        for g in self
+            .playables
+            .values_mut()
+            .filter_map(Playable::as_grid_mut)
+        {
+            g.swap_sample(&sample);
+        }
+
+        true
+    }
+
+    /// The number of samples the given render length spans.
+    pub fn render_len(&self, length: RenderLength) -> usize {
+        match length {
+            RenderLength::Seconds(seconds) => (seconds * self.sample_rate as f32) as usize,
+            RenderLength::Bars(bars) => bars as usize * self.bar_length as usize,
+            RenderLength::Loops(loops) => loops as usize * self.longest_loop() as usize,
+        }
+    }
+
+    /// The length of one pass through the longest grid in samples, 0 without grids.
+    pub fn longest_loop(&self) -> u128 {
+        self.playables
+            .values()
+            .filter_map(|playable| match playable {
+                Playable::Grid(g) => Some(g.loop_duration(self.sample_rate)),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Render the pipeline offline, instead of sending the samples to the audio engine.
+    ///
+    /// With a headroom, the whole render is measured first and then trimmed by a static gain.
+    pub fn render(&mut self, length: RenderLength) -> Vec<f32> {
+        let auto_gain = self.auto_gain.take();
+        let mut samples = self.tick(self.render_len(length));
+        self.auto_gain = auto_gain;
+
+        if let Some(headroom) = self.headroom {
+            let trim = normalize_trim(&samples, headroom);
+            samples.iter_mut().for_each(|sample| *sample *= trim);
+        }
+
+        samples
+    }
+
+    /// Advance the pipeline by `n` samples and return them, without the audio engine.
+    ///
+    /// After [`Pipeline::set_seed`], the same source always gives the same samples.
+    pub fn tick(&mut self, n: usize) -> Vec<f32> {
+        (0..n).map(|_| self.next_sample()).collect()
+    }
+
+    /// Render `n` samples of only the playable (or submix) `name`, after its effects, e.g. to
+    /// hear what is wrong with one track. The other playables and the master are left out.
+    ///
+    /// A name without a playable renders silence.
+    pub fn render_playable(&mut self, name: &str, n: usize) -> Vec<f32> {
+        (0..n).map(|_| self.next_sample_of(Some(name))).collect()
+    }
+
+    /// Seed the randomness of all grids, also of those which are added or reloaded later.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        for (name, playable) in self.playables.iter_mut() {
+            if let Some(g) = playable.as_grid_mut() {
+                g.set_seed(grid_seed(seed, name));
+            }
+        }
+    }
+
+    /// Continue playing from the given time, e.g. to loop or scrub a section.
+    pub fn seek(&mut self, time: u128) {
+        self.time = time;
+
+        for g in self
+            .playables
+            .values_mut()
+            .filter_map(Playable::as_grid_mut)
+        {
+            g.seek(time, self.sample_rate);
+        }
+    }
+
+    /// Change a parameter of the effect with the given id in the chain of `track`, while playing.
+    pub fn effect_param(
+        &mut self,
+        track: &str,
+        id: &str,
+        param: &str,
+        value: f32,
+    ) -> Result<(), &'static str> {
+        let chain = self.effects.get_mut(track).ok_or("Unknown track")?;
+        let (_, effect) = chain
+            .iter_mut()
+            .find(|(effect_id, _)| effect_id == id)
+            .ok_or("Unknown effect")?;
+
+        effect.set_param(param, value)
+    }
+
+    /// Ramp a parameter of the effect with the given id in the chain of `track`, starting now.
+    ///
+    /// The ramp lasts the given number of bars, so it follows the tempo.
+    pub fn automate(
+        &mut self,
+        track: &str,
+        id: &str,
+        param: &str,
+        (from, to): (f32, f32),
+        bars: f32,
+        curve: Curve,
+    ) -> Result<(), &'static str> {
+        // jump to the start value, which also checks that the parameter exists
+        self.effect_param(track, id, param, from)?;
+
+        let length = (bars.max(0.0) * self.bar_length as f32) as u128;
+        self.automations.push(Automation {
+            track: track.to_string(),
+            effect: id.to_string(),
+            param: param.to_string(),
+            ramp: Ramp::new(from, to, length, curve),
+        });
+
+        Ok(())
+    }
+
+    /// Advance all ramps by one sample, the finished ones are removed.
+    fn apply_automations(&mut self) {
+        let effects = &mut self.effects;
+        self.automations.retain_mut(|automation| {
+            let value = automation.ramp.next_value();
+            let effect = effects.get_mut(&automation.track).and_then(|chain| {
+                chain
+                    .iter_mut()
+                    .find(|(id, _)| *id == automation.effect)
+                    .map(|(_, effect)| effect)
+            });
+
+            // the effect can be gone after an update of the pipeline
+            match effect {
+                Some(effect) => {
+                    effect.set_param(&automation.param, value).is_ok()
+                        && !automation.ramp.is_finished()
+                }
+                None => false,
+            }
+        });
+    }
+
+    /// The mix level of `track` at the current time, including its fade.
+    pub fn mix_level(&self, track: &str) -> Option<f32> {
+        let mix = self.mix.get(track)?;
+        match self.fades.get(track) {
+            Some((start, fade)) => Some(mix * fade.value_at(self.time.saturating_sub(*start))),
+            None => Some(*mix),
+        }
+    }
+
+    /// Fade out and hold the current time, until resumed.
+    pub fn pause(&mut self) {
+        self.transport = Transport::Paused;
+    }
+
+    /// Fade out, and start from the beginning when resumed.
+    pub fn stop(&mut self) {
+        self.transport = Transport::Stopped;
+    }
+
+    /// Fade back in after a pause or stop.
+    pub fn resume(&mut self) {
+        self.transport = Transport::Playing;
+    }
+
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Change the tempo of all grids while playing, from the same position in the bar. What is
+    /// sounding plays on, and the fades and ramps keep their length in bars.
+    ///
+    /// The source sets the tempo again when it is reloaded.
+    pub fn set_tempo(&mut self, tempo: f32) {
+        if !(tempo > 0.0 && tempo.is_finite()) {
+            return;
+        }
+        let bar_length = samples_per_bar(tempo, self.time_signature, self.sample_rate) as u128;
+        if bar_length == 0 {
+            return;
+        }
+
+        let ratio = bar_length as f64 / self.bar_length as f64;
+        let time = (self.time as f64 * ratio) as u128;
+        for g in self
+            .playables
+            .values_mut()
+            .filter_map(Playable::as_grid_mut)
+        {
+            g.set_tempo(tempo);
+            g.shift(self.time, time);
+        }
+        for (start, fade) in self.fades.values_mut() {
+            *start = (*start as f64 * ratio) as u128;
+            fade.rescale(ratio);
+        }
+        for automation in &mut self.automations {
+            automation.ramp.rescale(ratio);
+        }
+
+        self.bar_length = bar_length;
+        self.tempo = tempo;
+        self.time = time;
+    }
+
+    /// The tempo of the grids, in bpm.
+    pub fn tempo(&self) -> f32 {
+        self.tempo
+    }
+
+    /// Mute `name` (a playable or submix) if it is heard, or unmute it. Returns whether it is
+    /// muted now, None if there is no such playable or submix.
+    pub fn toggle_mute(&mut self, name: &str) -> Option<bool> {
+        if !self.playables.contains_key(name) && !self.submix_of.values().any(|s| s == name) {
+            return None;
+        }
+
+        if !self.muted.remove(name) {
+            self.muted.insert(name.to_string());
+        }
+        Some(self.muted.contains(name))
+    }
+
+    /// Only let `name` (a playable or submix) be heard, None lets all playables be heard again.
+    pub fn set_solo(&mut self, name: Option<&str>) {
+        self.soloed = name.map(str::to_string);
+    }
+
+    /// Capture the mix levels, effect parameters and tempo, to recall them later.
+    pub fn snapshot(&self) -> PipelineSnapshot {
+        let params = self
+            .effects
+            .iter()
+            .map(|(track, chain)| {
+                let params = chain
+                    .iter()
+                    .flat_map(|(id, effect)| {
+                        effect
+                            .params()
+                            .into_iter()
+                            .map(|(param, value)| (id.clone(), param, value))
+                    })
+                    .collect();
+                (track.clone(), params)
+            })
+            .collect();
+
+        PipelineSnapshot {
+            mix: self.mix.clone(),
+            params,
+            tempo: self.tempo,
+        }
+    }
+
+    /// Switch back to a snapshot at the start of the next bar.
+    ///
+    /// Tracks which were added since are left as they are, tracks which were removed are skipped.
+    pub fn recall(&mut self, snapshot: PipelineSnapshot) {
+        self.recalling = Some(snapshot);
+    }
+
+    /// Apply the snapshot which is being recalled, the ramps of the recalled parameters stop.
+    fn apply_snapshot(&mut self) {
+        let Some(snapshot) = self.recalling.take() else {
+            return;
+        };
+
+        for (track, level) in &snapshot.mix {
+            if let Some(mix) = self.mix.get_mut(track) {
+                *mix = *level;
+            }
+        }
+
+        for (track, params) in &snapshot.params {
+            for (id, param, value) in params {
+                self.automations.retain(|automation| {
+                    (
+                        &automation.track,
+                        &automation.effect,
+                        automation.param.as_str(),
+                    ) != (track, id, *param)
+                });
+
+                let Some((_, effect)) = self
+                    .effects
+                    .get_mut(track)
+                    .and_then(|chain| chain.iter_mut().find(|(effect_id, _)| effect_id == id))
+                else {
+                    continue;
+                };
+                // setting some parameters resets the effect, so only the changed ones are set
+                if !effect.params().contains(&(*param, *value)) {
+                    let _ = effect.set_param(param, *value);
+                }
+            }
+        }
+
+        if snapshot.tempo != self.tempo {
+            self.set_tempo(snapshot.tempo);
+        }
+    }
+
+    /// Move the fade one sample towards the level of the transport state.
+    fn step_fade(&mut self) {
+        let step = 1000.0 / (self.fade_ms.max(0.0) * self.sample_rate as f32);
+        let target = match self.transport {
+            Transport::Playing => 1.0,
+            Transport::Paused | Transport::Stopped => 0.0,
+        };
+
+        // snap to the target instead of overshooting it, or missing it by a rounding error
+        if (target - self.fade).abs() < 1.5 * step {
+            self.fade = target;
+        } else if target > self.fade {
+            self.fade += step;
+        } else {
+            self.fade -= step;
+        }
+    }
+
+    /// Add a playable while playing, it joins the mix at the start of the next bar.
+    ///
+    /// The playable gets the average mix level, and the levels are normalized again. A grid keeps
+    /// its own tempo, set it before adding the grid.
+    pub fn add_playable(&mut self, name: &str, playable: Playable) {
+        self.joining.retain(|(joining, _)| joining != name);
+        self.joining.push((name.to_string(), playable));
+    }
+
+    /// Remove a playable while playing, along with its effects and mix level. Returns whether it
+    /// was there.
+    pub fn remove_playable(&mut self, name: &str) -> bool {
+        let joining = self.joining.len();
+        self.joining.retain(|(joining, _)| joining != name);
+
+        let removed = self.playables.remove(name).is_some();
+        self.effects.remove(name);
+        self.auxes.remove(name);
+        self.timbres.remove(name);
+        self.submix_of.remove(name);
+        self.fades.remove(name);
+        self.gates.remove(name);
+        self.gate_open.remove(name);
+        self.automations
+            .retain(|automation| automation.track != name);
+        if self.mix.remove(name).is_some() && !self.mix.is_empty() {
+            rescale_mix(&mut self.mix);
+        }
+
+        removed || self.joining.len() != joining
+    }
+
+    /// Let the added playables join, in step with the time of the pipeline.
+    fn join_playables(&mut self) {
+        for (name, mut playable) in std::mem::take(&mut self.joining) {
+            if let Some(g) = playable.as_grid_mut() {
+                g.seek(self.time, self.sample_rate);
+                if let Some(seed) = self.seed {
+                    g.set_seed(grid_seed(seed, &name));
+                }
+            }
+
+            let level = match self.mix.len() {
+                0 => 1.0,
+                n => self.mix.values().sum::<f32>() / n as f32,
+            };
+            self.mix.insert(name.clone(), level);
+            self.playables.insert(name, playable);
+        }
+        rescale_mix(&mut self.mix);
     }
 
-    pub fn update(&mut self, other: Pipeline) {
+    /// Switch to a pipeline built from reloaded source, at the next sample.
+    pub fn update(&mut self, other: Pipeline) -> ReloadStatus {
+        let status = match other.diagnostics.len() {
+            0 => ReloadStatus::Clean,
+            n => ReloadStatus::Diagnostics(n),
+        };
         self.next = Some(Box::new(other));
+        self.last_reload = Some(status);
+        status
+    }
+
+    /// Record that the reloaded source could not be turned into a pipeline.
+    pub fn reload_failed(&mut self) {
+        self.last_reload = Some(ReloadStatus::Failed);
+    }
+
+    /// How the last reload went, None if the source wasn't reloaded yet.
+    pub fn last_reload(&self) -> Option<ReloadStatus> {
+        self.last_reload
     }
 
     fn set_to_new(&mut self) {
@@ -274,50 +1895,212 @@ impl Pipeline {
             self.time = 0;
             self.playables = next.playables;
             self.effects = next.effects;
+            self.auxes = next.auxes;
             self.mix = next.mix;
+            // the fades of the new source start now
+            let now = self.time;
+            self.fades = next
+                .fades
+                .into_iter()
+                .map(|(track, (_, fade))| (track, (now, fade)))
+                .collect();
+            self.gates = next.gates;
+            self.gate_open = next.gate_open;
+            self.samples = next.samples;
+            self.skipped_samples = next.skipped_samples;
+            self.master_gain = next.master_gain;
+            self.diagnostics = next.diagnostics;
+
+            // only warn about the samples which weren't there before
+            let mismatches = self.samples.rate_mismatches(self.sample_rate);
+            for mismatch in &mismatches {
+                if !self.rate_mismatches.contains(mismatch) {
+                    log::warn!("{}", mismatch);
+                }
+            }
+            self.rate_mismatches = mismatches;
             self.bar_length = next.bar_length;
+            self.tempo = next.tempo;
+            self.time_signature = next.time_signature;
+            self.tuning = next.tuning;
+            self.timbres = next.timbres;
+            self.submix_of = next.submix_of;
+            self.submix_sums = next.submix_sums;
+            self.fade_ms = next.fade_ms;
+            // the normalization keeps the gain it settled on, unless the headroom changed
+            if next.headroom != self.headroom {
+                self.headroom = next.headroom;
+                self.auto_gain = next.auto_gain;
+            }
+
+            if let Some(seed) = self.seed {
+                self.set_seed(seed);
+            }
         }
     }
 
-    pub fn send_sample(&mut self) -> Result<(), SendError<f32>> {
+    /// Loop the whole pipeline every `bars` bars, crossfading the end of the loop into its start
+    /// over `crossfade_ms` milliseconds. None plays on without looping.
+    ///
+    /// The length follows the tempo. The start of the loop is heard during the crossfade, so the
+    /// next pass continues after it, and the start is faded into as it was on the pass before.
+    pub fn set_loop(&mut self, bars: Option<u32>, crossfade_ms: f32) {
+        self.looping = bars.filter(|bars| *bars > 0).map(|bars| PipelineLoop {
+            bars,
+            crossfade_ms: crossfade_ms.max(0.0),
+            head: Vec::new(),
+        });
+    }
+
+    /// Compute the next output sample, and advance the time.
+    fn next_sample(&mut self) -> f32 {
+        let Some(looping) = &self.looping else {
+            return self.next_sample_of(None);
+        };
+        let length = looping.bars as u128 * self.bar_length;
+        let crossfade = ((looping.crossfade_ms * self.sample_rate as f32 / 1000.0) as u128)
+            .min(length / 2) as usize;
+
+        if self.time >= length {
+            self.seek(0);
+            // the start was heard in the crossfade, so it runs silently to continue after it
+            let head = (0..crossfade).map(|_| self.next_sample_of(None)).collect();
+            if let Some(looping) = &mut self.looping {
+                looping.head = head;
+            }
+        }
+
+        let time = self.time;
+        let sample = self.next_sample_of(None);
+        let Some(looping) = &mut self.looping else {
+            return sample;
+        };
+
+        // the first pass records its start, the later ones run it silently at the wrap
+        if time as usize == looping.head.len() && looping.head.len() < crossfade && self.time > time
+        {
+            looping.head.push(sample);
+        }
+
+        let fade_start = length - crossfade as u128;
+        match looping.head.get(time.saturating_sub(fade_start) as usize) {
+            Some(head) if time >= fade_start => {
+                let t = ((time - fade_start) as f32 + 0.5) / crossfade as f32;
+                sample * (1.0 - t) + head * t
+            }
+            _ => sample,
+        }
+    }
+
+    /// Compute the next sample of only `solo` (a playable or submix) after its effects, or of the
+    /// whole output if it is None, and advance the time.
+    ///
+    /// The other playables don't advance, and a solo isn't scaled by the master.
+    fn next_sample_of(&mut self, solo: Option<&str>) -> f32 {
+        // once faded out, the time stands still
+        if self.transport != Transport::Playing && self.fade == 0.0 {
+            if self.transport == Transport::Stopped && self.time != 0 {
+                self.seek(0);
+            }
+            return 0.0;
+        }
+
         // check if we need to update the pipeline
         if self.time % (4 * self.bar_length) == 0 {
             self.set_to_new();
         }
+        if !self.joining.is_empty() && self.time.is_multiple_of(self.bar_length) {
+            self.join_playables();
+        }
+        if self.recalling.is_some() && self.time.is_multiple_of(self.bar_length) {
+            self.apply_snapshot();
+        }
+
+        self.apply_automations();
+
+        // evaluate the gates before any playable advances
+        for (target, gate) in &self.gates {
+            let open = match self.playables.get(gate) {
+                Some(Playable::Grid(g)) => g.gate_open(self.time, self.sample_rate),
+                // a chord or note is always sounding
+                _ => true,
+            };
+            if let Some(state) = self.gate_open.get_mut(target) {
+                *state = open;
+            }
+        }
 
         let mut sample: f32 = 0.0;
         for playable in self.playables.iter_mut() {
-            let dry = match playable.1 {
-                Playable::Grid(g) => {
-                    let s = g.get_sample(self.time, self.sample_rate);
-                    s * self.mix[playable.0]
+            let submix = self.submix_of.get(playable.0);
+            if solo.is_some_and(|solo| solo != playable.0 && submix.is_none_or(|s| s != solo)) {
+                continue;
+            }
+
+            let mix = match self.fades.get(playable.0) {
+                Some((start, fade)) => {
+                    self.mix[playable.0] * fade.value_at(self.time.saturating_sub(*start))
                 }
+                None => self.mix[playable.0],
+            };
+            let timbre = self.timbres.get(playable.0).copied().unwrap_or_default();
+            let dry = playable
+                .1
+                .get_sample(self.time, self.sample_rate, self.tuning, timbre)
+                * mix;
+
+            let dry = match self.gate_open.get(playable.0) {
+                Some(false) => 0.0,
+                _ => dry,
             };
 
-            let wet = {
-                let mut output = dry;
-                match self.effects.get_mut(playable.0) {
-                    None => output,
-                    Some(effects) => {
-                        for effect in effects {
-                            output = match effect {
-                                Effect::FIR(fir) => fir.process(output),
-                                Effect::Gain(gain) => gain.process(output),
-                                // not yet implemented effects
-                                _ => output,
-                            }
-                        }
+            // muted playables and submixes, and those besides the soloed one, keep running silently
+            let silenced = self.muted.contains(playable.0)
+                || submix.is_some_and(|s| self.muted.contains(s))
+                || self
+                    .soloed
+                    .as_ref()
+                    .is_some_and(|s| s != playable.0 && submix != Some(s));
+            let dry = if silenced { 0.0 } else { dry };
+
+            let wet = process_effects(&mut self.effects, &mut self.auxes, playable.0, dry);
+
+            // a soloed member of a submix skips the effects of its submix
+            match submix.filter(|_| solo != Some(playable.0)) {
+                Some(submix) => *self.submix_sums.get_mut(submix).unwrap() += wet,
+                None => sample += wet,
+            }
+        }
 
-                        output
-                    }
-                }
-            };
+        for (submix, sum) in self.submix_sums.iter_mut() {
+            let dry = std::mem::take(sum);
+            if solo.is_some_and(|solo| solo != submix) {
+                continue;
+            }
+            sample += process_effects(&mut self.effects, &mut self.auxes, submix, dry);
+        }
+
+        if solo.is_some() {
+            self.time += 1;
+            return sample;
+        }
 
-            sample += wet;
+        sample *= self.master_gain;
+        if let Some(auto_gain) = &mut self.auto_gain {
+            sample = auto_gain.process(sample);
         }
 
+        self.step_fade();
+        sample *= self.fade;
+
         self.time += 1;
 
+        sample
+    }
+
+    pub fn send_sample(&mut self) -> Result<(), SendError<f32>> {
+        let sample = self.next_sample();
+
         let res = self.sink.send(sample);
         log::trace!(
             "pipeline, {}",
@@ -334,6 +2117,10 @@ impl Pipeline {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        chromatic::{Octave, PitchClass},
+        grid::GridToken,
+    };
     use tree_sitter::Parser;
 
     fn get_test_tree() -> (String, tree_sitter::Tree) {
@@ -349,6 +2136,18 @@ mod tests {
         (source.to_string(), tree)
     }
 
+    fn pipeline(source: &str, config: Option<&PipelineConfig>) -> (Pipeline, Vec<Diagnostic>) {
+        let mut parser = Parser::new();
+        parser
+            .set_language(tree_sitter_breaker::language())
+            .unwrap();
+        let tree = parser.parse(source, None).unwrap();
+
+        let (pipeline, _) = Pipeline::from_tree(&tree, source, config).unwrap();
+        let diagnostics = pipeline.diagnostics().to_vec();
+        (pipeline, diagnostics)
+    }
+
     #[test]
     fn named_grid() {
         let (source, tree) = get_test_tree();
@@ -365,4 +2164,692 @@ mod tests {
         );
         assert!(playables.contains_key("veryfunname"));
     }
+
+    #[test]
+    fn gated_grid() {
+        let source = include_str!("../testdata/gate_test.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        pipeline.set_gate("pad", "gate");
+
+        // one token of a 1/16 grid at 120 bpm
+        let step = 6000;
+        let output: Vec<f32> = (0..2 * step).map(|_| pipeline.next_sample()).collect();
+
+        assert!(
+            output[..step].iter().any(|s| *s != 0.0),
+            "Target is silent on the gate's hit"
+        );
+        assert!(
+            output[step..].iter().all(|s| *s == 0.0),
+            "Target is audible on the gate's rest"
+        );
+    }
+
+    #[test]
+    fn effect_param_by_id() {
+        let source = include_str!("../testdata/effect_test.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        let render = |pipeline: &mut Pipeline| -> Vec<f32> {
+            (0..1000).map(|_| pipeline.next_sample()).collect()
+        };
+
+        let before = render(&mut pipeline);
+        assert!(before.iter().any(|s| *s != 0.0), "Test grid is silent");
+
+        pipeline.effect_param("pad", "gain", "amount", 0.0).unwrap();
+        let after = render(&mut pipeline);
+
+        // the gain glides to its new amount
+        assert!(
+            after[300..].iter().all(|s| *s == 0.0),
+            "Muting the gain effect didn't change the output"
+        );
+        assert!(pipeline
+            .effect_param("pad", "reverb", "amount", 0.0)
+            .is_err());
+    }
+
+    #[test]
+    fn headroom_normalizes_render_peak() {
+        let source = include_str!("../testdata/hot_mix.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        let peak = |samples: &[f32]| samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        assert!(peak(&pipeline.render(RenderLength::Bars(1))) > 1.0);
+
+        pipeline.set_headroom(Some(-1.0));
+        pipeline.seek(0);
+        let normalized = peak(&pipeline.render(RenderLength::Bars(1)));
+        assert!(
+            (normalized - 10.0_f32.powf(-1.0 / 20.0)).abs() < 1e-4,
+            "Normalized render peaks at {}",
+            normalized
+        );
+    }
+
+    #[test]
+    fn hot_render_reports_clipping() {
+        let source = include_str!("../testdata/hot_mix.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        let stats = RenderStats::new(&pipeline.render(RenderLength::Bars(1)));
+
+        assert!(stats.clips());
+        assert!(stats.clipped > 0);
+        assert!(stats.true_peak >= stats.peak && stats.peak > 1.0);
+
+        let quiet = RenderStats::new(&[0.0, 0.5, -0.5]);
+        assert_eq!((quiet.peak, quiet.clipped), (0.5, 0));
+        assert!(!quiet.clips());
+    }
+
+    #[test]
+    fn master_gain_scales_output() {
+        let source = include_str!("../testdata/effect_test.br");
+
+        let (mut full, _) = pipeline(source, None);
+        let (mut half, _) = pipeline(source, None);
+        half.set_master_gain(0.5);
+
+        for _ in 0..1000 {
+            let (full, half) = (full.next_sample(), half.next_sample());
+
+            assert!(
+                (half - full / 2.0).abs() < 1e-6,
+                "Master gain 0.5 turned {} into {}",
+                full,
+                half
+            );
+        }
+    }
+
+    #[test]
+    fn sample_rate_mismatch() {
+        let source = include_str!("../testdata/effect_test.br");
+
+        let config = PipelineConfig {
+            samples_dir: "testdata/samples".to_string(),
+            ..Default::default()
+        };
+        let (pipeline, _) = pipeline(source, Some(&config));
+
+        let kick = pipeline
+            .rate_mismatches()
+            .iter()
+            .find(|m| m.sample == "kick")
+            .expect("No notice for the 44100 Hz kick");
+        assert_eq!((kick.sample_rate, kick.output_rate), (44100, 48000));
+    }
+
+    #[test]
+    fn skipped_corrupt_sample() {
+        let config = PipelineConfig {
+            samples_dir: "testdata/samples_skipped".to_string(),
+            ..Default::default()
+        };
+
+        let (samples, skipped) = get_samples(&config);
+
+        assert_eq!(samples.samples.len(), 1);
+        assert!(samples.samples.contains_key("good"));
+        assert_eq!(
+            skipped,
+            vec![SkippedSample {
+                file: Path::new("testdata/samples_skipped/corrupt.wav").to_path_buf(),
+                reason: crate::sampler::SkipReason::Unreadable,
+            }]
+        );
+    }
+
+    #[test]
+    fn extreme_gain_is_clamped() {
+        let source = include_str!("../testdata/clamp_test.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+
+        let lines: Vec<usize> = pipeline.diagnostics().iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![5, 7], "{:?}", pipeline.diagnostics());
+
+        let output = pipeline.render(RenderLength::Bars(1));
+        assert!(output.iter().any(|s| *s != 0.0), "Test grid is silent");
+        assert!(
+            output.iter().all(|s| s.is_finite() && s.abs() <= MAX_GAIN),
+            "Clamped gain still blows up the output"
+        );
+    }
+
+    #[test]
+    fn pause_fades_out() {
+        let source = include_str!("../testdata/effect_test.br");
+
+        let (mut reference, _) = pipeline(source, None);
+        let (mut paused, _) = pipeline(source, None);
+
+        // 5 ms at 48 kHz
+        let fade = 240;
+        let expected = reference.render(RenderLength::Seconds(0.1));
+        let start = paused.render(RenderLength::Seconds(0.05)).len();
+        paused.pause();
+        let output: Vec<f32> = (0..2 * fade).map(|_| paused.next_sample()).collect();
+
+        assert!(expected[start..start + fade].iter().any(|s| *s != 0.0));
+        for (i, sample) in output[..fade].iter().enumerate() {
+            let level = 1.0 - (i + 1) as f32 / fade as f32;
+            assert!(
+                (sample - expected[start + i] * level).abs() < 1e-3,
+                "Sample {} of the fade is {} instead of {}",
+                i,
+                sample,
+                expected[start + i] * level
+            );
+        }
+        assert!(output[fade..].iter().all(|s| *s == 0.0));
+        assert_eq!(paused.transport(), Transport::Paused);
+    }
+
+    #[test]
+    fn reloaded_fade_starts_at_reload() {
+        let (reloaded, _) = pipeline(include_str!("../testdata/fade_test.br"), None);
+        let (mut pipeline, _) = pipeline(include_str!("../testdata/pipeline_test.br"), None);
+        let bar = pipeline.bar_length as usize;
+        pipeline.tick(5 * bar);
+        pipeline.update(reloaded);
+
+        // the new source is switched to at the start of the next four bars
+        pipeline.tick(3 * bar + 1);
+        assert!(pipeline.mix_level("pad").unwrap() < 1e-3);
+        pipeline.tick(2 * bar - 1);
+        let mix = pipeline.mix_level("pad").unwrap();
+        assert!(
+            (mix - 0.5).abs() < 1e-6,
+            "Mix is {} two bars after the reload",
+            mix
+        );
+    }
+
+    #[test]
+    fn reload_takes_output_settings() {
+        let source = include_str!("../testdata/effect_test.br");
+        let config = PipelineConfig {
+            samples_dir: "testdata/samples".to_string(),
+            fade_ms: 20.0,
+            headroom: Some(-3.0),
+            ..Default::default()
+        };
+
+        let (reloaded, _) = pipeline(source, Some(&config));
+        let (mut pipeline, _) = pipeline(source, None);
+        let bar = pipeline.bar_length as usize;
+        pipeline.update(reloaded);
+        pipeline.tick(4 * bar + 1);
+
+        assert_eq!(pipeline.fade_ms, 20.0);
+        assert_eq!(pipeline.headroom, Some(-3.0));
+        assert!(pipeline.auto_gain.is_some());
+    }
+
+    #[test]
+    fn time_signature_bar_lengths() {
+        // at 120 bpm, a beat is half a second
+        for (time_signature, bar_length) in [
+            ((4, 4), 96000),
+            ((3, 4), 72000),
+            ((6, 8), 144000),
+            ((7, 8), 168000),
+            ((5, 4), 120000),
+        ] {
+            let (bpm, parsed) = parse_tempo(
+                "120",
+                &time_signature.0.to_string(),
+                &time_signature.1.to_string(),
+            )
+            .unwrap();
+
+            assert_eq!(parsed, time_signature);
+            assert_eq!(
+                samples_per_bar(bpm, parsed, 48000),
+                bar_length,
+                "Wrong bar length for {:?}",
+                time_signature
+            );
+        }
+    }
+
+    #[test]
+    fn sub_one_bpm_bar_length() {
+        // half a beat per minute, a beat lasts two minutes
+        let (bpm, time_signature) = parse_tempo("0.5", "4", "4").unwrap();
+        assert_eq!(samples_per_bar(bpm, time_signature, 48000), 4 * 120 * 48000);
+
+        // fractional tempos aren't rounded to whole bpm
+        let (bpm, time_signature) = parse_tempo("133.33", "4", "4").unwrap();
+        assert_eq!(samples_per_bar(bpm, time_signature, 48000), 86402);
+
+        let (bpm, time_signature) = parse_tempo("1e12", "4", "4").unwrap();
+        assert_eq!(samples_per_bar(bpm, time_signature, 48000), 0);
+    }
+
+    #[test]
+    fn invalid_time_signature() {
+        let source = include_str!("../testdata/time_signature_test.br");
+
+        let (pipeline, _) = pipeline(source, None);
+
+        let diagnostic = pipeline
+            .diagnostics()
+            .first()
+            .expect("No diagnostic for the invalid time signature");
+        assert_eq!(diagnostic.line, 1);
+        assert!(diagnostic.message.starts_with("Invalid time signature"));
+        // the default of 4/4 at 120 bpm stays
+        assert_eq!(pipeline.bar_length, 96000);
+        assert!(parse_tempo("120", "x", "4").is_err());
+        assert!(parse_tempo("0", "4", "4").is_err());
+    }
+
+    #[test]
+    fn mix_fade_interpolates() {
+        let source = include_str!("../testdata/fade_test.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        assert!(
+            pipeline.diagnostics().is_empty(),
+            "{:?}",
+            pipeline.diagnostics()
+        );
+
+        // the fade lasts four bars, of 96000 samples each
+        for (bar, level) in [(0, 0.0), (1, 0.25), (2, 0.5), (4, 1.0), (6, 1.0)] {
+            pipeline.seek(bar * 96000);
+            let mix = pipeline.mix_level("pad").unwrap();
+            assert!(
+                (mix - level).abs() < 1e-6,
+                "Mix is {} after {} bars, instead of {}",
+                mix,
+                bar,
+                level
+            );
+        }
+    }
+
+    #[test]
+    fn mix_fade_follows_tempo() {
+        let source = include_str!("../testdata/fade_test.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        pipeline.tick(2 * 96000);
+
+        // halfway through the fade of four bars, the bars become twice as long
+        pipeline.set_tempo(60.0);
+        let mix = pipeline.mix_level("pad").unwrap();
+        assert!((mix - 0.5).abs() < 1e-6, "Mix jumped to {}", mix);
+
+        pipeline.tick(192000);
+        let mix = pipeline.mix_level("pad").unwrap();
+        assert!(
+            (mix - 0.75).abs() < 1e-6,
+            "Mix is {} a bar after the tempo change",
+            mix
+        );
+    }
+
+    #[test]
+    fn seeded_ticks_are_identical() {
+        let source = include_str!("../testdata/seeded_test.br");
+
+        let config = PipelineConfig {
+            samples_dir: "testdata/samples".to_string(),
+            ..Default::default()
+        };
+        let seeded = || {
+            let (mut pipeline, _) = pipeline(source, Some(&config));
+            pipeline.set_seed(7);
+            pipeline
+        };
+        let mut first = seeded();
+        let mut second = seeded();
+
+        // each tick is one pass through the eight steps of the grid
+        for _ in 0..2 {
+            let block = first.tick(48000);
+            assert_eq!(block.len(), 48000);
+            assert_eq!(block, second.tick(48000));
+        }
+    }
+
+    #[test]
+    fn speed_statement_sets_sample_speed() {
+        let source = include_str!("../testdata/speed_test.br");
+
+        let config = PipelineConfig {
+            samples_dir: "testdata/samples".to_string(),
+            ..Default::default()
+        };
+        let (pipeline, _) = pipeline(source, Some(&config));
+        assert!(
+            pipeline.diagnostics().is_empty(),
+            "{:?}",
+            pipeline.diagnostics()
+        );
+
+        // the speed of the key comes on top of the speed of the sample
+        let Some(Playable::Grid(g)) = pipeline.playables.get("beat") else {
+            panic!("beat is not a grid");
+        };
+        let speeds: Vec<f32> = g.tokens[..2]
+            .iter()
+            .map(|token| match token {
+                GridToken::Hit(player) => player.speed(),
+                _ => panic!("{} is not a hit", token),
+            })
+            .collect();
+        assert_eq!(speeds, vec![0.5, 0.25]);
+    }
+
+    #[test]
+    fn submix_effects_apply_to_all_members() {
+        let source = include_str!("../testdata/submix_test.br");
+        let render = |source: &str| {
+            let (mut pipeline, diagnostics) = pipeline(source, None);
+            assert!(diagnostics.is_empty());
+            pipeline.render(RenderLength::Seconds(0.1))
+        };
+
+        let grouped = render(source);
+        // without the gain, the submix is a plain sum of its members
+        let summed = render(&source.replace("set band gain = 0.5\n", ""));
+
+        assert!(summed.iter().any(|sample| sample.abs() > 0.1));
+        for (grouped, summed) in grouped.iter().zip(&summed) {
+            assert!((grouped - 0.5 * summed).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn mute_submix() {
+        let source = include_str!("../testdata/submix_test.br");
+        let (mut pipeline, _) = pipeline(source, None);
+
+        assert_eq!(pipeline.toggle_mute("band"), Some(true));
+        assert_eq!(pipeline.toggle_mute("drums"), None);
+        assert!(pipeline.tick(4800).iter().all(|sample| *sample == 0.0));
+
+        assert_eq!(pipeline.toggle_mute("band"), Some(false));
+        assert!(pipeline.tick(4800).iter().any(|sample| sample.abs() > 0.1));
+    }
+
+    #[test]
+    fn render_playable_isolates_track() {
+        let source = include_str!("../testdata/two_tracks.br");
+
+        let mixed = pipeline(source, None).0.tick(4800);
+        let lead = pipeline(source, None).0.render_playable("lead", 4800);
+        let bass = pipeline(source, None).0.render_playable("bass", 4800);
+
+        assert!(lead.iter().any(|sample| sample.abs() > 0.1));
+        assert!(lead.iter().zip(&bass).any(|(lead, bass)| lead != bass));
+        for ((mixed, lead), bass) in mixed.iter().zip(&lead).zip(&bass) {
+            assert!((mixed - (lead + bass)).abs() < 1e-5);
+        }
+        assert!(pipeline(source, None)
+            .0
+            .render_playable("drums", 100)
+            .iter()
+            .all(|sample| *sample == 0.0));
+    }
+
+    #[test]
+    fn render_loops() {
+        let source = include_str!("../testdata/grid_test.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        let single = pipeline.render(RenderLength::Loops(1)).len();
+        let double = pipeline.render(RenderLength::Loops(2)).len();
+
+        // four steps of 6000 samples each
+        assert_eq!(single, 4 * 6000);
+        assert_eq!(double, 2 * single);
+    }
+
+    #[test]
+    fn diagnostics_in_source_order() {
+        let source = include_str!("../testdata/diagnostics_test.br");
+
+        let (_, diagnostics) = pipeline(source, None);
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).collect();
+
+        assert_eq!(lines, vec![5, 7, 11, 12, 13], "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].message, "Unknown sample: nosuchsample");
+    }
+
+    #[test]
+    fn invalid_rest_counts_are_reported() {
+        let source = include_str!("../testdata/rest_test.br");
+
+        let (pipeline, _) = pipeline(source, None);
+        let messages: Vec<&str> = pipeline
+            .diagnostics()
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "Unparsable grid token: _0",
+                "Unparsable grid token: _999999999"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_sample_speed_is_reported_once() {
+        let source = include_str!("../testdata/speed_invalid_test.br");
+
+        let config = PipelineConfig {
+            samples_dir: "testdata/samples".to_string(),
+            ..Default::default()
+        };
+        let (_, diagnostics) = pipeline(source, Some(&config));
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert!(diagnostics[0].message.starts_with("Invalid speed"));
+    }
+
+    #[test]
+    fn invalid_slice_is_reported() {
+        let source = include_str!("../testdata/slice_test.br");
+
+        // the frames don't fit, which is reported instead of failing the whole pipeline
+        let (_, diagnostics) = pipeline(source, None);
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].message.starts_with("Invalid slice frames"));
+    }
+
+    #[test]
+    fn invalid_humanize_values_are_reported() {
+        let source = include_str!("../testdata/humanize_test.br");
+
+        let (_, diagnostics) = pipeline(source, None);
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![5, 6], "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn invalid_polyphony_is_reported() {
+        let source = include_str!("../testdata/polyphony_test.br");
+
+        let (_, diagnostics) = pipeline(source, None);
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![5, 6], "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn invalid_reverb_params_are_reported() {
+        let source = include_str!("../testdata/reverb_param_test.br");
+
+        // a bad value is reported instead of failing the whole pipeline
+        let (_, diagnostics) = pipeline(source, None);
+        let lines: Vec<usize> = diagnostics.iter().map(|d| d.line).collect();
+        assert_eq!(lines, vec![6], "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn chord_problems_are_reported() {
+        let source = include_str!("../testdata/chord_problems_test.br");
+
+        // both chords are played anyway
+        let (pipeline, _) = pipeline(source, None);
+        assert!(pipeline.playables.contains_key("lead"));
+        assert!(pipeline.playables.contains_key("pad"));
+
+        let problems: Vec<(usize, &str)> = pipeline
+            .diagnostics()
+            .iter()
+            .map(|d| (d.line, d.message.as_str()))
+            .collect();
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(problems[0].0, 2);
+        assert!(problems[0].1.contains("above the highest octave"));
+        assert_eq!(
+            problems[1],
+            (5, "Conflicting tensions 9 and b9 in chord C9b9")
+        );
+    }
+
+    #[test]
+    fn duplicate_playable_is_reported() {
+        let source = include_str!("../testdata/duplicate_test.br");
+
+        let first_hit = |duplicates| {
+            let config = PipelineConfig {
+                samples_dir: "testdata/samples".to_string(),
+                duplicates,
+                ..Default::default()
+            };
+            let (mut pipeline, _) = pipeline(source, Some(&config));
+            assert_eq!(pipeline.diagnostics().len(), 1);
+            assert_eq!(pipeline.diagnostics()[0].line, 5);
+
+            let grid = pipeline
+                .playables
+                .get_mut("beat")
+                .unwrap()
+                .as_grid_mut()
+                .unwrap();
+            grid.tokens
+                .iter()
+                .position(|token| *token != GridToken::Pause)
+        };
+
+        assert_eq!(first_hit(Duplicates::KeepLast), Some(2));
+        assert_eq!(first_hit(Duplicates::KeepFirst), Some(0));
+    }
+
+    #[test]
+    fn clean_reload_is_reported() {
+        let (source, tree) = get_test_tree();
+        let (mut pipeline, _rx) = Pipeline::from_tree(&tree, &source, None).unwrap();
+        assert_eq!(pipeline.last_reload(), None);
+
+        let (reloaded, _) = Pipeline::from_tree(&tree, &source, None).unwrap();
+        assert_eq!(pipeline.update(reloaded), ReloadStatus::Clean);
+        assert_eq!(pipeline.last_reload(), Some(ReloadStatus::Clean));
+
+        pipeline.reload_failed();
+        assert_eq!(pipeline.last_reload(), Some(ReloadStatus::Failed));
+    }
+
+    #[test]
+    fn recall_restores_mix() {
+        let source = include_str!("../testdata/two_tracks.br");
+
+        let (mut pipeline, _) = pipeline(source, None);
+        let bar = pipeline.bar_length as usize;
+        let snapshot = pipeline.snapshot();
+        let levels = pipeline.mix.clone();
+
+        *pipeline.mix.get_mut("lead").unwrap() = 0.0;
+        *pipeline.mix.get_mut("bass").unwrap() = 2.0;
+        pipeline
+            .effect_param("lead", "gain", "amount", 0.1)
+            .unwrap();
+        pipeline.tick(bar / 2);
+        pipeline.recall(snapshot.clone());
+
+        // the snapshot is recalled at the start of the next bar
+        pipeline.tick(bar - bar / 2);
+        assert_eq!(pipeline.mix["lead"], 0.0);
+        pipeline.tick(1);
+        assert_eq!(pipeline.mix, levels);
+        assert_eq!(pipeline.snapshot(), snapshot);
+    }
+
+    #[test]
+    fn loop_end_crossfades_into_start() {
+        let source = include_str!("../testdata/two_tracks.br");
+
+        let (mut reference, _) = pipeline(source, None);
+        let (mut pipeline, _) = pipeline(source, None);
+        let bar = pipeline.bar_length as usize;
+        // 10 ms at 48 kHz
+        let crossfade = 480;
+        pipeline.set_loop(Some(1), 10.0);
+
+        let played = reference.tick(bar + crossfade);
+        let first = pipeline.tick(bar);
+        let second = pipeline.tick(bar);
+
+        // the end of the bar fades into its start, instead of jumping to it
+        assert_eq!(first[..bar - crossfade], played[..bar - crossfade]);
+        for i in 0..crossfade {
+            let t = (i as f32 + 0.5) / crossfade as f32;
+            let end = played[bar - crossfade + i];
+            let expected = end * (1.0 - t) + played[i] * t;
+            let s = first[bar - crossfade + i];
+            assert!(
+                (s - expected).abs() < 1e-5,
+                "Crossfade is at {} instead of {}",
+                s,
+                expected
+            );
+        }
+
+        // the next pass continues after the start which was faded into
+        for (s, expected) in second.iter().zip(&played[crossfade..bar - crossfade]) {
+            assert!((s - expected).abs() < 1e-5);
+        }
+        assert_eq!(pipeline.time as usize, 2 * crossfade);
+    }
+
+    #[test]
+    fn added_playable_joins_at_next_bar() {
+        let (source, tree) = get_test_tree();
+        let (mut pipeline, _rx) = Pipeline::from_tree(&tree, &source, None).unwrap();
+        let bar = pipeline.bar_length as usize;
+
+        // the test grid has no mapped tokens, so it is silent
+        let before = pipeline.render(RenderLength::Seconds(0.01));
+        assert!(before.iter().all(|s| *s == 0.0));
+
+        let note = Note::new(PitchClass::A, Octave::Four);
+        pipeline.add_playable("drone", Playable::Note(note));
+
+        // the rest of the bar is still silent, the note sounds from the next bar on
+        let rest = pipeline.render(RenderLength::Seconds(0.01));
+        assert!(rest.iter().all(|s| *s == 0.0));
+        let time = pipeline.time as usize;
+        let output: Vec<f32> = (time..2 * bar).map(|_| pipeline.next_sample()).collect();
+        assert!(output[..bar - time].iter().all(|s| *s == 0.0));
+        assert!(output[bar - time..].iter().any(|s| *s != 0.0));
+
+        // the existing grid continues, and both share the mix
+        assert_eq!(pipeline.time as usize, 2 * bar);
+        assert!(pipeline.playables.contains_key("veryfunname"));
+        assert_eq!(pipeline.mix["drone"], 0.5);
+
+        assert!(pipeline.remove_playable("drone"));
+        assert_eq!(pipeline.mix["veryfunname"], 1.0);
+        assert!(!pipeline.remove_playable("drone"));
+    }
 }
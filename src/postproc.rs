@@ -7,12 +7,62 @@ use std::f32::consts::PI;
 pub enum Effect {
     FIR(FIR),
     Reverb(Reverb),
+    Convolution(Convolution),
+    Delay(Delay),
     Gain(Gain),
     Compressor(Compressor),
 }
 
-/// Arbitrary maximum length for FIR filters.
-const MAX_FIR_LENGTH: usize = 100;
+impl Effect {
+    /// Change a parameter of this effect, by name.
+    pub fn set_param(&mut self, param: &str, value: f32) -> Result<(), &'static str> {
+        match (self, param) {
+            (Effect::Gain(gain), "amount") => {
+                gain.set_amount(value);
+                Ok(())
+            }
+            (Effect::FIR(fir), "cutoff") => fir.set_cutoff(value),
+            (Effect::Reverb(reverb), param) => reverb.set_param(param, value),
+            (Effect::Compressor(compressor), param) => compressor.set_param(param, value),
+            (Effect::Delay(delay), param) => delay.set_param(param, value),
+            (Effect::Convolution(convolution), "mix") => {
+                convolution.mix = value.clamp(0.0, 1.0);
+                Ok(())
+            }
+            _ => Err("Unknown parameter for this effect"),
+        }
+    }
+
+    /// The parameters of this effect which can be changed, with their current values.
+    pub fn params(&self) -> Vec<(&'static str, f32)> {
+        match self {
+            Effect::Gain(gain) => vec![("amount", gain.target)],
+            Effect::FIR(fir) => fir.design.map_or(vec![], |d| vec![("cutoff", d.cutoff)]),
+            Effect::Reverb(reverb) => reverb.params(),
+            Effect::Convolution(convolution) => vec![("mix", convolution.mix)],
+            Effect::Delay(delay) => vec![("feedback", delay.feedback), ("mix", delay.mix)],
+            Effect::Compressor(compressor) => vec![
+                ("ratio", compressor.ratio),
+                ("threshold", compressor.threshold),
+            ],
+        }
+    }
+
+    /// Process a single sample.
+    pub fn process(&mut self, input: f32) -> f32 {
+        match self {
+            Effect::FIR(fir) => fir.process(input),
+            Effect::Reverb(reverb) => reverb.process(input),
+            Effect::Convolution(convolution) => convolution.process(input),
+            Effect::Delay(delay) => delay.process(input),
+            Effect::Gain(gain) => gain.process(input),
+            Effect::Compressor(compressor) => compressor.process(input),
+        }
+    }
+}
+
+/// Arbitrary default maximum length for FIR filters, odd so a filter has a centre tap.
+pub const MAX_FIR_LENGTH: usize = 101;
 
 /// A simple low pass FIR filter.
 pub struct FIR {
@@ -20,27 +70,161 @@ pub struct FIR {
     coeffs: Vec<f32>,
     /// The filter state.
     state: Vec<f32>,
+    /// The design the coefficients were computed from, if any.
+    design: Option<FIRDesign>,
+}
+
+/// The kinds of filters the builder can design.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FIRKind {
+    LowPass,
+    HighPass,
+}
+
+/// What a filter was designed as, so it can be recomputed for another cutoff.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FIRDesign {
+    kind: FIRKind,
+    cutoff: f32,
+    sample_rate: f32,
+    max_length: usize,
 }
 
 /// Builder to create different types of FIR filters.
 pub struct FIRBuilder {
     coeffs: Vec<f32>,
+    design: Option<FIRDesign>,
+    max_length: usize,
 }
 
-#[allow(unused)]
+/// Default number of early reflections of the reverb.
+pub const DEFAULT_EARLY_REFLECTIONS: usize = 4;
+/// Default time between the early reflections, in milliseconds.
+pub const DEFAULT_REFLECTION_SPACING: f32 = 12.0;
+/// Default time the tail takes to decay by 60 dB, in seconds.
+pub const DEFAULT_TAIL: f32 = 1.5;
+
+/// Delays of the comb filters of the tail, in samples at 44.1 kHz (from the Freeverb tuning).
+const COMB_DELAYS: [usize; 4] = [1557, 1617, 1491, 1422];
+
+/// A reverb with discrete early reflections, followed by a diffuse tail.
+///
+/// Few, closely spaced reflections and a short tail sound like a small room, many widely spaced
+/// reflections and a long tail like a hall.
 pub struct Reverb {
-    state: f32,
+    sample_rate: f32,
+    early_reflections: usize,
+    /// Time between the early reflections in samples, the tail starts after the last one.
+    spacing: usize,
+    /// Time the tail takes to decay by 60 dB, in seconds.
+    tail: f32,
+    /// Level of the reverberated signal, the dry signal makes up the rest.
+    mix: f32,
+    /// The most recent input samples, long enough to reach the start of the tail.
+    history: Vec<f32>,
+    position: usize,
+    combs: Vec<Comb>,
+}
+
+/// A reverb which convolves its input with a recorded mono impulse response, as a stereo
+/// convolution of which both sides are summed back to mono.
+pub struct Convolution {
+    stereo: StereoConvolution,
+    /// Level of the convolved signal, the dry signal makes up the rest.
+    mix: f32,
 }
 
+/// Largest feedback of a delay, so its echoes always die out.
+pub const MAX_FEEDBACK: f32 = 0.99;
+/// Default level of the echoes of a delay.
+pub const DEFAULT_DELAY_MIX: f32 = 0.5;
+
+/// An echo of the input after a fixed time, which is fed back into itself to repeat.
+pub struct Delay {
+    /// The delayed signal, as long as the delay.
+    buffer: Vec<f32>,
+    position: usize,
+    /// The level of each echo relative to the one before it.
+    feedback: f32,
+    /// Level of the echoes, the dry signal makes up the rest.
+    mix: f32,
+}
+
+/// A convolution with a stereo impulse response, so the reverb tail has its own stereo image.
+///
+/// A mono impulse response is used for both sides, a stereo one convolves the input into the left
+/// and right channel, and a true stereo one (left to left, left to right, right to left and right
+/// to right) also keeps the image of a stereo input.
+pub struct StereoConvolution {
+    /// The impulse responses from each input channel (outer) to each output channel (inner).
+    responses: [[Vec<f32>; 2]; 2],
+    /// The most recent left and right input samples, the newest first.
+    history: [Vec<f32>; 2],
+}
+
+/// A feedback comb filter, which makes an exponentially decaying train of echoes.
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    feedback: f32,
+}
+
+/// Number of samples over which a gain change is ramped (5 ms at 48 kHz).
+const GAIN_RAMP_LENGTH: f32 = 240.0;
+
 pub struct Gain {
+    /// The gain which is applied now.
     amount: f32,
+    /// The gain the ramp is heading to.
+    target: f32,
+    /// The change of the gain per sample while ramping.
+    step: f32,
 }
 
+/// Time over which the peak of an auto gain falls back after a loud part, in seconds.
+const AUTO_GAIN_RELEASE: f32 = 3.0;
+/// Time over which an auto gain moves most of the way to a new trim, in seconds.
+const AUTO_GAIN_SMOOTHING: f32 = 0.5;
+
+/// A trim which follows the long-term peak of its input, to keep the peaks at a target level.
+///
+/// It only turns the input down, so quiet parts aren't pumped up while playing live.
+pub struct AutoGain {
+    /// The peak level to keep, as an amplitude.
+    target: f32,
+    /// The slowly released peak of the input.
+    peak: f32,
+    /// The trim which is applied now.
+    gain: f32,
+    /// The factor by which the peak falls every sample.
+    release: f32,
+    /// The part of the way to the new trim which the trim moves every sample.
+    smoothing: f32,
+}
+
+/// Default time a compressor takes to turn the gain down, in seconds.
+pub const DEFAULT_ATTACK: f32 = 0.01;
+/// Default time a compressor takes to let the gain recover, in seconds.
+pub const DEFAULT_RELEASE: f32 = 0.1;
+
 pub struct Compressor {
     ratio: f32,
+    /// The level above which the input is reduced, in the level of `AudioEnergy`.
     threshold: f32,
     energy: AudioEnergy,
+    /// The gain which is applied now.
     current: f32,
+    /// The part of the way to a lower gain which the gain moves every sample.
+    attack: f32,
+    /// The part of the way to a higher gain which the gain moves every sample.
+    release: f32,
+}
+
+/// A copy of a track which runs through its own effects, and is summed back with the dry track.
+pub struct Aux {
+    effects: Vec<Effect>,
+    /// The level of the processed copy in the sum.
+    level: f32,
 }
 
 pub struct AudioEnergy {
@@ -50,51 +234,75 @@ pub struct AudioEnergy {
 
 impl FIRBuilder {
     pub fn new() -> Self {
-        Self { coeffs: vec![] }
+        Self {
+            coeffs: vec![],
+            design: None,
+            max_length: MAX_FIR_LENGTH,
+        }
     }
 
+    /// Limit the number of coefficients, longer filters are steeper but cost more CPU.
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length.max(1);
+        self
+    }
+
+    /// A windowed sinc, which is symmetric around its centre tap, so it delays all frequencies
+    /// equally and is at -6 dB at the cutoff.
     pub fn low_pass(mut self, cutoff: f32, sample_rate: f32) -> Self {
-        let n = 2.0 * sample_rate / cutoff;
-        let mut n = n as usize;
-        n = n.min(MAX_FIR_LENGTH);
+        let n = ((2.0 * sample_rate / cutoff) as usize).clamp(1, self.max_length);
+        // an odd length, so the centre is a tap
+        let n = n - (1 - n % 2);
+        let center = (n / 2) as f32;
+
         let mut coeffs = vec![0.0; n];
         for (i, item) in coeffs.iter_mut().enumerate() {
-            let x = i as f32 * cutoff / sample_rate;
-            if x == 0.0 {
-                *item = 1.0;
-                continue;
-            }
-            *item = x.sin() / (x);
+            let x = 2.0 * cutoff / sample_rate * (i as f32 - center);
+            *item = if x == 0.0 {
+                1.0
+            } else {
+                (PI * x).sin() / (PI * x)
+            };
             // Hann window
-            *item *= (PI * i as f32 / n as f32).sin().powi(2);
+            *item *= (PI * (i + 1) as f32 / (n + 1) as f32).sin().powi(2);
         }
 
+        // normalize to unity gain at DC
+        let sum: f32 = coeffs.iter().sum();
+        coeffs.iter_mut().for_each(|c| *c /= sum);
+
         self.coeffs = coeffs;
+        self.design = Some(FIRDesign {
+            kind: FIRKind::LowPass,
+            cutoff,
+            sample_rate,
+            max_length: self.max_length,
+        });
         self
     }
 
-    pub fn high_pass(mut self, cutoff: f32, sample_rate: f32) -> Self {
-        let n = 2.0 * sample_rate / cutoff;
-        let mut n = n as usize;
-        n = n.min(MAX_FIR_LENGTH);
-        let mut coeffs = vec![0.0; n];
-        for (i, item) in coeffs.iter_mut().enumerate() {
-            let x = i as f32 * cutoff / sample_rate;
-            if x == 0.0 {
-                *item = 1.0;
-                continue;
-            }
-            *item = (-1.0_f32).powi(i as i32) * x.sin() / x;
-            // Hann window
-            // *item *= (PI * i as f32 / n as f32).sin().powi(2);
-        }
+    /// A high pass as the spectral inversion of the low pass at the same cutoff (a delta at its
+    /// centre tap minus the low pass), so both filters split the spectrum at the same frequency.
+    pub fn high_pass(self, cutoff: f32, sample_rate: f32) -> Self {
+        let mut builder = self.low_pass(cutoff, sample_rate);
+        builder.coeffs.iter_mut().for_each(|c| *c = -*c);
+        // the low pass is normalized, so this leaves zero gain at DC
+        let center = builder.coeffs.len() / 2;
+        builder.coeffs[center] += 1.0;
 
-        self.coeffs = coeffs;
-        self
+        builder.design = Some(FIRDesign {
+            kind: FIRKind::HighPass,
+            cutoff,
+            sample_rate,
+            max_length: builder.max_length,
+        });
+        builder
     }
 
     pub fn build(&self) -> FIR {
-        FIR::new(self.coeffs.clone())
+        let mut fir = FIR::new(self.coeffs.clone());
+        fir.design = self.design;
+        fir
     }
 }
 
@@ -108,7 +316,29 @@ impl FIR {
     /// Create a new FIR filter with the given coefficients.
     fn new(coeffs: Vec<f32>) -> Self {
         let state = vec![0.0; coeffs.len()];
-        Self { coeffs, state }
+        Self {
+            coeffs,
+            state,
+            design: None,
+        }
+    }
+
+    /// Recompute the coefficients for a new cutoff frequency (in Hz), e.g. for a filter sweep.
+    ///
+    /// The most recent input samples are kept, so the output continues without a click.
+    pub fn set_cutoff(&mut self, cutoff: f32) -> Result<(), &'static str> {
+        let design = self.design.ok_or("Filter was not designed for a cutoff")?;
+
+        let builder = FIRBuilder::new().max_length(design.max_length);
+        let builder = match design.kind {
+            FIRKind::LowPass => builder.low_pass(cutoff, design.sample_rate),
+            FIRKind::HighPass => builder.high_pass(cutoff, design.sample_rate),
+        };
+
+        self.coeffs = builder.coeffs;
+        self.design = builder.design;
+        self.state.resize(self.coeffs.len(), 0.0);
+        Ok(())
     }
 
     /// Process a single sample.
@@ -121,35 +351,388 @@ impl FIR {
         }
         output
     }
+
+    /// Magnitude of the frequency response at each of the given frequencies (in Hz).
+    ///
+    /// This evaluates the DFT of the filter coefficients, which is useful to draw the filter curve.
+    pub fn magnitude_response(&self, freqs: &[f32], sample_rate: f32) -> Vec<f32> {
+        freqs
+            .iter()
+            .map(|freq| {
+                let omega = 2.0 * PI * freq / sample_rate;
+                let (re, im) =
+                    self.coeffs
+                        .iter()
+                        .enumerate()
+                        .fold((0.0, 0.0), |(re, im), (k, c)| {
+                            let phase = omega * k as f32;
+                            (re + c * phase.cos(), im - c * phase.sin())
+                        });
+                (re * re + im * im).sqrt()
+            })
+            .collect()
+    }
 }
 
-impl Reverb {
-    pub fn new() -> Self {
-        Self { state: 0.0 }
+/// The reverb impulse response which was baked in at build time.
+#[cfg(feature = "baked-reverb")]
+pub mod baked {
+    include!(concat!(env!("OUT_DIR"), "/reverb.rs"));
+}
+
+impl Delay {
+    /// A delay of `delay_samples` samples, whose echoes are `feedback` times as loud as the one
+    /// before them (at most [`MAX_FEEDBACK`]).
+    pub fn new(delay_samples: usize, feedback: f32, mix: f32) -> Self {
+        Self {
+            buffer: vec![0.0; delay_samples.max(1)],
+            position: 0,
+            feedback: feedback.clamp(0.0, MAX_FEEDBACK),
+            mix: mix.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Change a parameter by name: `feedback` or `mix`.
+    fn set_param(&mut self, param: &str, value: f32) -> Result<(), &'static str> {
+        match param {
+            "feedback" => self.feedback = value.clamp(0.0, MAX_FEEDBACK),
+            "mix" => self.mix = value.clamp(0.0, 1.0),
+            _ => return Err("Unknown parameter for this effect"),
+        }
+        Ok(())
     }
 
     pub fn process(&mut self, input: f32) -> f32 {
-        // TODO: implement a proper reverb algorithm
-        input
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * self.feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+
+        input * (1.0 - self.mix) + delayed * self.mix
     }
 }
 
-impl Default for Reverb {
-    fn default() -> Self {
-        Self::new()
+impl Convolution {
+    pub fn new(response: Vec<f32>, mix: f32) -> Self {
+        let response = if response.is_empty() {
+            vec![0.0]
+        } else {
+            response
+        };
+        Self {
+            stereo: StereoConvolution::new(vec![response]).unwrap(),
+            mix: mix.clamp(0.0, 1.0),
+        }
+    }
+
+    /// A convolution with the impulse response which was baked in, resampled to `sample_rate`.
+    #[cfg(feature = "baked-reverb")]
+    pub fn baked(mix: f32, sample_rate: f32) -> Self {
+        let ratio = baked::REVERB_SAMPLE_RATE as f32 / sample_rate;
+        let length = (baked::REVERB_SIZE as f32 / ratio) as usize;
+        let response = (0..length)
+            .map(|i| {
+                let position = i as f32 * ratio;
+                let low = position as usize;
+                let high = (low + 1).min(baked::REVERB_SIZE - 1);
+                let t = position - low as f32;
+                baked::REVERB_MASK[low] * (1.0 - t) + baked::REVERB_MASK[high] * t
+            })
+            .collect();
+
+        Self::new(response, mix)
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        // a mono response gives both sides the whole tail, they are summed at half level
+        let (left, right) = self.stereo.process_mono(input);
+        let wet = 0.5 * (left + right);
+
+        input * (1.0 - self.mix) + wet * self.mix
+    }
+}
+
+impl StereoConvolution {
+    /// Create a convolution from the channels of an impulse response, which has one, two or four
+    /// channels (in the order LL, LR, RL, RR).
+    pub fn new(mut channels: Vec<Vec<f32>>) -> Result<Self, &'static str> {
+        let responses = match channels.len() {
+            1 => {
+                let mono = channels.remove(0);
+                [[mono.clone(), mono.clone()], [mono.clone(), mono]]
+            }
+            2 => {
+                let right = channels.remove(1);
+                let left = channels.remove(0);
+                [[left.clone(), right.clone()], [left, right]]
+            }
+            4 => {
+                let rr = channels.remove(3);
+                let rl = channels.remove(2);
+                let lr = channels.remove(1);
+                let ll = channels.remove(0);
+                [[ll, lr], [rl, rr]]
+            }
+            _ => return Err("Impulse response needs one, two or four channels"),
+        };
+
+        let length = responses.iter().flatten().map(Vec::len).max().unwrap_or(0);
+        Ok(Self {
+            responses,
+            history: [vec![0.0; length], vec![0.0; length]],
+        })
+    }
+
+    /// Process a single stereo frame.
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        for (history, input) in self.history.iter_mut().zip([left, right]) {
+            history.insert(0, input);
+            history.pop();
+        }
+
+        let mut output = [0.0; 2];
+        for (history, responses) in self.history.iter().zip(&self.responses) {
+            for (out, response) in output.iter_mut().zip(responses) {
+                *out += response
+                    .iter()
+                    .zip(history)
+                    .map(|(c, s)| c * s)
+                    .sum::<f32>();
+            }
+        }
+        (output[0], output[1])
+    }
+
+    /// Process a single mono sample, which feeds both input channels at half level.
+    pub fn process_mono(&mut self, input: f32) -> (f32, f32) {
+        self.process(0.5 * input, 0.5 * input)
+    }
+}
+
+impl Comb {
+    fn new(delay: usize) -> Self {
+        Self {
+            buffer: vec![0.0; delay.max(1)],
+            position: 0,
+            feedback: 0.0,
+        }
+    }
+
+    /// Set the feedback so the echoes decay by 60 dB in `tail` seconds.
+    fn set_tail(&mut self, tail: f32, sample_rate: f32) {
+        let delay = self.buffer.len() as f32 / sample_rate;
+        self.feedback = if tail > 0.0 {
+            10.0_f32.powf(-3.0 * delay / tail)
+        } else {
+            0.0
+        };
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.buffer[self.position] = input + output * self.feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        output
+    }
+}
+
+impl Reverb {
+    pub fn new(mix: f32, sample_rate: f32) -> Self {
+        let mut reverb = Self {
+            sample_rate,
+            early_reflections: DEFAULT_EARLY_REFLECTIONS,
+            spacing: 0,
+            tail: DEFAULT_TAIL,
+            mix: mix.clamp(0.0, 1.0),
+            history: vec![],
+            position: 0,
+            combs: COMB_DELAYS
+                .iter()
+                .map(|delay| Comb::new((*delay as f32 * sample_rate / 44100.0) as usize))
+                .collect(),
+        };
+        reverb.set_early_reflections(DEFAULT_EARLY_REFLECTIONS, DEFAULT_REFLECTION_SPACING);
+        reverb.set_tail(DEFAULT_TAIL);
+        reverb
+    }
+
+    /// Use `count` early reflections, `spacing` milliseconds apart.
+    pub fn set_early_reflections(&mut self, count: usize, spacing: f32) {
+        self.early_reflections = count;
+        self.spacing = ((spacing.max(0.0) * self.sample_rate / 1000.0) as usize).max(1);
+
+        // the tail is fed from the input that comes after the last reflection
+        self.history = vec![0.0; (count + 1) * self.spacing + 1];
+        self.position = 0;
+    }
+
+    /// Let the tail decay by 60 dB in `tail` seconds.
+    pub fn set_tail(&mut self, tail: f32) {
+        self.tail = tail.max(0.0);
+        for comb in &mut self.combs {
+            comb.set_tail(self.tail, self.sample_rate);
+        }
+    }
+
+    /// Change a parameter by name: `mix`, `early_reflections`, `spacing` (ms) or `tail` (s).
+    pub fn set_param(&mut self, param: &str, value: f32) -> Result<(), &'static str> {
+        match param {
+            "mix" => self.mix = value.clamp(0.0, 1.0),
+            "early_reflections" => {
+                let spacing = self.spacing as f32 * 1000.0 / self.sample_rate;
+                self.set_early_reflections(value.max(0.0) as usize, spacing);
+            }
+            "spacing" => self.set_early_reflections(self.early_reflections, value),
+            "tail" => self.set_tail(value),
+            _ => return Err("Unknown parameter for this effect"),
+        }
+        Ok(())
+    }
+
+    /// The values of the parameters [`Reverb::set_param`] takes.
+    fn params(&self) -> Vec<(&'static str, f32)> {
+        vec![
+            ("mix", self.mix),
+            ("early_reflections", self.early_reflections as f32),
+            ("spacing", self.spacing as f32 * 1000.0 / self.sample_rate),
+            ("tail", self.tail),
+        ]
+    }
+
+    /// The input of `delay` samples ago.
+    fn delayed(&self, delay: usize) -> f32 {
+        let len = self.history.len();
+        self.history[(self.position + len - delay % len) % len]
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.history[self.position] = input;
+
+        // each reflection is quieter than the one before it
+        let mut early = 0.0;
+        let mut gain = 1.0;
+        for reflection in 1..=self.early_reflections {
+            gain *= 0.7;
+            early += gain * self.delayed(reflection * self.spacing);
+        }
+
+        let tail_input = self.delayed((self.early_reflections + 1) * self.spacing);
+        let tail = self
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(tail_input))
+            .sum::<f32>()
+            / self.combs.len() as f32;
+
+        self.position = (self.position + 1) % self.history.len();
+
+        input * (1.0 - self.mix) + (early + tail) * self.mix
     }
 }
 
 impl Gain {
     pub fn new(amount: f32) -> Self {
-        Self { amount }
+        Self {
+            amount,
+            target: amount,
+            step: 0.0,
+        }
+    }
+
+    /// Glide to a new gain, instead of jumping to it (which would click).
+    pub fn set_amount(&mut self, target: f32) {
+        self.target = target;
+        self.step = (target - self.amount) / GAIN_RAMP_LENGTH;
     }
 
     pub fn process(&mut self, input: f32) -> f32 {
+        if self.amount != self.target {
+            self.amount += self.step;
+            // don't overshoot the target
+            if (self.target - self.amount) * self.step <= 0.0 {
+                self.amount = self.target;
+            }
+        }
+
         input * self.amount
     }
 }
 
+/// Number of samples on each side which an oversampled value is interpolated from.
+const TRUE_PEAK_TAPS: usize = 8;
+
+/// The peak of `samples` between the samples too, by oversampling them `oversampling` times with
+/// a windowed sinc interpolation. This finds the peaks a DAC (or a resampler) would reconstruct.
+pub fn true_peak(samples: &[f32], oversampling: usize) -> f32 {
+    let oversampling = oversampling.max(1);
+    let taps = TRUE_PEAK_TAPS as isize;
+    let sinc = |x: f32| {
+        if x.abs() < 1e-6 {
+            1.0
+        } else {
+            (PI * x).sin() / (PI * x)
+        }
+    };
+    let window = |x: f32| 0.5 + 0.5 * (PI * x / (taps as f32 + 1.0)).cos();
+
+    let mut peak = samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+    for i in 0..samples.len() {
+        for phase in 1..oversampling {
+            let fraction = phase as f32 / oversampling as f32;
+            let value: f32 = (-taps + 1..=taps)
+                .filter_map(|offset| {
+                    let sample = samples.get(usize::try_from(i as isize + offset).ok()?)?;
+                    let x = offset as f32 - fraction;
+                    Some(sample * sinc(x) * window(x))
+                })
+                .sum();
+            peak = peak.max(value.abs());
+        }
+    }
+
+    peak
+}
+
+/// Convert a level in dBFS into an amplitude.
+pub fn db_to_amplitude(db: f32) -> f32 {
+    10.0_f32.powf(db / 20.0)
+}
+
+/// The static trim which brings the peak of `samples` to `target_db` dBFS, 1.0 for silence.
+pub fn normalize_trim(samples: &[f32], target_db: f32) -> f32 {
+    let peak = samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+    if peak == 0.0 {
+        return 1.0;
+    }
+
+    db_to_amplitude(target_db) / peak
+}
+
+impl AutoGain {
+    /// An auto gain which keeps the peaks at or below `target_db` dBFS.
+    pub fn new(target_db: f32, sample_rate: f32) -> Self {
+        Self {
+            target: db_to_amplitude(target_db),
+            peak: 0.0,
+            gain: 1.0,
+            release: (-1.0 / (AUTO_GAIN_RELEASE * sample_rate)).exp(),
+            smoothing: 1.0 - (-1.0 / (AUTO_GAIN_SMOOTHING * sample_rate)).exp(),
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.peak = input.abs().max(self.peak * self.release);
+
+        let trim = if self.peak > self.target {
+            self.target / self.peak
+        } else {
+            1.0
+        };
+        self.gain += (trim - self.gain) * self.smoothing;
+
+        input * self.gain
+    }
+}
+
 impl AudioEnergy {
     fn new(len: usize) -> Self {
         // maybe change length to be a parameter
@@ -181,11 +764,395 @@ impl Compressor {
             threshold,
             energy: AudioEnergy::new(len),
             current: 1.0,
+            attack: 1.0,
+            release: 1.0,
         }
     }
 
+    /// Let the gain come down over `attack` seconds and recover over `release` seconds, instead
+    /// of following the level at once.
+    pub fn set_times(&mut self, attack: f32, release: f32, sample_rate: f32) {
+        let smoothing = |time: f32| match time * sample_rate {
+            samples if samples > 0.0 => 1.0 - (-1.0 / samples).exp(),
+            _ => 1.0,
+        };
+        self.attack = smoothing(attack);
+        self.release = smoothing(release);
+    }
+
+    /// Change a parameter by name: `ratio` or `threshold`.
+    fn set_param(&mut self, param: &str, value: f32) -> Result<(), &'static str> {
+        match param {
+            "ratio" => self.ratio = value.max(1.0),
+            "threshold" => self.threshold = value,
+            _ => return Err("Unknown parameter for this effect"),
+        }
+        Ok(())
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        let level = self.energy.add_and_get(input);
+
+        // reduce everything above the threshold by the ratio
+        let over = level - self.threshold;
+        let reduction = if over > 0.0 {
+            over * (1.0 - 1.0 / self.ratio)
+        } else {
+            0.0
+        };
+        let target = 10.0_f32.powf(-reduction / 20.0);
+        let smoothing = if target < self.current {
+            self.attack
+        } else {
+            self.release
+        };
+        self.current += (target - self.current) * smoothing;
+
+        input * self.current
+    }
+}
+
+/// Ratio of the compressor of a parallel compression aux, heavy enough to squash the peaks.
+const PARALLEL_RATIO: f32 = 10.0;
+/// Threshold (in the level of `AudioEnergy`) of the compressor of a parallel compression aux.
+const PARALLEL_THRESHOLD: f32 = 0.0;
+/// Makeup gain of the compressed copy, which brings the quiet parts up.
+const PARALLEL_MAKEUP: f32 = 2.0;
+/// Number of samples the level of a parallel compression aux is measured over (10 ms at 48 kHz).
+const PARALLEL_WINDOW: usize = 480;
+
+impl Aux {
+    pub fn new(effects: Vec<Effect>, level: f32) -> Self {
+        Self { effects, level }
+    }
+
+    /// New York style compression: a heavily compressed copy, blended in at the given level.
+    pub fn parallel_compression(level: f32) -> Self {
+        Self::new(
+            vec![
+                Effect::Compressor(Compressor::new(
+                    PARALLEL_RATIO,
+                    PARALLEL_THRESHOLD,
+                    PARALLEL_WINDOW,
+                )),
+                Effect::Gain(Gain::new(PARALLEL_MAKEUP)),
+            ],
+            level,
+        )
+    }
+
+    /// The dry input summed with the processed copy.
     pub fn process(&mut self, input: f32) -> f32 {
-        // TODO: implement a proper compressor algorithm
-        input
+        let wet = self
+            .effects
+            .iter_mut()
+            .fold(input, |output, effect| effect.process(output));
+
+        input + self.level * wet
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn true_peak_between_samples() {
+        // a sine at a quarter of the sample rate, sampled 45 degrees off its peaks
+        let samples: Vec<f32> = (0..64)
+            .map(|i| (PI / 2.0 * i as f32 + PI / 4.0).sin())
+            .collect();
+
+        let sampled = samples.iter().fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        let peak = true_peak(&samples[16..48], 4);
+        assert!((sampled - 0.707).abs() < 1e-3);
+        assert!(peak > 0.95, "True peak is {}", peak);
+    }
+
+    #[test]
+    fn auto_gain_settles_hot_input() {
+        let mut auto_gain = AutoGain::new(-6.0, 48000.0);
+
+        // a sine which peaks at +6 dBFS, for four seconds
+        let output: Vec<f32> = (0..4 * 48000)
+            .map(|i| auto_gain.process(2.0 * (i as f32 * 0.05).sin()))
+            .collect();
+
+        let peak = output[3 * 48000..]
+            .iter()
+            .fold(0.0_f32, |peak, s| peak.max(s.abs()));
+        assert!(
+            (peak - db_to_amplitude(-6.0)).abs() < 0.01,
+            "Auto gain settled at a peak of {}",
+            peak
+        );
+    }
+
+    #[test]
+    fn low_pass_response() {
+        let sample_rate = 48000.0;
+        let cutoff = 1000.0;
+        let fir = FIRBuilder::new().low_pass(cutoff, sample_rate).build();
+
+        let response = fir.magnitude_response(&[0.0, 2.0 * cutoff, 4.0 * cutoff], sample_rate);
+
+        assert!(
+            (response[0] - 1.0).abs() < 0.01,
+            "DC gain is not unity, but {}",
+            response[0]
+        );
+        assert!(
+            response[1] < 0.1 && response[2] < 0.1,
+            "Frequencies above the cutoff are not attenuated: {:?}",
+            response
+        );
+    }
+
+    #[test]
+    fn high_pass_complements_low_pass() {
+        let sample_rate = 48000.0;
+        let cutoff = 1000.0;
+        let low = FIRBuilder::new().low_pass(cutoff, sample_rate).build();
+        let high = FIRBuilder::new().high_pass(cutoff, sample_rate).build();
+        let probe = [0.0, cutoff / 4.0, cutoff, 2.0 * cutoff, 8.0 * cutoff];
+
+        let low = low.magnitude_response(&probe, sample_rate);
+        let high = high.magnitude_response(&probe, sample_rate);
+
+        // both are at -6 dB at the cutoff they share
+        for gain in [low[2], high[2]] {
+            assert!(
+                (gain - db_to_amplitude(-6.0)).abs() < 0.02,
+                "Gain at the cutoff is {} instead of -6 dB",
+                gain
+            );
+        }
+
+        // and each attenuates the band the other passes
+        assert!(high[0] < 1e-4, "DC passes the high pass: {}", high[0]);
+        assert!(
+            high[1] < 0.05,
+            "Low frequencies pass the high pass: {:?}",
+            high
+        );
+        assert!(
+            low[3] < 0.05 && low[4] < 0.05,
+            "High frequencies pass the low pass: {:?}",
+            low
+        );
+        assert!(
+            (high[3] - 1.0).abs() < 0.05 && (high[4] - 1.0).abs() < 0.05,
+            "High frequencies are attenuated: {:?}",
+            high
+        );
+    }
+
+    #[test]
+    fn set_cutoff_moves_response() {
+        let sample_rate = 48000.0;
+        let mut fir = FIRBuilder::new().low_pass(1000.0, sample_rate).build();
+        let probe = [2000.0];
+
+        let closed = fir.magnitude_response(&probe, sample_rate)[0];
+        fir.set_cutoff(8000.0).unwrap();
+        let open = fir.magnitude_response(&probe, sample_rate)[0];
+
+        assert!(closed < 0.1, "2 kHz passes a 1 kHz low pass: {}", closed);
+        assert!(
+            open > 0.5,
+            "2 kHz is blocked by an 8 kHz low pass: {}",
+            open
+        );
+        assert_eq!(fir.state.len(), fir.coeffs.len());
+    }
+
+    #[test]
+    fn longer_max_length() {
+        let sample_rate = 48000.0;
+        let short = FIRBuilder::new().low_pass(100.0, sample_rate).build();
+        let long = FIRBuilder::new()
+            .max_length(1000)
+            .low_pass(100.0, sample_rate)
+            .build();
+
+        assert_eq!(short.coeffs.len(), MAX_FIR_LENGTH);
+        assert!(
+            long.coeffs.len() > short.coeffs.len(),
+            "Longer max length gave {} coefficients",
+            long.coeffs.len()
+        );
+    }
+
+    #[test]
+    fn gain_glides() {
+        let mut gain = Gain::new(1.0);
+        gain.set_amount(0.0);
+
+        let output: Vec<f32> = (0..300).map(|_| gain.process(1.0)).collect();
+
+        assert!(
+            output[0] > 0.9,
+            "Gain jumped to {} instead of gliding",
+            output[0]
+        );
+        assert!(output.windows(2).all(|w| w[1] <= w[0]));
+        assert_eq!(output[GAIN_RAMP_LENGTH as usize], 0.0);
+    }
+
+    #[test]
+    fn parallel_compression_lifts_quiet_parts() {
+        // the gain from the dry input to the output, once the level detector settled
+        let gain = |input: f32| {
+            let mut aux = Aux::parallel_compression(1.0);
+            let output = (0..2 * PARALLEL_WINDOW)
+                .map(|_| aux.process(input))
+                .last()
+                .unwrap();
+            output / input
+        };
+
+        let quiet = gain(0.05);
+        let loud = gain(0.8);
+
+        assert!(quiet > 1.0, "Quiet part isn't raised: {}", quiet);
+        assert!(
+            quiet > loud,
+            "Quiet part gained {} and loud part {}",
+            quiet,
+            loud
+        );
+    }
+
+    #[test]
+    fn early_reflections_before_tail() {
+        let sample_rate = 48000.0;
+        // 10 ms between the reflections
+        let spacing = 480;
+
+        // the samples before the tail (of the reverb with the most reflections) which sound
+        let echoes = |count: usize| {
+            let mut reverb = Reverb::new(1.0, sample_rate);
+            reverb.set_early_reflections(count, 10.0);
+
+            let output: Vec<f32> = (0..5 * spacing)
+                .map(|i| reverb.process(if i == 0 { 1.0 } else { 0.0 }))
+                .collect();
+            output
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.abs() > 1e-6)
+                .map(|(i, _)| i)
+                .collect::<Vec<usize>>()
+        };
+
+        assert_eq!(echoes(2), vec![spacing, 2 * spacing]);
+        assert_eq!(
+            echoes(4),
+            vec![spacing, 2 * spacing, 3 * spacing, 4 * spacing]
+        );
+    }
+
+    #[test]
+    fn stereo_response_splits_mono_input() {
+        // the right channel of the impulse response echoes later than the left one
+        let mut left = vec![0.0; 8];
+        left[2] = 1.0;
+        let mut right = vec![0.0; 8];
+        right[5] = 1.0;
+        let mut convolution = StereoConvolution::new(vec![left, right]).unwrap();
+
+        let tails: Vec<(f32, f32)> = (0..8)
+            .map(|i| convolution.process_mono(if i == 0 { 1.0 } else { 0.0 }))
+            .collect();
+
+        assert_ne!(
+            tails.iter().map(|t| t.0).collect::<Vec<_>>(),
+            tails.iter().map(|t| t.1).collect::<Vec<_>>()
+        );
+        assert_eq!(tails[2], (1.0, 0.0));
+        assert_eq!(tails[5], (0.0, 1.0));
+        assert!(StereoConvolution::new(vec![vec![1.0]; 3]).is_err());
+    }
+
+    #[test]
+    fn compressor_settles_toward_threshold() {
+        let sample_rate = 48000.0;
+        let window = 480;
+        let mut compressor = Compressor::new(4.0, 0.0, window);
+        compressor.set_times(0.01, 0.1, sample_rate);
+
+        // a full scale sine is at about 17 in the level of the energy detector
+        let output: Vec<f32> = (0..48000)
+            .map(|i| (2.0 * PI * 440.0 * i as f32 / sample_rate).sin())
+            .map(|s| compressor.process(s))
+            .collect();
+        let level = |part: &[f32]| {
+            10.0 * (100.0 * part.iter().map(|s| s * s).sum::<f32>() / part.len() as f32).log10()
+        };
+        let input_level = 10.0 * 50.0_f32.log10();
+
+        // the attack lets the start through, the gain comes down to a quarter of the overshoot
+        let early = level(&output[window..2 * window]);
+        let late = level(&output[43200..]);
+        assert!(early > late + 3.0, "Level went from {} to {}", early, late);
+        assert!(
+            (late - input_level / 4.0).abs() < 0.5,
+            "Settled at {} instead of {}",
+            late,
+            input_level / 4.0
+        );
+    }
+
+    #[test]
+    fn delay_echoes_impulse() {
+        let mut delay = Delay::new(100, 0.5, 0.5);
+
+        let output: Vec<f32> = (0..400)
+            .map(|i| delay.process(if i == 0 { 1.0 } else { 0.0 }))
+            .collect();
+        let echoes: Vec<(usize, f32)> = output
+            .into_iter()
+            .enumerate()
+            .filter(|(_, s)| *s != 0.0)
+            .collect();
+
+        // the dry impulse, then an echo every 100 samples at half the level of the one before
+        assert_eq!(
+            echoes,
+            vec![(0, 0.5), (100, 0.5), (200, 0.25), (300, 0.125)]
+        );
+        assert_eq!(Delay::new(100, 1.5, 0.5).feedback, MAX_FEEDBACK);
+    }
+
+    #[test]
+    fn convolution_tail_follows_response() {
+        let response: Vec<f32> = (0..64).map(|i| 0.9_f32.powi(i)).collect();
+        let mut convolution = Convolution::new(response.clone(), 0.5);
+
+        let output: Vec<f32> = (0..128)
+            .map(|i| convolution.process(if i == 0 { 1.0 } else { 0.0 }))
+            .collect();
+
+        // the dry impulse, followed by the decaying response at half level
+        assert_eq!(output[0], 0.5 + 0.5 * response[0]);
+        for (out, tap) in output[1..64].iter().zip(&response[1..]) {
+            assert!(
+                (out - 0.5 * tap).abs() < 1e-6,
+                "Tail is {} instead of {}",
+                out,
+                tap
+            );
+        }
+        assert!(output[64..].iter().all(|s| *s == 0.0));
+    }
+
+    #[cfg(feature = "baked-reverb")]
+    #[test]
+    fn baked_reverb_constants() {
+        assert_eq!(baked::REVERB_MASK.len(), baked::REVERB_SIZE);
+        assert!(
+            baked::REVERB_MASK.iter().any(|s| *s != 0.0),
+            "The baked impulse response is silent"
+        );
     }
 }
@@ -1,6 +1,22 @@
-use std::{collections::HashMap, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
 
 use dasp_sample::Sample as Sm;
+use symphonia::core::{
+    audio::SampleBuffer,
+    codecs::{DecoderOptions, CODEC_TYPE_NULL},
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+
+use crate::chromatic::Note;
 
 /// SamplePlayer contains a sample reference and the information
 /// which is required to play it
@@ -9,44 +25,469 @@ pub struct SamplePlayer {
     pub sample: Arc<Sample>,
     start: u128,
     speed: f32,
+    // the part of the sample which is played, as [start, end) frames
+    range: Option<(usize, usize)>,
+    // how the left and right channel of a stereo sample are summed, None averages them
+    balance: Option<f32>,
+    // the only channel which is played, instead of summing them
+    channel: Option<usize>,
+    // whether the played part is played backwards, from its last frame to its first
+    reverse: bool,
+    // what the frames past the end of the played part are, when interpolating
+    boundary: Boundary,
+    // the frame of the played part which is continued from once its end is reached, None plays
+    // the sample once
+    loop_start: Option<usize>,
+}
+
+/// What the frames past the end of a sample are, which the last frames are interpolated with.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum Boundary {
+    /// Silence, which fades the last frame out.
+    #[default]
+    Zero,
+    /// Repeat the last frame.
+    Clamp,
+    /// Continue from the first frame, for samples which loop seamlessly.
+    Wrap,
+}
+
+/// How long a triggered sample sounds.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum SampleLength {
+    /// Until the end of the sample.
+    #[default]
+    Natural,
+    /// For the step it was triggered on (and the repeats after it), then it is released.
+    Step,
 }
 
+/// Length of the fade of a voice which is released before its end, in milliseconds.
+const RELEASE_MS: f32 = 5.0;
+
 /// Sample contains the name and data of a single sample
 #[derive(Debug, PartialEq)]
 pub struct Sample {
     pub name: String,
+    // all channels averaged
     data: Vec<f32>,
+    // the separate channels, only kept when the sample has more than one
+    channels: Vec<Vec<f32>>,
     sample_rate: u32,
+    // the pitch of the sample, declared or detected when it is first needed
+    root: OnceLock<Option<Note>>,
+}
+
+/// Which voice is stopped to make room when the polyphony limit is reached.
+#[derive(Default, Debug, PartialEq, Clone, Copy)]
+pub enum StealPolicy {
+    #[default]
+    Oldest,
+    Quietest,
+}
+
+/// A voice in the pool, with a (slowly decaying) peak level to find the quietest voice.
+#[derive(Debug)]
+struct Voice {
+    player: SamplePlayer,
+    level: f32,
+    gain: f32,
+    // time at which the voice is released and fades out, None plays it to its end
+    release: Option<u128>,
+}
+
+/// VoicePool contains the sample voices which are sounding, at most `max_voices` at once
+#[derive(Debug)]
+pub struct VoicePool {
+    // ordered from oldest to newest
+    voices: Vec<Voice>,
+    max_voices: usize,
+    policy: StealPolicy,
+}
+
+/// A sample which is recorded at another rate than the output, so it is resampled while playing.
+#[derive(Debug, PartialEq, Clone)]
+pub struct RateMismatch {
+    pub sample: String,
+    pub sample_rate: u32,
+    pub output_rate: u32,
+}
+
+/// A named part of a sample, so one file (e.g. a sliced drum kit) can hold many sounds.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Slice {
+    pub sample: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Why a sample file could not be loaded.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SkipReason {
+    /// The file can't be opened, or isn't a wav file.
+    Unreadable,
+    /// A wav file with a sample format or bit depth which can't be decoded.
+    UnsupportedFormat,
+    /// A wav file without any samples.
+    Empty,
+}
+
+/// A file in the samples directory which was not loaded.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SkippedSample {
+    pub file: PathBuf,
+    pub reason: SkipReason,
 }
 
 #[derive(Debug)]
 pub struct SampleSet {
     pub samples: HashMap<String, Arc<Sample>>,
+    pub slices: HashMap<String, Slice>,
+    /// The speed each sample or slice is played at, 1.0 if it isn't here.
+    pub speeds: HashMap<String, f32>,
 }
 
 impl Sample {
-    pub fn try_new(file: &Path) -> Option<Self> {
+    pub fn try_new(file: &Path) -> Result<Self, SkipReason> {
         let name = file.file_name().unwrap().to_str().unwrap();
-        let Ok(mut data) = hound::WavReader::open(file) else {
-            return None;
+        let (interleaved, spec, sample_rate) = match hound::WavReader::open(file) {
+            Ok(mut data) => {
+                let spec = data.spec();
+                let interleaved = decode(&mut data).ok_or(SkipReason::UnsupportedFormat)?;
+                (interleaved, spec.channels as usize, spec.sample_rate)
+            }
+            // not a wav file that hound can read, but it can be another format
+            Err(err) => decode_other(file).ok_or(match err {
+                hound::Error::Unsupported => SkipReason::UnsupportedFormat,
+                _ => SkipReason::Unreadable,
+            })?,
         };
-        let mut samples = vec![];
-
-        let spec = data.spec().channels;
-        let sample_rate = data.spec().sample_rate;
 
-        for s in data.samples().step_by(spec as usize) {
-            let s: i16 = s.unwrap();
-            let converted: f32 = s.to_sample();
-            samples.push(converted)
+        if interleaved.is_empty() {
+            return Err(SkipReason::Empty);
         }
+        let samples = downmix(&interleaved, spec);
+        let channels = match spec {
+            1 => Vec::new(),
+            _ => deinterleave(&interleaved, spec),
+        };
 
-        Some(Self {
+        Ok(Self {
             name: name.to_string(),
             data: samples,
+            channels,
             sample_rate,
+            root: OnceLock::new(),
         })
     }
+
+    /// Create a sample from data which is already in memory.
+    pub fn new(name: &str, data: Vec<f32>, sample_rate: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            data,
+            channels: Vec::new(),
+            sample_rate,
+            root: OnceLock::new(),
+        }
+    }
+
+    /// Declare the root note of this sample, instead of detecting it.
+    pub fn with_root(self, root: Note) -> Self {
+        let _ = self.root.set(Some(root));
+        self
+    }
+
+    /// The root note of this sample, which melodic playback transposes from.
+    ///
+    /// Unless it was declared, the pitch is detected the first time this is called.
+    /// Returns None for unpitched samples.
+    pub fn root(&self) -> Option<Note> {
+        *self
+            .root
+            .get_or_init(|| detect_pitch(&self.data, self.sample_rate).and_then(Note::from_freq))
+    }
+
+    /// The frame at `index`, with the left and right channel weighted by `balance`, from -1.0
+    /// (only left) to 1.0 (only right). Without a balance, or for mono samples, all channels are
+    /// averaged.
+    pub fn frame(&self, index: usize, balance: Option<f32>) -> Option<f32> {
+        match (balance, self.channels.as_slice()) {
+            (Some(balance), [left, right, ..]) => {
+                let balance = balance.clamp(-1.0, 1.0);
+                let left = left.get(index)? * (1.0 - balance) / 2.0;
+                let right = right.get(index)? * (1.0 + balance) / 2.0;
+                Some(left + right)
+            }
+            _ => self.data.get(index).copied(),
+        }
+    }
+
+    /// The frames of one channel, starting at 0 (the left channel of a stereo sample). A mono
+    /// sample only has channel 0.
+    pub fn channel(&self, channel: usize) -> Option<&[f32]> {
+        match self.channels.as_slice() {
+            [] if channel == 0 => Some(&self.data),
+            channels => channels.get(channel).map(Vec::as_slice),
+        }
+    }
+
+    /// The number of channels of the file this sample was loaded from.
+    pub fn channel_count(&self) -> usize {
+        self.channels.len().max(1)
+    }
+
+    /// The number of frames in this sample.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The sample rate the sample was recorded at.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The length of this sample in seconds.
+    pub fn duration_secs(&self) -> f32 {
+        self.len() as f32 / self.sample_rate as f32
+    }
+}
+
+/// Decode all samples of a wav file to f32 in [-1, 1], according to its sample format and bit
+/// depth. The samples stay interleaved.
+fn decode<R: std::io::Read>(reader: &mut hound::WavReader<R>) -> Option<Vec<f32>> {
+    let spec = reader.spec();
+
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Float, 32) => reader.samples::<f32>().collect::<Result<_, _>>().ok(),
+        (hound::SampleFormat::Int, 16) => reader
+            .samples::<i16>()
+            .map(|s| s.map(|s| s.to_sample::<f32>()))
+            .collect::<Result<_, _>>()
+            .ok(),
+        (hound::SampleFormat::Int, bits @ (8 | 24 | 32)) => {
+            let max = (1_i64 << (bits - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / max))
+                .collect::<Result<_, _>>()
+                .ok()
+        }
+        _ => None,
+    }
+}
+
+/// Decode a file in another format than wav (like flac or ogg) with symphonia, to interleaved f32
+/// samples in [-1, 1], with the number of channels and the sample rate.
+fn decode_other(file: &Path) -> Option<(Vec<f32>, usize, u32)> {
+    let source = fs::File::open(file).ok()?;
+    let stream = MediaSourceStream::new(Box::new(source), Default::default());
+
+    // the extension is only a hint, the format is detected from the content
+    let mut hint = Hint::new();
+    if let Some(extension) = file.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?
+        .format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)?;
+    let track_id = track.id;
+    let sample_rate = track.codec_params.sample_rate?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+
+    let mut interleaved = Vec::new();
+    let mut channels = 1;
+    // the end of the stream is an error too
+    while let Ok(packet) = format.next_packet() {
+        if packet.track_id() != track_id {
+            continue;
+        }
+        // a corrupt packet is skipped, the rest of the file can still be decoded
+        let Ok(decoded) = decoder.decode(&packet) else {
+            continue;
+        };
+
+        let spec = *decoded.spec();
+        channels = spec.channels.count();
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+        interleaved.extend_from_slice(buffer.samples());
+    }
+
+    Some((interleaved, channels, sample_rate))
+}
+
+/// Average all channels of interleaved data into a single channel.
+fn downmix(interleaved: &[f32], channels: usize) -> Vec<f32> {
+    interleaved
+        .chunks(channels.max(1))
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect()
+}
+
+/// Split interleaved data into one vec per channel.
+fn deinterleave(interleaved: &[f32], channels: usize) -> Vec<Vec<f32>> {
+    (0..channels)
+        .map(|channel| {
+            interleaved
+                .iter()
+                .skip(channel)
+                .step_by(channels)
+                .copied()
+                .collect()
+        })
+        .collect()
+}
+
+impl SampleSet {
+    pub fn new(samples: HashMap<String, Arc<Sample>>) -> Self {
+        Self {
+            samples,
+            slices: HashMap::new(),
+            speeds: HashMap::new(),
+        }
+    }
+
+    /// A player for the sample or slice with the given name, at its speed.
+    pub fn player(&self, name: &str) -> Option<SamplePlayer> {
+        let player = match self.slices.get(name) {
+            Some(slice) => {
+                let sample = self.samples.get(&slice.sample)?;
+                SamplePlayer::new(sample.clone()).with_range(slice.start, slice.end)
+            }
+            None => SamplePlayer::new(self.samples.get(name)?.clone()),
+        };
+
+        Some(player.scale_speed(self.speeds.get(name).copied().unwrap_or(1.0)))
+    }
+
+    /// Whether there is a sample or slice with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.samples.contains_key(name) || self.slices.contains_key(name)
+    }
+
+    /// Add a sample to the set, replacing the one with the same name.
+    ///
+    /// Players which hold the previous sample keep playing it, only players created (or updated)
+    /// afterwards use the new one.
+    pub fn insert(&mut self, name: &str, sample: Sample) -> Arc<Sample> {
+        let sample = Arc::new(sample);
+        self.samples.insert(name.to_string(), sample.clone());
+        sample
+    }
+
+    /// All samples which don't match the output sample rate, sorted by name.
+    pub fn rate_mismatches(&self, output_rate: u32) -> Vec<RateMismatch> {
+        let mut mismatches: Vec<RateMismatch> = self
+            .samples
+            .iter()
+            .filter(|(_, sample)| sample.sample_rate != output_rate)
+            .map(|(name, sample)| RateMismatch {
+                sample: name.to_string(),
+                sample_rate: sample.sample_rate,
+                output_rate,
+            })
+            .collect();
+
+        mismatches.sort_by(|a, b| a.sample.cmp(&b.sample));
+        mismatches
+    }
+
+    /// Reload a single sample file, e.g. after it was changed on disk.
+    pub fn reload(&mut self, file: &Path) -> Option<Arc<Sample>> {
+        let name = file.file_stem()?.to_str()?.to_string();
+        let sample = Sample::try_new(file).ok()?;
+        Some(self.insert(&name, sample))
+    }
+}
+
+/// Lowest and highest fundamental (in Hz) the pitch detection looks for.
+const PITCH_RANGE: (f32, f32) = (40.0, 2000.0);
+
+/// Estimate the fundamental frequency of the start of a sample, using autocorrelation.
+fn detect_pitch(data: &[f32], sample_rate: u32) -> Option<f64> {
+    let min_lag = (sample_rate as f32 / PITCH_RANGE.1) as usize;
+    let max_lag = ((sample_rate as f32 / PITCH_RANGE.0) as usize).min(data.len() / 2);
+    if max_lag <= min_lag + 1 {
+        return None;
+    }
+
+    // only analyse the start of the sample, that is where it is the loudest
+    let window = (data.len() - max_lag).min(4096);
+    let energy: f32 = data[..window].iter().map(|s| s * s).sum();
+    if energy < 1e-6 {
+        return None;
+    }
+
+    let correlation: Vec<f32> = (0..=max_lag)
+        .map(|lag| {
+            let sum: f32 = (0..window).map(|i| data[i] * data[i + lag]).sum();
+            sum / energy
+        })
+        .collect();
+
+    // the first lag that comes close to the best one is the period, later ones are multiples
+    let best = correlation[min_lag..]
+        .iter()
+        .cloned()
+        .fold(f32::MIN, f32::max);
+    if best < 0.5 {
+        return None;
+    }
+    let mut lag = (min_lag..max_lag).find(|lag| correlation[*lag] > 0.9 * best)?;
+    while lag < max_lag && correlation[lag + 1] > correlation[lag] {
+        lag += 1;
+    }
+
+    // parabolic interpolation around the peak
+    let (a, b, c) = (correlation[lag - 1], correlation[lag], correlation[lag + 1]);
+    let offset = 0.5 * (a - c) / (a - 2.0 * b + c);
+    let period = lag as f32 + if offset.is_finite() { offset } else { 0.0 };
+
+    Some(sample_rate as f64 / period as f64)
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Unreadable => write!(f, "could not open it as a wav file"),
+            SkipReason::UnsupportedFormat => write!(f, "its sample format is not supported"),
+            SkipReason::Empty => write!(f, "it is empty"),
+        }
+    }
+}
+
+impl Display for SkippedSample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "skipped sample {}: {}", self.file.display(), self.reason)
+    }
+}
+
+impl Display for RateMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sample {} is recorded at {} Hz but plays at {} Hz, resample it beforehand for the best quality",
+            self.sample, self.sample_rate, self.output_rate
+        )
+    }
 }
 
 fn interpolate(a: f32, b: f32, t: f32) -> f32 {
@@ -54,35 +495,80 @@ fn interpolate(a: f32, b: f32, t: f32) -> f32 {
 }
 
 impl SamplePlayer {
-    pub fn get_sample(&mut self, time: u128, sample_rate: u32) -> f32 {
-        // NOTE: there may be better ways to interpolate than just linear interpolation
-
+    /// The (fractional) index into the sample data at the given time.
+    fn index(&self, time: u128, sample_rate: u32) -> f32 {
         // index of the destination sample rate
         let mut index = (time as i128 - self.start as i128) as f32 * self.speed;
         // if the sample rate is different, we need to adjust the index
         if sample_rate != self.sample.sample_rate {
             index *= self.sample.sample_rate as f32 / sample_rate as f32;
         }
+        index
+    }
+
+    /// The frames of the sample which this player plays, as [start, end).
+    fn bounds(&self) -> (usize, usize) {
+        let len = self.sample.len();
+        match self.range {
+            Some((start, end)) => {
+                let end = end.min(len);
+                (start.min(end), end)
+            }
+            None => (0, len),
+        }
+    }
+
+    /// The frame at `index` of the played part of the sample, counted from its end if it is
+    /// played backwards.
+    fn frame(&self, index: usize) -> Option<f32> {
+        let (start, end) = self.bounds();
+        if start + index >= end {
+            return None;
+        }
+        let index = match self.reverse {
+            true => end - start - 1 - index,
+            false => index,
+        };
+        match self.channel {
+            Some(channel) => self.sample.channel(channel)?.get(start + index).copied(),
+            None => self.sample.frame(start + index, self.balance),
+        }
+    }
+
+    /// The frame at `index` of the played part of the sample, resolved by the boundary policy if
+    /// it lies past the end.
+    fn frame_at_boundary(&self, index: usize) -> f32 {
+        let (start, end) = self.bounds();
+        let len = end - start;
+        if index < len || len == 0 {
+            return self.frame(index).unwrap_or(0.0);
+        }
+
+        match self.boundary {
+            Boundary::Zero => 0.0,
+            Boundary::Clamp => self.frame(len - 1).unwrap_or(0.0),
+            Boundary::Wrap => self.frame(index % len).unwrap_or(0.0),
+        }
+    }
+
+    pub fn get_sample(&mut self, time: u128, sample_rate: u32) -> f32 {
+        // NOTE: there may be better ways to interpolate than just linear interpolation
+
+        let index = self.index(time, sample_rate);
 
         // index at this point is a float, so we need to interpolate between two samples, which we
         // will call low and high
         let index_low = index.floor() as usize;
         let t = index.fract();
 
-        let low = match self.sample.data.get(index_low) {
-            Some(s) => *s,
-            None => 0.0,
-        };
+        let low = self.frame_at_boundary(self.looped(index_low));
 
         // no need to interpolate if the target time falls on the grid
         if t < 0.0001 {
             return low;
         }
 
-        let high = match self.sample.data.get(index_low + 1) {
-            Some(s) => *s,
-            None => 0.0,
-        };
+        let high = self.frame_at_boundary(self.looped(index_low + 1));
 
         // no need to interpolate if the target time falls on the grid
         if t > 0.9999 {
@@ -97,11 +583,618 @@ impl SamplePlayer {
         self.start = time;
     }
 
+    /// The index into the played part, wrapped back to the loop start once it is past the end.
+    fn looped(&self, index: usize) -> usize {
+        let (start, end) = self.bounds();
+        let len = end - start;
+        match self.loop_start {
+            Some(loop_start) if index >= len && loop_start < len => {
+                loop_start + (index - loop_start) % (len - loop_start)
+            }
+            _ => index,
+        }
+    }
+
+    /// Whether this player loops, so it never finishes.
+    pub fn loops(&self) -> bool {
+        let (start, end) = self.bounds();
+        self.loop_start
+            .is_some_and(|loop_start| loop_start < end - start)
+    }
+
+    /// Whether the whole sample has been played at the given time.
+    pub fn is_finished(&self, time: u128, sample_rate: u32) -> bool {
+        if self.loops() {
+            return false;
+        }
+        let (start, end) = self.bounds();
+        self.index(time, sample_rate) >= (end - start) as f32
+    }
+
     pub fn new(sample: Arc<Sample>) -> Self {
         Self {
             sample,
             start: 0,
-            speed: 0.8,
+            speed: 1.0,
+            range: None,
+            balance: None,
+            channel: None,
+            reverse: false,
+            boundary: Boundary::default(),
+            loop_start: None,
+        }
+    }
+
+    /// Choose what the last frames are interpolated with.
+    pub fn with_boundary(mut self, boundary: Boundary) -> Self {
+        self.boundary = boundary;
+        self
+    }
+
+    /// Loop the played part of the sample, continuing from its frame `start` once it reaches its
+    /// end. None plays it once.
+    pub fn with_loop(mut self, start: Option<usize>) -> Self {
+        self.loop_start = start;
+        self
+    }
+
+    /// Only play the frames from `start` up to `end` of the sample.
+    pub fn with_range(mut self, start: usize, end: usize) -> Self {
+        self.range = Some((start, end));
+        self
+    }
+
+    /// Repitch the sample to `note`, relative to its root note, so the root plays at the speed it
+    /// had. Unpitched samples are left as they are.
+    pub fn with_pitch(mut self, note: Note) -> Self {
+        if let Some(root) = self.sample.root() {
+            self.speed *= (note.to_freq() / root.to_freq()) as f32;
+        }
+        self
+    }
+
+    /// Play the sample `speed` times as fast (and as high) as it plays now, on top of its pitch.
+    pub fn scale_speed(mut self, speed: f32) -> Self {
+        self.speed *= speed;
+        self
+    }
+
+    /// The playback speed, 1.0 plays the sample at its original pitch.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Play the sample backwards, the speed, range and loop apply to the reversed sample.
+    pub fn with_reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    /// Only play one channel of the sample (0 is left, 1 is right), instead of summing them.
+    pub fn with_channel(mut self, channel: usize) -> Self {
+        self.channel = Some(channel);
+        self
+    }
+
+    /// Sum the channels of a stereo sample with a balance, from -1.0 (only left) to 1.0 (only
+    /// right), instead of averaging them.
+    pub fn with_balance(mut self, balance: f32) -> Self {
+        self.balance = Some(balance.clamp(-1.0, 1.0));
+        self
+    }
+}
+
+impl VoicePool {
+    pub fn new(max_voices: usize, policy: StealPolicy) -> Self {
+        Self {
+            voices: Vec::new(),
+            max_voices: max_voices.max(1),
+            policy,
+        }
+    }
+
+    /// Start a new voice playing the given sample at the given gain, stealing a voice if the pool
+    /// is full.
+    pub fn trigger(&mut self, player: &SamplePlayer, time: u128, gain: f32) {
+        if self.voices.len() >= self.max_voices {
+            self.steal();
+        }
+
+        let mut player = player.clone();
+        player.hit(time);
+        self.voices.push(Voice {
+            player,
+            level: 0.0,
+            gain,
+            release: None,
+        });
+    }
+
+    /// Release the voices which were triggered at `start` at the time `release`, after which they
+    /// fade out in a few milliseconds.
+    pub fn release_started_at(&mut self, start: u128, release: u128) {
+        self.voices
+            .iter_mut()
+            .filter(|voice| voice.player.start == start)
+            .for_each(|voice| voice.release = Some(release));
+    }
+
+    fn steal(&mut self) {
+        let index = match self.policy {
+            StealPolicy::Oldest => 0,
+            StealPolicy::Quietest => self
+                .voices
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1.level.total_cmp(&b.1.level))
+                .map_or(0, |(i, _)| i),
+        };
+
+        self.voices.remove(index);
+    }
+
+    /// Sum of all sounding voices, voices that have finished playing are removed.
+    pub fn get_sample(&mut self, time: u128, sample_rate: u32) -> f32 {
+        let fade = (RELEASE_MS * sample_rate as f32 / 1000.0).max(1.0);
+        // how far the voice has faded out, 1.0 once it is silent
+        let faded = |voice: &Voice| {
+            voice.release.map_or(0.0, |release| {
+                (time.saturating_sub(release) as f32 / fade).min(1.0)
+            })
+        };
+
+        self.voices
+            .retain(|voice| !voice.player.is_finished(time, sample_rate) && faded(voice) < 1.0);
+
+        let mut sample = 0.0;
+        for voice in self.voices.iter_mut() {
+            let s = voice.player.get_sample(time, sample_rate) * voice.gain * (1.0 - faded(voice));
+            voice.level = s.abs().max(voice.level * 0.999);
+            sample += s;
         }
+
+        sample
+    }
+
+    /// The voices which are sounding, from oldest to newest.
+    pub fn players(&self) -> impl Iterator<Item = &SamplePlayer> {
+        self.voices.iter().map(|voice| &voice.player)
+    }
+
+    /// The voices which were triggered at `start`.
+    pub fn players_started_at(&self, start: u128) -> impl Iterator<Item = &SamplePlayer> {
+        self.players().filter(move |player| player.start == start)
+    }
+
+    pub fn len(&self) -> usize {
+        self.voices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.voices.is_empty()
+    }
+
+    /// Move the voices from the time `from` to `to`, so they play on from where they were, e.g.
+    /// when the tempo changes.
+    pub fn shift(&mut self, from: u128, to: u128) {
+        let shift = |time: u128| (time + to).saturating_sub(from);
+        for voice in self.voices.iter_mut() {
+            voice.player.start = shift(voice.player.start);
+            voice.release = voice.release.map(shift);
+        }
+    }
+
+    /// Stop all voices.
+    pub fn clear(&mut self) {
+        self.voices.clear();
+    }
+
+    pub fn set_max_voices(&mut self, max_voices: usize) {
+        self.max_voices = max_voices.max(1);
+        while self.voices.len() > self.max_voices {
+            self.steal();
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: StealPolicy) {
+        self.policy = policy;
+    }
+}
+
+impl TryFrom<&str> for Boundary {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "zero" => Ok(Self::Zero),
+            "clamp" => Ok(Self::Clamp),
+            "wrap" => Ok(Self::Wrap),
+            _ => Err("Invalid boundary"),
+        }
+    }
+}
+
+impl TryFrom<&str> for SampleLength {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "natural" => Ok(Self::Natural),
+            "step" => Ok(Self::Step),
+            _ => Err("Invalid sample length"),
+        }
+    }
+}
+
+impl TryFrom<&str> for StealPolicy {
+    type Error = &'static str;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "oldest" => Ok(Self::Oldest),
+            "quietest" => Ok(Self::Quietest),
+            _ => Err("Invalid steal policy"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chromatic::{Octave, PitchClass};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn swap_keeps_old_players() {
+        let mut set = SampleSet::new(HashMap::new());
+        let old = set.insert("kick", Sample::new("kick.wav", vec![1.0; 16], 48000));
+        let player = SamplePlayer::new(old.clone());
+
+        let new = set.insert("kick", Sample::new("kick.wav", vec![0.5; 16], 48000));
+
+        assert!(Arc::ptr_eq(&player.sample, &old));
+        assert!(Arc::ptr_eq(&set.samples["kick"], &new));
+    }
+
+    #[test]
+    fn steal_oldest_voice() {
+        let mut pool = VoicePool::new(2, StealPolicy::Oldest);
+
+        for (time, name) in ["a", "b", "c"].iter().enumerate() {
+            let sample = Arc::new(Sample::new(name, vec![1.0; 1000], 48000));
+            pool.trigger(&SamplePlayer::new(sample), time as u128, 1.0);
+        }
+        pool.get_sample(3, 48000);
+
+        let names: Vec<&str> = pool.players().map(|p| p.sample.name.as_str()).collect();
+        assert_eq!(names, ["b", "c"]);
+    }
+
+    #[test]
+    fn players_started_at_time() {
+        let mut pool = VoicePool::new(4, StealPolicy::Oldest);
+        let sample = Arc::new(Sample::new("a", vec![1.0; 1000], 48000));
+        let looped = SamplePlayer::new(sample.clone()).with_loop(Some(0));
+
+        pool.trigger(&looped, 0, 1.0);
+        pool.trigger(&looped, 1, 1.0);
+        pool.trigger(&SamplePlayer::new(sample), 1, 1.0);
+
+        // the last voice doesn't loop, but one of the others which started with it does
+        assert!(!pool.players().last().unwrap().loops());
+        assert_eq!(pool.players_started_at(1).count(), 2);
+        assert!(pool.players_started_at(1).any(SamplePlayer::loops));
+        assert_eq!(pool.players_started_at(2).count(), 0);
+    }
+
+    #[test]
+    fn detect_sine_pitch() {
+        let sine = (0..48000)
+            .map(|i| (2.0 * std::f32::consts::PI * 440.0 * i as f32 / 48000.0).sin())
+            .collect();
+        let sample = Sample::new("sine.wav", sine, 48000);
+
+        let freq = detect_pitch(&sample.data, sample.sample_rate).unwrap();
+        assert!((freq - 440.0).abs() < 2.0, "Detected {} Hz", freq);
+        assert_eq!(sample.root(), Some(Note::new(PitchClass::A, Octave::Four)));
+
+        let declared = Sample::new("sine.wav", vec![0.0; 16], 48000)
+            .with_root(Note::from_freq(262.0).unwrap());
+        assert_eq!(declared.root(), Note::from_freq(262.0));
+    }
+
+    #[test]
+    fn decode_bit_depths() {
+        let expected = [0.0, 0.5, -0.5, 0.25, -0.25, 0.75, -0.75, 0.125];
+
+        for file in ["testdata/formats/pcm24.wav", "testdata/formats/float32.wav"] {
+            let sample = Sample::try_new(Path::new(file)).unwrap();
+
+            assert_eq!(
+                sample.len(),
+                expected.len(),
+                "{} has the wrong length",
+                file
+            );
+            for (s, e) in sample.data.iter().zip(expected) {
+                assert!(
+                    (s - e).abs() < 1e-6,
+                    "{} decoded {} instead of {}",
+                    file,
+                    s,
+                    e
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decode_flac_and_ogg() {
+        let flac = Sample::try_new(Path::new("testdata/formats/stereo.flac")).unwrap();
+        let ogg = Sample::try_new(Path::new("testdata/formats/mono.ogg")).unwrap();
+
+        assert_eq!(
+            (flac.len(), flac.sample_rate(), flac.channel_count()),
+            (1000, 44100, 2)
+        );
+        assert_eq!(
+            (ogg.len(), ogg.sample_rate(), ogg.channel_count()),
+            (800, 48000, 1)
+        );
+
+        // both hold a 440 Hz sine, at half scale (and a quarter in the right channel of the flac)
+        let sine = |i: usize, rate: f32| (2.0 * PI * 440.0 * i as f32 / rate).sin();
+        for i in [10, 20, 30] {
+            let left = flac.channel(0).unwrap()[i];
+            assert!(
+                (left - 0.5 * sine(i, 44100.0)).abs() < 1e-3,
+                "Left is {}",
+                left
+            );
+            assert!((flac.data[i] - 0.375 * sine(i, 44100.0)).abs() < 1e-3);
+            assert!((ogg.data[i] - 0.5 * sine(i, 48000.0)).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn stereo_is_averaged() {
+        let sample = Sample::try_new(Path::new("testdata/formats/stereo16.wav")).unwrap();
+
+        assert_eq!(sample.len(), 4);
+        assert!(
+            (sample.data[0] - 0.125).abs() < 1e-4,
+            "Averaging 0.5 and -0.25 gave {}",
+            sample.data[0]
+        );
+        assert!((sample.data[1] - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn reversed_ramp_descends() {
+        let ramp = Arc::new(Sample::new(
+            "ramp.wav",
+            vec![0.0, 0.25, 0.5, 0.75, 1.0],
+            48000,
+        ));
+
+        let mut reversed = SamplePlayer::new(ramp.clone()).with_reverse(true);
+        let output: Vec<f32> = (0..5)
+            .map(|time| reversed.get_sample(time, 48000))
+            .collect();
+        assert_eq!(output, [1.0, 0.75, 0.5, 0.25, 0.0]);
+
+        // at half speed, the frames in between are still interpolated
+        let mut slow = SamplePlayer::new(ramp.clone())
+            .with_reverse(true)
+            .scale_speed(0.5);
+        assert!((slow.get_sample(1, 48000) - 0.875).abs() < 1e-6);
+
+        // a reversed loop repeats the reversed frames
+        let mut looped = SamplePlayer::new(ramp)
+            .with_reverse(true)
+            .with_loop(Some(3));
+        let output: Vec<f32> = (5..9).map(|time| looped.get_sample(time, 48000)).collect();
+        assert_eq!(output, [0.25, 0.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn channel_one_is_right() {
+        let sample = Arc::new(Sample::try_new(Path::new("testdata/formats/stereo16.wav")).unwrap());
+        let mut player = SamplePlayer::new(sample.clone()).with_channel(1);
+
+        let right = [-0.25, 0.75, 0.0, 1.0];
+        for (time, expected) in right.iter().enumerate() {
+            let output = player.get_sample(time as u128, sample.sample_rate());
+            assert!(
+                (output - expected).abs() < 1e-4,
+                "Frame {} is {} instead of the right channel's {}",
+                time,
+                output,
+                expected
+            );
+        }
+        assert_eq!(sample.channel_count(), 2);
+        assert!(sample.channel(2).is_none());
+    }
+
+    #[test]
+    fn both_channels_reach_output() {
+        let sample = Arc::new(Sample::try_new(Path::new("testdata/formats/stereo16.wav")).unwrap());
+        let output = |mut player: SamplePlayer| -> Vec<f32> {
+            (0..4).map(|time| player.get_sample(time, 48000)).collect()
+        };
+
+        let mixed = output(SamplePlayer::new(sample.clone()));
+        let left = output(SamplePlayer::new(sample.clone()).with_channel(0));
+        let right = output(SamplePlayer::new(sample).with_channel(1));
+
+        // the output follows both channels, not just the first one
+        assert_ne!(mixed, left);
+        assert_ne!(mixed, right);
+        for ((mixed, left), right) in mixed.iter().zip(&left).zip(&right) {
+            assert!((mixed - (left + right) / 2.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn default_speed_reads_every_frame() {
+        let sample = Arc::new(Sample::new("ramp.wav", vec![0.1, 0.2, 0.3, 0.4], 48000));
+        let mut player = SamplePlayer::new(sample);
+
+        assert_eq!(player.speed(), 1.0);
+        for (time, expected) in [0.1, 0.2, 0.3, 0.4].iter().enumerate() {
+            assert_eq!(player.index(time as u128, 48000), time as f32);
+            assert_eq!(player.get_sample(time as u128, 48000), *expected);
+        }
+        assert_eq!(player.scale_speed(0.5).speed(), 0.5);
+    }
+
+    #[test]
+    fn sample_speed_applies_to_players() {
+        let mut set = SampleSet::new(HashMap::new());
+        set.insert(
+            "pluck",
+            Sample::new("pluck.wav", vec![0.0; 16], 48000)
+                .with_root(Note::new(PitchClass::C, Octave::Three)),
+        );
+        set.speeds.insert("pluck".to_string(), 0.5);
+
+        let player = set.player("pluck").unwrap();
+        assert_eq!(player.speed(), 0.5);
+        // a mapped pitch and speed come on top of it
+        let octave = player.with_pitch(Note::new(PitchClass::C, Octave::Four));
+        assert!((octave.speed() - 1.0).abs() < 1e-6);
+        assert_eq!(octave.scale_speed(0.5).speed(), 0.5);
+    }
+
+    #[test]
+    fn looped_sample_keeps_playing() {
+        let sample = Arc::new(Sample::new("blip.wav", vec![0.5, 1.0, 0.25, 0.75], 48000));
+        let mut player = SamplePlayer::new(sample).with_loop(Some(1));
+
+        // the first frame plays once, then frames 1 to 3 repeat
+        let output: Vec<f32> = (0..10).map(|time| player.get_sample(time, 48000)).collect();
+        assert_eq!(
+            output,
+            [0.5, 1.0, 0.25, 0.75, 1.0, 0.25, 0.75, 1.0, 0.25, 0.75]
+        );
+
+        assert!(!player.is_finished(48000, 48000));
+        assert!(player.get_sample(48000, 48000) != 0.0);
+    }
+
+    #[test]
+    fn clamp_repeats_edge_frame() {
+        let sample = Arc::new(Sample::new("edge.wav", vec![0.2, 0.4, 1.0], 48000));
+        let halfway_past_end = |boundary| {
+            let mut player = SamplePlayer::new(sample.clone()).with_boundary(boundary);
+            player.speed = 0.5;
+            // index 2.5, between the last frame and the one after it
+            player.get_sample(5, 48000)
+        };
+
+        assert_eq!(halfway_past_end(Boundary::Clamp), 1.0);
+        assert_eq!(halfway_past_end(Boundary::Zero), 0.5);
+        assert!((halfway_past_end(Boundary::Wrap) - 0.6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn balance_left_plays_left_channel() {
+        let sample = Arc::new(Sample::try_new(Path::new("testdata/formats/stereo16.wav")).unwrap());
+        let mut player = SamplePlayer::new(sample.clone()).with_balance(-1.0);
+        player.speed = 1.0;
+
+        let left = [0.5, 0.25, -0.5, 0.0];
+        for (time, expected) in left.iter().enumerate() {
+            let output = player.get_sample(time as u128, sample.sample_rate());
+            assert!(
+                (output - expected).abs() < 1e-4,
+                "Frame {} is {} instead of the left channel's {}",
+                time,
+                output,
+                expected
+            );
+        }
+
+        // without a balance, the channels are still averaged
+        assert_eq!(sample.frame(0, None), Some(sample.data[0]));
+    }
+
+    #[test]
+    fn octave_up_doubles_speed() {
+        let sample = Arc::new(
+            Sample::new("pluck.wav", vec![0.0; 16], 48000)
+                .with_root(Note::new(PitchClass::C, Octave::Three)),
+        );
+
+        let root =
+            SamplePlayer::new(sample.clone()).with_pitch(Note::new(PitchClass::C, Octave::Three));
+        let octave =
+            SamplePlayer::new(sample.clone()).with_pitch(Note::new(PitchClass::C, Octave::Four));
+
+        assert!((root.speed() - 1.0).abs() < 1e-6);
+        assert!(
+            (octave.speed() - 2.0 * root.speed()).abs() < 1e-6,
+            "An octave up plays at {} instead of {}",
+            octave.speed(),
+            2.0 * root.speed()
+        );
+
+        // without a root, the pitch can't be mapped
+        let unpitched = Arc::new(Sample::new("noise.wav", vec![0.0; 16], 48000));
+        let player = SamplePlayer::new(unpitched.clone());
+        assert_eq!(
+            player
+                .clone()
+                .with_pitch(Note::new(PitchClass::C, Octave::Four))
+                .speed(),
+            player.speed()
+        );
+    }
+
+    #[test]
+    fn sliced_player_stays_in_range() {
+        let mut set = SampleSet::new(HashMap::new());
+        set.insert(
+            "kit",
+            Sample::new("kit.wav", (0..10).map(|i| i as f32).collect(), 48000),
+        );
+        set.slices.insert(
+            "tom".to_string(),
+            Slice {
+                sample: "kit".to_string(),
+                start: 4,
+                end: 6,
+            },
+        );
+
+        let mut tom = set.player("tom").unwrap();
+        let output: Vec<f32> = (0..20).map(|time| tom.get_sample(time, 48000)).collect();
+
+        assert_eq!(output[0], 4.0);
+        assert!(
+            output.iter().all(|s| *s <= 5.0),
+            "Slice read outside its range: {:?}",
+            output
+        );
+        assert!(tom.is_finished(3, 48000));
+    }
+
+    #[test]
+    fn sample_duration() {
+        let sample = Sample::new(
+            "ramp",
+            (0..22050).map(|i| i as f32 / 22050.0).collect(),
+            44100,
+        );
+
+        assert_eq!(sample.len(), 22050);
+        assert_eq!(sample.sample_rate(), 44100);
+        assert!(
+            (sample.duration_secs() - sample.len() as f32 / sample.sample_rate() as f32).abs()
+                < f32::EPSILON,
+            "Duration is not len/sample_rate, but {}",
+            sample.duration_secs()
+        );
     }
 }
@@ -0,0 +1,405 @@
+/*!
+* Where the breaker source code is read from: a file, or stdin.
+*
+* Other files can be spliced into the source with `include "common.br"` lines, a [LineMap] tells
+* which file each line of the spliced source came from.
+*
+* Also keeps the syntax tree of the source, which is reparsed incrementally when it changes.
+*/
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use tree_sitter::{InputEdit, Parser, Point, Tree};
+
+use crate::diagnostic::Diagnostic;
+
+/// The input of the interpreter, as given on the command line.
+#[derive(Debug, PartialEq, Clone)]
+pub enum InputSource {
+    /// Read the patch from stdin, given as `-`.
+    Stdin,
+    /// Read the patch from a file, which is watched for changes.
+    File(PathBuf),
+}
+
+impl From<&str> for InputSource {
+    fn from(arg: &str) -> Self {
+        match arg {
+            "-" => Self::Stdin,
+            path => Self::File(PathBuf::from(path)),
+        }
+    }
+}
+
+/// The file and line each line of the spliced source came from.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct LineMap {
+    // the included files, which the lines index into (None is the input itself)
+    includes: Vec<PathBuf>,
+    // the file and line (starting at 1) of each spliced line
+    lines: Vec<(Option<usize>, usize)>,
+}
+
+impl LineMap {
+    /// The included file and line within it of a line (starting at 1) of the spliced source,
+    /// the file is None for lines of the input itself.
+    pub fn locate(&self, line: usize) -> Option<(Option<&Path>, usize)> {
+        let (file, line) = self.lines.get(line.checked_sub(1)?)?;
+        Some((file.map(|file| self.includes[file].as_path()), *line))
+    }
+
+    /// Point a diagnostic in the spliced source at the file and line it is in.
+    pub fn relocate(&self, diagnostic: &Diagnostic) -> Diagnostic {
+        let mut relocated = diagnostic.clone();
+        if let Some((file, line)) = self.locate(diagnostic.line) {
+            relocated.file = file.map(Path::to_path_buf);
+            relocated.line = line;
+        }
+        relocated
+    }
+
+    /// The files which were spliced into the input, which change the source when they change.
+    pub fn includes(&self) -> &[PathBuf] {
+        &self.includes
+    }
+}
+
+impl InputSource {
+    /// Read the whole source code, with the included files spliced in, and where each of its
+    /// lines came from.
+    ///
+    /// Includes are relative to the file, or to the working directory for stdin.
+    pub fn read(&self) -> io::Result<(String, LineMap)> {
+        let mut lines = LineMap::default();
+        let source = match self {
+            Self::Stdin => {
+                let mut source = String::new();
+                io::stdin().read_to_string(&mut source)?;
+                splice_includes(&source, Path::new("."), &mut Vec::new(), &mut lines)?
+            }
+            Self::File(path) => {
+                let source = fs::read_to_string(path)?;
+                let path = fs::canonicalize(path)?;
+                let dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+                splice_includes(&source, &dir, &mut vec![path], &mut lines)?
+            }
+        };
+
+        Ok((source, lines))
+    }
+
+    /// The file to watch for changes, stdin can't change once it is read.
+    pub fn watch_path(&self) -> Option<&Path> {
+        match self {
+            Self::Stdin => None,
+            Self::File(path) => Some(path),
+        }
+    }
+}
+
+/// The file named by an `include "file.br"` line, if the line is one.
+fn include_path(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix("include")?;
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    rest.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Replace the include lines of `source` with the (spliced) files they name, relative to `dir`.
+///
+/// `stack` holds the files which are being spliced, an include of one of them is a cycle. The
+/// origin of every spliced line is added to `lines`.
+fn splice_includes(
+    source: &str,
+    dir: &Path,
+    stack: &mut Vec<PathBuf>,
+    lines: &mut LineMap,
+) -> io::Result<String> {
+    let mut spliced = String::with_capacity(source.len());
+    // the input itself isn't one of the includes
+    let origin = stack
+        .last()
+        .and_then(|current| lines.includes.iter().position(|file| file == current));
+
+    for (number, line) in source.split_inclusive('\n').enumerate() {
+        let Some(file) = include_path(line) else {
+            spliced.push_str(line);
+            lines.lines.push((origin, number + 1));
+            continue;
+        };
+
+        let context = |err: io::Error| io::Error::new(err.kind(), format!("{}: {}", file, err));
+        let path = fs::canonicalize(dir.join(file)).map_err(context)?;
+        if stack.contains(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} includes itself", file),
+            ));
+        }
+
+        let included = fs::read_to_string(&path).map_err(context)?;
+        let included_dir = path.parent().unwrap_or(dir).to_path_buf();
+
+        if !lines.includes.contains(&path) {
+            lines.includes.push(path.clone());
+        }
+        stack.push(path);
+        spliced.push_str(&splice_includes(&included, &included_dir, stack, lines)?);
+        stack.pop();
+
+        if !spliced.is_empty() && !spliced.ends_with('\n') {
+            spliced.push('\n');
+        }
+    }
+
+    Ok(spliced)
+}
+
+/// The position of a byte offset in the text, as tree-sitter counts it.
+fn point_at(text: &str, byte: usize) -> Point {
+    let before = &text.as_bytes()[..byte];
+    let row = before.iter().filter(|b| **b == b'\n').count();
+    let column = match before.iter().rposition(|b| *b == b'\n') {
+        Some(newline) => byte - newline - 1,
+        None => byte,
+    };
+
+    Point::new(row, column)
+}
+
+/// The single edit which turns `old` into `new`, None if they are the same.
+///
+/// The edit spans everything between the common prefix and the common suffix of both texts.
+pub fn diff_edit(old: &str, new: &str) -> Option<InputEdit> {
+    if old == new {
+        return None;
+    }
+
+    let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+
+    let mut start = old_bytes
+        .iter()
+        .zip(new_bytes)
+        .take_while(|(a, b)| a == b)
+        .count();
+    // edits start on a character boundary
+    while !old.is_char_boundary(start) || !new.is_char_boundary(start) {
+        start -= 1;
+    }
+
+    let max_suffix = old_bytes.len().min(new_bytes.len()) - start;
+    let mut suffix = old_bytes
+        .iter()
+        .rev()
+        .zip(new_bytes.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+
+    let old_end_byte = old.len() - suffix;
+    let new_end_byte = new.len() - suffix;
+
+    Some(InputEdit {
+        start_byte: start,
+        old_end_byte,
+        new_end_byte,
+        start_position: point_at(old, start),
+        old_end_position: point_at(old, old_end_byte),
+        new_end_position: point_at(new, new_end_byte),
+    })
+}
+
+/// Tree-sitter returned no tree at all, because it has no language, timed out or was cancelled.
+///
+/// Syntax errors don't cause this, they end up as error nodes in the tree.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ParseError;
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tree-sitter could not parse the source")
+    }
+}
+
+impl Error for ParseError {}
+
+/// A parser which keeps the last tree, so changed source is reparsed incrementally.
+pub struct IncrementalParser {
+    parser: Parser,
+    source: String,
+    tree: Tree,
+}
+
+impl IncrementalParser {
+    /// Parse the initial source.
+    pub fn new(mut parser: Parser, source: String) -> Result<Self, ParseError> {
+        let tree = parser.parse(&source, None).ok_or(ParseError)?;
+
+        Ok(Self {
+            parser,
+            source,
+            tree,
+        })
+    }
+
+    /// Reparse after the source changed, reusing the unchanged parts of the previous tree.
+    ///
+    /// Returns whether the source changed, so the pipeline only has to be rebuilt if it did
+    /// (editors often write the file without changing it). If parsing fails, the previous tree
+    /// and source are kept.
+    pub fn update(&mut self, source: String) -> Result<bool, ParseError> {
+        let Some(edit) = diff_edit(&self.source, &source) else {
+            return Ok(false);
+        };
+
+        let mut old_tree = self.tree.clone();
+        old_tree.edit(&edit);
+
+        let tree = self
+            .parser
+            .parse(&source, Some(&old_tree))
+            .ok_or(ParseError)?;
+        log::debug!(
+            "Reparsed, {} syntax ranges changed",
+            old_tree.changed_ranges(&tree).len()
+        );
+
+        self.tree = tree;
+        self.source = source;
+        Ok(true)
+    }
+
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser() -> Parser {
+        let mut parser = Parser::new();
+        parser
+            .set_language(tree_sitter_breaker::language())
+            .unwrap();
+        parser
+    }
+
+    #[test]
+    fn dash_is_stdin() {
+        assert_eq!(InputSource::from("-"), InputSource::Stdin);
+        assert_eq!(InputSource::from("-").watch_path(), None);
+
+        let file = InputSource::from("beat.br");
+        assert_eq!(file, InputSource::File(PathBuf::from("beat.br")));
+        assert_eq!(file.watch_path(), Some(Path::new("beat.br")));
+    }
+
+    #[test]
+    fn included_grid_is_available() {
+        let (source, _) = InputSource::from("testdata/include_test.br")
+            .read()
+            .unwrap();
+        assert!(source.contains("grid shared"));
+
+        let tree = parser().parse(&source, None).unwrap();
+        let (pipeline, _) = crate::pipeline::Pipeline::from_tree(&tree, &source, None).unwrap();
+        assert!(pipeline.playables.contains_key("shared"));
+        assert!(pipeline.playables.contains_key("own"));
+
+        let cycle = InputSource::from("testdata/include_cycle.br").read();
+        assert_eq!(cycle.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn included_lines_are_located() {
+        let (source, lines) = InputSource::from("testdata/include_test.br")
+            .read()
+            .unwrap();
+        let line_of = |text: &str| 1 + source.lines().position(|line| line == text).unwrap();
+
+        let common = fs::canonicalize("testdata/include/common.br").unwrap();
+        assert_eq!(lines.includes(), std::slice::from_ref(&common));
+        assert_eq!(
+            lines.locate(line_of("grid shared {")),
+            Some((Some(common.as_path()), 3))
+        );
+        assert_eq!(lines.locate(line_of("grid own {")), Some((None, 3)));
+
+        let diagnostic = Diagnostic {
+            file: None,
+            line: line_of("grid shared {"),
+            column: 6,
+            message: "A problem".to_string(),
+        };
+        let relocated = lines.relocate(&diagnostic);
+        assert_eq!(relocated.file, Some(common));
+        assert_eq!(relocated.line, 3);
+        assert!(relocated
+            .to_string()
+            .ends_with("common.br line 3:6: A problem"));
+    }
+
+    #[test]
+    fn edit_spans_the_difference() {
+        let edit = diff_edit("grid a {\n\tx _\n}", "grid a {\n\tx x _\n}").unwrap();
+
+        assert_eq!(edit.start_byte, 12);
+        assert_eq!(edit.old_end_byte, 12);
+        assert_eq!(edit.new_end_byte, 14);
+        assert_eq!(edit.start_position, Point::new(1, 3));
+        assert!(diff_edit("same", "same").is_none());
+    }
+
+    #[test]
+    fn incremental_matches_full_parse() {
+        let old = include_str!("../testdata/pipeline_test.br");
+        let new = old.replace("x_?_", "x?x_");
+
+        let mut incremental = IncrementalParser::new(parser(), old.to_string()).unwrap();
+        incremental.update(new.clone()).unwrap();
+
+        let full = parser().parse(&new, None).unwrap();
+        assert_eq!(
+            incremental.tree().root_node().to_sexp(),
+            full.root_node().to_sexp()
+        );
+        assert_eq!(incremental.source(), new);
+    }
+
+    #[test]
+    fn parse_failure_is_an_error() {
+        // without a language, tree-sitter returns no tree
+        let source = include_str!("../testdata/pipeline_test.br").to_string();
+        assert_eq!(
+            IncrementalParser::new(Parser::new(), source.clone()).err(),
+            Some(ParseError)
+        );
+
+        // a failed reparse keeps the previous tree and source
+        let mut incremental = IncrementalParser::new(parser(), source.clone()).unwrap();
+        let before = incremental.tree().root_node().to_sexp();
+        incremental.parser = Parser::new();
+
+        let changed = source.replace("x_?_", "x?x_");
+        assert_eq!(incremental.update(changed), Err(ParseError));
+        assert_eq!(incremental.tree().root_node().to_sexp(), before);
+        assert_eq!(incremental.source(), source);
+    }
+}
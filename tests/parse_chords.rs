@@ -1,6 +1,8 @@
 use breakers::{
+    chromatic::{Chord, Note},
     grid::GridToken,
-    pipeline::{Pipeline, Playable},
+    pipeline::{Pipeline, Playable, RenderLength},
+    util::FromNode,
 };
 use tree_sitter::Parser;
 
@@ -59,6 +61,7 @@ fn chord_parse_and_freqs() {
 
     let chord = match grid {
         Playable::Grid(g) => g.tokens.first().unwrap(),
+        _ => panic!("chordName is not a grid"),
     };
 
     match chord {
@@ -79,3 +82,111 @@ fn chord_parse_and_freqs() {
         _ => panic!("GridToken is not a chord"),
     }
 }
+
+#[test]
+fn altered_dominant() {
+    let source = include_str!("../testdata/altered_chord.br");
+
+    let mut parser = Parser::new();
+    parser
+        .set_language(tree_sitter_breaker::language())
+        .unwrap();
+    let tree = parser.parse(source, None).unwrap();
+
+    let Ok((pipeline, _)) = Pipeline::from_tree(&tree, source, None) else {
+        panic!("Pipeline creation failed");
+    };
+
+    // the augmented ninth is 15 semitones above the root
+    match &pipeline.playables["lead"] {
+        Playable::Chord(c) => test_freqs(
+            c.as_freqs(),
+            &[
+                261.6255653005986,
+                329.6275569128699,
+                391.99543598174927,
+                466.1637615180899,
+                622.2539674441618,
+            ],
+        ),
+        _ => panic!("lead is not a standalone chord"),
+    }
+}
+
+#[test]
+fn flat_chord_round_trips() {
+    let source = include_str!("../testdata/flat_chord.br");
+
+    let mut parser = Parser::new();
+    parser
+        .set_language(tree_sitter_breaker::language())
+        .unwrap();
+    let tree = parser.parse(source, None).unwrap();
+
+    let Ok((pipeline, _)) = Pipeline::from_tree(&tree, source, None) else {
+        panic!("Pipeline creation failed");
+    };
+
+    // the chord is spelled as it was written, not as A sharp minor
+    match &pipeline.playables["lead"] {
+        Playable::Chord(c) => assert_eq!(c.to_string(), "Bbm"),
+        _ => panic!("lead is not a standalone chord"),
+    }
+}
+
+#[test]
+fn standalone_chord() {
+    let source = include_str!("../testdata/standalone_chord.br");
+
+    let mut parser = Parser::new();
+    parser
+        .set_language(tree_sitter_breaker::language())
+        .unwrap();
+    let tree = parser.parse(source, None).unwrap();
+
+    let Ok((mut pipeline, _)) = Pipeline::from_tree(&tree, source, None) else {
+        panic!("Pipeline creation failed");
+    };
+
+    match &pipeline.playables["pad"] {
+        Playable::Chord(c) => test_freqs(
+            c.as_freqs(),
+            &[
+                493.8833012561241,
+                587.3295358348151,
+                739.9888454232688,
+                880.0,
+                554.3652619537442,
+                174.61411571650194,
+            ],
+        ),
+        _ => panic!("pad is not a standalone chord"),
+    }
+
+    // the chord keeps sounding, it has no steps which rest
+    let output = pipeline.render(RenderLength::Bars(2));
+    assert!(
+        output.chunks(1000).all(|c| c.iter().any(|s| *s != 0.0)),
+        "Standalone chord went silent"
+    );
+}
+
+#[test]
+fn malformed_chord_nodes() {
+    let (source, tree) = get_test_tree();
+
+    // the grid itself is no chord (it has no root), so it should be rejected
+    let grid = tree.root_node().child(0).expect("No grid node found");
+    assert!(Chord::from_node(&grid, &source).is_none());
+    assert!(Note::from_node(&grid, &source).is_none());
+
+    // no node at all can make chord parsing panic
+    let mut stack = vec![tree.root_node()];
+    while let Some(node) = stack.pop() {
+        let _ = Chord::from_node(&node, &source);
+        let _ = Note::from_node(&node, &source);
+
+        let mut cursor = node.walk();
+        stack.extend(node.children(&mut cursor));
+    }
+}